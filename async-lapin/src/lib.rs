@@ -1,11 +1,12 @@
 use async_io::{Async, Timer};
+use flume::{Receiver, Sender};
+use futures_lite::future;
 use lapin::{
     executor::Executor,
     heartbeat::Heartbeat,
     reactor::{Reactor, ReactorBuilder, ReactorHandle, Slot},
     socket_state::{SocketEvent, SocketStateHandle},
-    tcp::{TcpStream, TcpStreamWrapper},
-    ConnectionProperties, Result,
+    ConnectionProperties, OwnedTransportSocket, Result, TransportSocket,
 };
 use parking_lot::Mutex;
 use std::{collections::HashMap, fmt, sync::Arc};
@@ -54,7 +55,13 @@ struct AsyncIoReactorHandle {
 #[derive(Default)]
 struct Inner {
     slot: Slot,
-    slots: HashMap<usize, (Arc<Async<TcpStreamWrapper>>, SocketStateHandle)>,
+    slots: HashMap<usize, Registration>,
+}
+
+struct Registration {
+    socket: Arc<Async<OwnedTransportSocket>>,
+    socket_state: SocketStateHandle,
+    unregistered: (Sender<()>, Receiver<()>),
 }
 
 impl fmt::Debug for AsyncIoReactorHandle {
@@ -66,12 +73,19 @@ impl fmt::Debug for AsyncIoReactorHandle {
 impl Inner {
     fn register(
         &mut self,
-        socket: Arc<Async<TcpStreamWrapper>>,
+        socket: Arc<Async<OwnedTransportSocket>>,
         socket_state: SocketStateHandle,
     ) -> Result<usize> {
         let slot = self.slot;
         self.slot += 1;
-        self.slots.insert(slot, (socket, socket_state));
+        self.slots.insert(
+            slot,
+            Registration {
+                socket,
+                socket_state,
+                unregistered: flume::bounded(0),
+            },
+        );
         Ok(slot)
     }
 }
@@ -89,10 +103,10 @@ impl ReactorBuilder for AsyncIoReactorBuilder {
 impl Reactor for AsyncIoReactor {
     fn register(
         &mut self,
-        socket: &mut TcpStream,
+        socket: TransportSocket,
         socket_state: SocketStateHandle,
     ) -> Result<usize> {
-        let socket = Arc::new(Async::new(unsafe { TcpStreamWrapper::new(socket) })?);
+        let socket = Arc::new(Async::new(socket.try_clone()?)?);
         let slot = self.0.inner.lock().register(socket, socket_state)?;
         self.0.poll_read(slot);
         self.0.poll_write(slot);
@@ -111,18 +125,29 @@ impl ReactorHandle for AsyncIoReactorHandle {
     }
 
     fn poll_read(&self, slot: usize) {
-        if let Some((socket, socket_state)) = self.inner.lock().slots.get(&slot) {
-            self.executor
-                .spawn(Box::pin(poll_read(socket.clone(), socket_state.clone())));
+        if let Some(registration) = self.inner.lock().slots.get(&slot) {
+            self.executor.spawn(Box::pin(poll_read(
+                registration.socket.clone(),
+                registration.socket_state.clone(),
+                registration.unregistered.1.clone(),
+            )));
         }
     }
 
     fn poll_write(&self, slot: usize) {
-        if let Some((socket, socket_state)) = self.inner.lock().slots.get(&slot) {
-            self.executor
-                .spawn(Box::pin(poll_write(socket.clone(), socket_state.clone())));
+        if let Some(registration) = self.inner.lock().slots.get(&slot) {
+            self.executor.spawn(Box::pin(poll_write(
+                registration.socket.clone(),
+                registration.socket_state.clone(),
+                registration.unregistered.1.clone(),
+            )));
         }
     }
+
+    fn unregister(&self, slot: usize) {
+        // Dropping the sender wakes the polling tasks up, releasing the socket
+        self.inner.lock().slots.remove(&slot);
+    }
 }
 
 async fn heartbeat(heartbeat: Heartbeat) {
@@ -131,12 +156,35 @@ async fn heartbeat(heartbeat: Heartbeat) {
     }
 }
 
-async fn poll_read(socket: Arc<Async<TcpStreamWrapper>>, socket_state: SocketStateHandle) {
-    socket.readable().await.unwrap();
-    socket_state.send(SocketEvent::Readable);
+async fn poll_read(
+    socket: Arc<Async<OwnedTransportSocket>>,
+    socket_state: SocketStateHandle,
+    unregistered: Receiver<()>,
+) {
+    let readable = async {
+        socket.readable().await.unwrap();
+        true
+    };
+    if future::or(readable, unregistered_event(unregistered)).await {
+        socket_state.send(SocketEvent::Readable);
+    }
+}
+
+async fn poll_write(
+    socket: Arc<Async<OwnedTransportSocket>>,
+    socket_state: SocketStateHandle,
+    unregistered: Receiver<()>,
+) {
+    let writable = async {
+        socket.writable().await.unwrap();
+        true
+    };
+    if future::or(writable, unregistered_event(unregistered)).await {
+        socket_state.send(SocketEvent::Writable);
+    }
 }
 
-async fn poll_write(socket: Arc<Async<TcpStreamWrapper>>, socket_state: SocketStateHandle) {
-    socket.writable().await.unwrap();
-    socket_state.send(SocketEvent::Writable);
+async fn unregistered_event(unregistered: Receiver<()>) -> bool {
+    let _ = unregistered.recv_async().await;
+    false
 }
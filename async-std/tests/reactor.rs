@@ -0,0 +1,44 @@
+use async_amqp::*;
+use lapin::{
+    options::*, types::FieldTable, BasicProperties, Connection, ConnectionProperties, IoLoopMode,
+};
+use lapin_mock::MockServer;
+
+#[async_std::test]
+async fn async_std_runtime() {
+    let server = MockServer::start().expect("mock server");
+    let conn = Connection::connect(
+        &server.uri(),
+        ConnectionProperties::default()
+            .with_async_std()
+            .with_io_loop_mode(IoLoopMode::Async),
+    )
+    .await
+    .expect("connection");
+    let channel = conn.create_channel().await.expect("create_channel");
+    channel
+        .queue_declare(
+            "async-std",
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .expect("queue_declare");
+    channel
+        .basic_publish(
+            "",
+            "async-std",
+            BasicPublishOptions::default(),
+            b"Hello world!".to_vec(),
+            BasicProperties::default(),
+        )
+        .await
+        .expect("basic_publish");
+    let message = channel
+        .basic_get("async-std", BasicGetOptions { no_ack: true })
+        .await
+        .expect("basic_get")
+        .expect("message");
+    assert_eq!(message.delivery.data, b"Hello world!");
+    conn.close(200, "OK").await.expect("close");
+}
@@ -1,3 +1,4 @@
+use async_lapin::*;
 use lapin::{executor::Executor, ConnectionProperties};
 use std::{future::Future, pin::Pin};
 
@@ -6,18 +7,27 @@ pub trait BastionExt {
     where
         Self: Sized,
     {
-        self.with_bastion_executor()
+        self.with_bastion_executor().with_bastion_reactor()
     }
 
     fn with_bastion_executor(self) -> Self
     where
         Self: Sized;
+
+    fn with_bastion_reactor(self) -> Self
+    where
+        Self: Sized;
 }
 
 impl BastionExt for ConnectionProperties {
     fn with_bastion_executor(self) -> Self {
         self.with_executor(BastionExecutor)
     }
+
+    fn with_bastion_reactor(self) -> Self {
+        // bastion has no driver of its own, use async-io reactor, which spawns on the bastion executor
+        self.with_async_io_reactor()
+    }
 }
 
 #[derive(Debug)]
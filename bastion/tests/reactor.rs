@@ -0,0 +1,55 @@
+use bastion::Bastion;
+use bastion_amqp::*;
+use lapin::{
+    options::*, types::FieldTable, BasicProperties, Connection, ConnectionProperties, IoLoopMode,
+};
+use lapin_mock::MockServer;
+
+async fn publish_and_get(uri: String) {
+    let conn = Connection::connect(
+        &uri,
+        ConnectionProperties::default()
+            .with_bastion()
+            .with_io_loop_mode(IoLoopMode::Async),
+    )
+    .await
+    .expect("connection");
+    let channel = conn.create_channel().await.expect("create_channel");
+    channel
+        .queue_declare(
+            "bastion",
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .expect("queue_declare");
+    channel
+        .basic_publish(
+            "",
+            "bastion",
+            BasicPublishOptions::default(),
+            b"Hello world!".to_vec(),
+            BasicProperties::default(),
+        )
+        .await
+        .expect("basic_publish");
+    let message = channel
+        .basic_get("bastion", BasicGetOptions { no_ack: true })
+        .await
+        .expect("basic_get")
+        .expect("message");
+    assert_eq!(message.delivery.data, b"Hello world!");
+    conn.close(200, "OK").await.expect("close");
+}
+
+#[test]
+fn bastion_runtime() {
+    let server = MockServer::start().expect("mock server");
+
+    Bastion::init();
+    Bastion::start();
+
+    bastion::run!(publish_and_get(server.uri()));
+
+    Bastion::stop();
+}
@@ -0,0 +1,76 @@
+use futures_lite::stream::StreamExt;
+use lapin::{
+    options::*, types::FieldTable, BasicProperties, Connection, ConnectionProperties,
+    RecoveryConfig, Result,
+};
+use std::time::Duration;
+use tracing::{info, warn};
+
+fn main() -> Result<()> {
+    if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+
+    tracing_subscriber::fmt::init();
+
+    let addr = std::env::var("AMQP_ADDR").unwrap_or_else(|_| "amqp://127.0.0.1:5672/%2f".into());
+
+    async_global_executor::block_on(async {
+        let conn = Connection::connect(
+            &addr,
+            ConnectionProperties::default().with_connection_recovery(RecoveryConfig::default()),
+        )
+        .await?;
+
+        info!("CONNECTED");
+
+        let channel_a = conn.create_channel().await?;
+        let channel_b = conn.create_channel().await?;
+
+        channel_a
+            .queue_declare(
+                "hello",
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+
+        // This consumer keeps receiving deliveries after the connection got recovered
+        let mut consumer = channel_b
+            .basic_consume(
+                "hello",
+                "my_consumer",
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+        async_global_executor::spawn(async move {
+            while let Some(delivery) = consumer.next().await {
+                let (channel, delivery) = delivery.expect("error in consumer");
+                info!(delivery_tag=%delivery.delivery_tag, "received message");
+                channel
+                    .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+                    .await
+                    .expect("ack");
+            }
+        })
+        .detach();
+
+        loop {
+            // Publishing fails while we're reconnecting, just try again later
+            if let Err(err) = channel_a
+                .basic_publish(
+                    "",
+                    "hello",
+                    BasicPublishOptions::default(),
+                    b"Hello world!".to_vec(),
+                    BasicProperties::default(),
+                )
+                .await
+            {
+                warn!(%err, "failed to publish");
+            }
+            async_io::Timer::after(Duration::from_secs(1)).await;
+        }
+    })
+}
@@ -0,0 +1,953 @@
+use crate::session::{Session, COMMAND_INVALID, NOT_ALLOWED};
+use amq_protocol::{
+    protocol::BasicProperties,
+    types::{AMQPValue, FieldTable},
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
+
+pub(crate) const NO_ROUTE: u16 = 312;
+pub(crate) const ACCESS_REFUSED: u16 = 403;
+pub(crate) const NOT_FOUND: u16 = 404;
+pub(crate) const RESOURCE_LOCKED: u16 = 405;
+pub(crate) const PRECONDITION_FAILED: u16 = 406;
+
+/// The pseudo-queue of RabbitMQ's direct reply-to
+const DIRECT_REPLY_TO: &str = "amq.rabbitmq.reply-to";
+
+/// A channel-level exception: the server closes the channel with this code and text
+#[derive(Debug)]
+pub(crate) struct Refusal(pub(crate) u16, pub(crate) String);
+
+pub(crate) type Outcome<T> = Result<T, Refusal>;
+
+#[derive(Clone, Debug)]
+pub(crate) struct Message {
+    pub(crate) exchange: String,
+    pub(crate) routing_key: String,
+    pub(crate) properties: BasicProperties,
+    pub(crate) body: Vec<u8>,
+    pub(crate) redelivered: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Destination {
+    Queue(String),
+    Exchange(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Binding {
+    destination: Destination,
+    routing_key: String,
+    arguments: FieldTable,
+}
+
+#[derive(Debug)]
+struct Exchange {
+    kind: String,
+    durable: bool,
+    auto_delete: bool,
+    internal: bool,
+    arguments: FieldTable,
+    bindings: Vec<Binding>,
+}
+
+#[derive(Debug)]
+struct Queue {
+    durable: bool,
+    exclusive_owner: Option<u64>,
+    auto_delete: bool,
+    arguments: FieldTable,
+    messages: VecDeque<Message>,
+    consumers: Vec<Consumer>,
+    next_consumer: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Consumer {
+    tag: String,
+    session: Arc<Session>,
+    channel: u16,
+    no_ack: bool,
+}
+
+#[derive(Debug)]
+struct Unacked {
+    queue: String,
+    message: Message,
+}
+
+#[derive(Debug)]
+struct ChannelState {
+    prefetch: u16,
+    confirm: bool,
+    next_publish_tag: u64,
+    next_delivery_tag: u64,
+    unacked: BTreeMap<u64, Unacked>,
+}
+
+/// What a publish turned into, for the session to notify the publisher
+pub(crate) struct Published {
+    pub(crate) routed: bool,
+    pub(crate) confirm_tag: Option<u64>,
+}
+
+/// The state shared by all the connections of a [`MockServer`]
+///
+/// [`MockServer`]: ./struct.MockServer.html
+#[derive(Debug)]
+pub(crate) struct Broker {
+    exchanges: HashMap<String, Exchange>,
+    queues: HashMap<String, Queue>,
+    channels: HashMap<(u64, u16), ChannelState>,
+    /// The consumers of the direct reply-to pseudo-queue, by session and channel
+    reply_consumers: HashMap<(u64, u16), Consumer>,
+    next_id: u64,
+}
+
+impl Default for Broker {
+    fn default() -> Self {
+        let mut broker = Self {
+            exchanges: HashMap::default(),
+            queues: HashMap::default(),
+            channels: HashMap::default(),
+            reply_consumers: HashMap::default(),
+            next_id: 0,
+        };
+        for (name, kind) in &[
+            ("", "direct"),
+            ("amq.direct", "direct"),
+            ("amq.fanout", "fanout"),
+            ("amq.topic", "topic"),
+            ("amq.headers", "headers"),
+            ("amq.match", "headers"),
+        ] {
+            broker.exchanges.insert(
+                (*name).into(),
+                Exchange {
+                    kind: (*kind).into(),
+                    durable: true,
+                    auto_delete: false,
+                    internal: false,
+                    arguments: FieldTable::default(),
+                    bindings: Vec::new(),
+                },
+            );
+        }
+        broker
+    }
+}
+
+impl Broker {
+    pub(crate) fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    pub(crate) fn open_channel(&mut self, session: u64, channel: u16) {
+        self.channels.insert(
+            (session, channel),
+            ChannelState {
+                prefetch: 0,
+                confirm: false,
+                next_publish_tag: 0,
+                next_delivery_tag: 0,
+                unacked: BTreeMap::new(),
+            },
+        );
+    }
+
+    /// Forget about a channel, cancelling its consumers and requeueing its unacked messages
+    pub(crate) fn close_channel(&mut self, session: u64, channel: u16) {
+        let mut touched = HashSet::new();
+        let mut auto_deleted = Vec::new();
+        for (name, queue) in self.queues.iter_mut() {
+            let before = queue.consumers.len();
+            queue
+                .consumers
+                .retain(|c| c.session.id() != session || c.channel != channel);
+            if queue.consumers.len() != before {
+                touched.insert(name.clone());
+                if queue.auto_delete && queue.consumers.is_empty() {
+                    auto_deleted.push(name.clone());
+                }
+            }
+        }
+        self.reply_consumers.remove(&(session, channel));
+        if let Some(state) = self.channels.remove(&(session, channel)) {
+            for (_, unacked) in state.unacked.into_iter().rev() {
+                touched.insert(unacked.queue.clone());
+                self.requeue(unacked);
+            }
+        }
+        for name in auto_deleted {
+            self.remove_queue(&name);
+        }
+        for name in touched {
+            self.dispatch(&name);
+        }
+    }
+
+    /// Forget about a connection, its channels and exclusive queues
+    pub(crate) fn close_session(&mut self, session: u64) {
+        let channels = self
+            .channels
+            .keys()
+            .filter(|(s, _)| *s == session)
+            .map(|(_, c)| *c)
+            .collect::<Vec<_>>();
+        for channel in channels {
+            self.close_channel(session, channel);
+        }
+        let exclusive = self
+            .queues
+            .iter()
+            .filter(|(_, q)| q.exclusive_owner == Some(session))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for name in exclusive {
+            self.remove_queue(&name);
+        }
+    }
+
+    fn channel_mut(&mut self, session: u64, channel: u16) -> &mut ChannelState {
+        self.channels
+            .get_mut(&(session, channel))
+            .expect("operation on a channel which isn't open")
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn declare_exchange(
+        &mut self,
+        name: &str,
+        kind: &str,
+        passive: bool,
+        durable: bool,
+        auto_delete: bool,
+        internal: bool,
+        arguments: &FieldTable,
+    ) -> Outcome<()> {
+        if let Some(exchange) = self.exchanges.get(name) {
+            if passive {
+                return Ok(());
+            }
+            if exchange.kind != kind
+                || exchange.durable != durable
+                || exchange.auto_delete != auto_delete
+                || exchange.internal != internal
+                || &exchange.arguments != arguments
+            {
+                return Err(Refusal(
+                    PRECONDITION_FAILED,
+                    format!("inequivalent arg for exchange '{}'", name),
+                ));
+            }
+            return Ok(());
+        }
+        if passive {
+            return Err(not_found("exchange", name));
+        }
+        if name.starts_with("amq.") {
+            return Err(Refusal(
+                ACCESS_REFUSED,
+                format!("exchange name '{}' contains reserved prefix 'amq.*'", name),
+            ));
+        }
+        if !["direct", "fanout", "topic", "headers"].contains(&kind) {
+            return Err(Refusal(
+                COMMAND_INVALID,
+                format!("unknown exchange type '{}'", kind),
+            ));
+        }
+        self.exchanges.insert(
+            name.into(),
+            Exchange {
+                kind: kind.into(),
+                durable,
+                auto_delete,
+                internal,
+                arguments: arguments.clone(),
+                bindings: Vec::new(),
+            },
+        );
+        Ok(())
+    }
+
+    pub(crate) fn delete_exchange(&mut self, name: &str, if_unused: bool) -> Outcome<()> {
+        if name.is_empty() || name.starts_with("amq.") {
+            return Err(Refusal(
+                ACCESS_REFUSED,
+                format!("cannot delete the builtin exchange '{}'", name),
+            ));
+        }
+        let destination = Destination::Exchange(name.into());
+        let used = self
+            .exchanges
+            .values()
+            .any(|e| e.bindings.iter().any(|b| b.destination == destination));
+        match self.exchanges.get(name) {
+            Some(exchange) if if_unused && (used || !exchange.bindings.is_empty()) => Err(Refusal(
+                PRECONDITION_FAILED,
+                format!("exchange '{}' in use", name),
+            )),
+            Some(_) => {
+                self.exchanges.remove(name);
+                for exchange in self.exchanges.values_mut() {
+                    exchange.bindings.retain(|b| b.destination != destination);
+                }
+                Ok(())
+            }
+            // Deleting an exchange which doesn't exist is fine
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the name of the queue along with its message and consumer counts
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn declare_queue(
+        &mut self,
+        session: u64,
+        name: &str,
+        passive: bool,
+        durable: bool,
+        exclusive: bool,
+        auto_delete: bool,
+        arguments: &FieldTable,
+    ) -> Outcome<(String, u32, u32)> {
+        if let Some(queue) = self.queues.get(name) {
+            if queue
+                .exclusive_owner
+                .map_or(false, |owner| owner != session)
+            {
+                return Err(Refusal(
+                    RESOURCE_LOCKED,
+                    format!("cannot obtain exclusive access to locked queue '{}'", name),
+                ));
+            }
+            if !passive
+                && (queue.durable != durable
+                    || queue.exclusive_owner.is_some() != exclusive
+                    || queue.auto_delete != auto_delete
+                    || &queue.arguments != arguments)
+            {
+                return Err(Refusal(
+                    PRECONDITION_FAILED,
+                    format!("inequivalent arg for queue '{}'", name),
+                ));
+            }
+            return Ok((
+                name.into(),
+                queue.messages.len() as u32,
+                queue.consumers.len() as u32,
+            ));
+        }
+        if passive {
+            return Err(not_found("queue", name));
+        }
+        let name = if name.is_empty() {
+            format!("amq.gen-{}", self.next_id())
+        } else if name.starts_with("amq.") {
+            return Err(Refusal(
+                ACCESS_REFUSED,
+                format!("queue name '{}' contains reserved prefix 'amq.*'", name),
+            ));
+        } else {
+            name.into()
+        };
+        self.queues.insert(
+            name.clone(),
+            Queue {
+                durable,
+                exclusive_owner: if exclusive { Some(session) } else { None },
+                auto_delete,
+                arguments: arguments.clone(),
+                messages: VecDeque::new(),
+                consumers: Vec::new(),
+                next_consumer: 0,
+            },
+        );
+        Ok((name, 0, 0))
+    }
+
+    /// Returns the number of messages which were in the queue
+    pub(crate) fn delete_queue(
+        &mut self,
+        name: &str,
+        if_unused: bool,
+        if_empty: bool,
+    ) -> Outcome<u32> {
+        match self.queues.get(name) {
+            Some(queue) if if_unused && !queue.consumers.is_empty() => Err(Refusal(
+                PRECONDITION_FAILED,
+                format!("queue '{}' in use", name),
+            )),
+            Some(queue) if if_empty && !queue.messages.is_empty() => Err(Refusal(
+                PRECONDITION_FAILED,
+                format!("queue '{}' not empty", name),
+            )),
+            Some(_) => Ok(self.remove_queue(name)),
+            None => Ok(0),
+        }
+    }
+
+    fn remove_queue(&mut self, name: &str) -> u32 {
+        let destination = Destination::Queue(name.into());
+        for exchange in self.exchanges.values_mut() {
+            exchange.bindings.retain(|b| b.destination != destination);
+        }
+        self.queues.remove(name).map_or(0, |queue| {
+            for consumer in queue.consumers {
+                consumer
+                    .session
+                    .send_consumer_cancel(consumer.channel, &consumer.tag);
+            }
+            queue.messages.len() as u32
+        })
+    }
+
+    pub(crate) fn purge_queue(&mut self, name: &str) -> Outcome<u32> {
+        let queue = self
+            .queues
+            .get_mut(name)
+            .ok_or_else(|| not_found("queue", name))?;
+        let count = queue.messages.len() as u32;
+        queue.messages.clear();
+        Ok(count)
+    }
+
+    pub(crate) fn bind(
+        &mut self,
+        source: &str,
+        destination: &str,
+        to_exchange: bool,
+        routing_key: &str,
+        arguments: FieldTable,
+    ) -> Outcome<()> {
+        let destination = self.destination(destination, to_exchange)?;
+        let exchange = self
+            .exchanges
+            .get_mut(source)
+            .ok_or_else(|| not_found("exchange", source))?;
+        if source.is_empty() {
+            return Err(Refusal(
+                ACCESS_REFUSED,
+                "operation not permitted on the default exchange".into(),
+            ));
+        }
+        let binding = Binding {
+            destination,
+            routing_key: routing_key.into(),
+            arguments,
+        };
+        if !exchange.bindings.contains(&binding) {
+            exchange.bindings.push(binding);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn unbind(
+        &mut self,
+        source: &str,
+        destination: &str,
+        to_exchange: bool,
+        routing_key: &str,
+        arguments: FieldTable,
+    ) -> Outcome<()> {
+        let destination = self.destination(destination, to_exchange)?;
+        let exchange = self
+            .exchanges
+            .get_mut(source)
+            .ok_or_else(|| not_found("exchange", source))?;
+        let binding = Binding {
+            destination,
+            routing_key: routing_key.into(),
+            arguments,
+        };
+        exchange.bindings.retain(|b| b != &binding);
+        Ok(())
+    }
+
+    fn destination(&self, name: &str, to_exchange: bool) -> Outcome<Destination> {
+        if to_exchange {
+            if !self.exchanges.contains_key(name) {
+                return Err(not_found("exchange", name));
+            }
+            Ok(Destination::Exchange(name.into()))
+        } else {
+            if !self.queues.contains_key(name) {
+                return Err(not_found("queue", name));
+            }
+            Ok(Destination::Queue(name.into()))
+        }
+    }
+
+    pub(crate) fn set_prefetch(&mut self, session: u64, channel: u16, prefetch: u16) {
+        self.channel_mut(session, channel).prefetch = prefetch;
+        let queues = self.queues.keys().cloned().collect::<Vec<_>>();
+        for queue in queues {
+            self.dispatch(&queue);
+        }
+    }
+
+    pub(crate) fn set_confirm(&mut self, session: u64, channel: u16) {
+        self.channel_mut(session, channel).confirm = true;
+    }
+
+    /// Returns the consumer tag
+    pub(crate) fn consume(
+        &mut self,
+        session: &Arc<Session>,
+        channel: u16,
+        queue: &str,
+        consumer_tag: &str,
+        no_ack: bool,
+        exclusive: bool,
+    ) -> Outcome<String> {
+        let tag = if consumer_tag.is_empty() {
+            format!("amq.ctag-{}", self.next_id())
+        } else {
+            consumer_tag.into()
+        };
+        if queue == DIRECT_REPLY_TO {
+            if !no_ack {
+                return Err(Refusal(
+                    PRECONDITION_FAILED,
+                    "reply consumer cannot acknowledge".into(),
+                ));
+            }
+            self.reply_consumers.insert(
+                (session.id(), channel),
+                Consumer {
+                    tag: tag.clone(),
+                    session: session.clone(),
+                    channel,
+                    no_ack,
+                },
+            );
+            return Ok(tag);
+        }
+        let q = self
+            .queues
+            .get_mut(queue)
+            .ok_or_else(|| not_found("queue", queue))?;
+        if q.exclusive_owner
+            .map_or(false, |owner| owner != session.id())
+        {
+            return Err(Refusal(
+                RESOURCE_LOCKED,
+                format!("cannot obtain exclusive access to locked queue '{}'", queue),
+            ));
+        }
+        if exclusive && !q.consumers.is_empty() {
+            return Err(Refusal(
+                ACCESS_REFUSED,
+                format!("queue '{}' already has consumers", queue),
+            ));
+        }
+        let duplicate = self
+            .queues
+            .values()
+            .flat_map(|q| q.consumers.iter())
+            .any(|c| c.session.id() == session.id() && c.channel == channel && c.tag == tag);
+        if duplicate {
+            return Err(Refusal(
+                NOT_ALLOWED,
+                format!("attempt to reuse consumer tag '{}'", tag),
+            ));
+        }
+        self.queues
+            .get_mut(queue)
+            .expect("queue vanished")
+            .consumers
+            .push(Consumer {
+                tag: tag.clone(),
+                session: session.clone(),
+                channel,
+                no_ack,
+            });
+        Ok(tag)
+    }
+
+    /// Start delivering to a consumer, once its consume-ok has been sent
+    pub(crate) fn dispatch_all(&mut self) {
+        let queues = self.queues.keys().cloned().collect::<Vec<_>>();
+        for queue in queues {
+            self.dispatch(&queue);
+        }
+    }
+
+    pub(crate) fn cancel(&mut self, session: u64, channel: u16, consumer_tag: &str) {
+        if self
+            .reply_consumers
+            .get(&(session, channel))
+            .map_or(false, |c| c.tag == consumer_tag)
+        {
+            self.reply_consumers.remove(&(session, channel));
+        }
+        let mut auto_deleted = None;
+        for (name, queue) in self.queues.iter_mut() {
+            let before = queue.consumers.len();
+            queue.consumers.retain(|c| {
+                c.session.id() != session || c.channel != channel || c.tag != consumer_tag
+            });
+            if queue.consumers.len() != before && queue.auto_delete && queue.consumers.is_empty() {
+                auto_deleted = Some(name.clone());
+            }
+        }
+        if let Some(name) = auto_deleted {
+            self.remove_queue(&name);
+        }
+    }
+
+    /// Route a message to the queues bound to `exchange`
+    pub(crate) fn publish(
+        &mut self,
+        session: u64,
+        channel: u16,
+        exchange: &str,
+        routing_key: &str,
+        properties: BasicProperties,
+        body: Vec<u8>,
+    ) -> Outcome<Published> {
+        match self.exchanges.get(exchange) {
+            None => return Err(not_found("exchange", exchange)),
+            Some(e) if e.internal => {
+                return Err(Refusal(
+                    ACCESS_REFUSED,
+                    format!("cannot publish to internal exchange '{}'", exchange),
+                ))
+            }
+            Some(_) => {}
+        }
+        let mut properties = properties;
+        if properties.reply_to().as_ref().map(|r| r.as_str()) == Some(DIRECT_REPLY_TO) {
+            if !self.reply_consumers.contains_key(&(session, channel)) {
+                return Err(Refusal(
+                    PRECONDITION_FAILED,
+                    "fast reply consumer does not exist".into(),
+                ));
+            }
+            // Let the replies find their way back to this channel
+            properties = properties
+                .with_reply_to(format!("{}.{}.{}", DIRECT_REPLY_TO, session, channel).into());
+        }
+        let reply_consumer = if exchange.is_empty() {
+            self.reply_consumer(routing_key)
+        } else {
+            None
+        };
+        let queues = if reply_consumer.is_some() {
+            HashSet::new()
+        } else {
+            self.route(exchange, routing_key, properties.headers().as_ref())
+        };
+        let message = Message {
+            exchange: exchange.into(),
+            routing_key: routing_key.into(),
+            properties,
+            body,
+            redelivered: false,
+        };
+        for name in &queues {
+            if let Some(queue) = self.queues.get_mut(name) {
+                queue.messages.push_back(message.clone());
+            }
+        }
+        if let Some(consumer) = &reply_consumer {
+            if let Some(state) = self
+                .channels
+                .get_mut(&(consumer.session.id(), consumer.channel))
+            {
+                state.next_delivery_tag += 1;
+                consumer.session.send_delivery(
+                    consumer.channel,
+                    &consumer.tag,
+                    state.next_delivery_tag,
+                    &message,
+                );
+            }
+        }
+        let state = self.channel_mut(session, channel);
+        let confirm_tag = if state.confirm {
+            state.next_publish_tag += 1;
+            Some(state.next_publish_tag)
+        } else {
+            None
+        };
+        for name in &queues {
+            self.dispatch(name);
+        }
+        Ok(Published {
+            routed: reply_consumer.is_some() || !queues.is_empty(),
+            confirm_tag,
+        })
+    }
+
+    /// The consumer of the direct reply-to pseudo-queue this routing key leads to, if any
+    fn reply_consumer(&self, routing_key: &str) -> Option<Consumer> {
+        let mut ids = routing_key
+            .strip_prefix(DIRECT_REPLY_TO)?
+            .strip_prefix('.')?
+            .splitn(2, '.');
+        let session = ids.next()?.parse().ok()?;
+        let channel = ids.next()?.parse().ok()?;
+        self.reply_consumers.get(&(session, channel)).cloned()
+    }
+
+    fn route(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        headers: Option<&FieldTable>,
+    ) -> HashSet<String> {
+        let mut queues = HashSet::new();
+        if exchange.is_empty() {
+            if self.queues.contains_key(routing_key) {
+                queues.insert(routing_key.into());
+            }
+            return queues;
+        }
+        let mut visited = HashSet::new();
+        let mut pending = vec![exchange.to_string()];
+        while let Some(name) = pending.pop() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            let exchange = match self.exchanges.get(&name) {
+                Some(exchange) => exchange,
+                None => continue,
+            };
+            for binding in &exchange.bindings {
+                if !matches(&exchange.kind, binding, routing_key, headers) {
+                    continue;
+                }
+                match &binding.destination {
+                    Destination::Queue(queue) => {
+                        queues.insert(queue.clone());
+                    }
+                    Destination::Exchange(exchange) => pending.push(exchange.clone()),
+                }
+            }
+        }
+        queues
+    }
+
+    /// Hand the queue's messages to its consumers, round-robin, honouring their prefetch
+    fn dispatch(&mut self, name: &str) {
+        loop {
+            let queue = match self.queues.get_mut(name) {
+                Some(queue) if !queue.messages.is_empty() && !queue.consumers.is_empty() => queue,
+                _ => return,
+            };
+            let channels = &self.channels;
+            let count = queue.consumers.len();
+            let chosen = (0..count)
+                .map(|offset| (queue.next_consumer + offset) % count)
+                .find(|index| {
+                    let consumer = &queue.consumers[*index];
+                    consumer.no_ack
+                        || channels
+                            .get(&(consumer.session.id(), consumer.channel))
+                            .map_or(false, |state| {
+                                state.prefetch == 0
+                                    || state.unacked.len() < usize::from(state.prefetch)
+                            })
+                });
+            let index = match chosen {
+                Some(index) => index,
+                None => return,
+            };
+            queue.next_consumer = (index + 1) % count;
+            let consumer = queue.consumers[index].clone();
+            let message = queue.messages.pop_front().expect("queue emptied");
+            let state = match self
+                .channels
+                .get_mut(&(consumer.session.id(), consumer.channel))
+            {
+                Some(state) => state,
+                None => return,
+            };
+            state.next_delivery_tag += 1;
+            let delivery_tag = state.next_delivery_tag;
+            consumer
+                .session
+                .send_delivery(consumer.channel, &consumer.tag, delivery_tag, &message);
+            if !consumer.no_ack {
+                state.unacked.insert(
+                    delivery_tag,
+                    Unacked {
+                        queue: name.into(),
+                        message,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Returns the delivery tag along with the message and the number of messages left
+    pub(crate) fn get(
+        &mut self,
+        session: u64,
+        channel: u16,
+        queue: &str,
+        no_ack: bool,
+    ) -> Outcome<Option<(u64, Message, u32)>> {
+        let q = self
+            .queues
+            .get_mut(queue)
+            .ok_or_else(|| not_found("queue", queue))?;
+        let message = match q.messages.pop_front() {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+        let left = q.messages.len() as u32;
+        let state = self.channel_mut(session, channel);
+        state.next_delivery_tag += 1;
+        let delivery_tag = state.next_delivery_tag;
+        if !no_ack {
+            state.unacked.insert(
+                delivery_tag,
+                Unacked {
+                    queue: queue.into(),
+                    message: message.clone(),
+                },
+            );
+        }
+        Ok(Some((delivery_tag, message, left)))
+    }
+
+    /// Settle one delivery (or all the ones up to `delivery_tag` with `multiple`), putting them
+    /// back in their queue with `requeue`
+    pub(crate) fn settle(
+        &mut self,
+        session: u64,
+        channel: u16,
+        delivery_tag: u64,
+        multiple: bool,
+        requeue: Option<bool>,
+    ) -> Outcome<()> {
+        let state = self.channel_mut(session, channel);
+        let settled = if multiple {
+            let rest = if delivery_tag == 0 {
+                BTreeMap::new()
+            } else {
+                state.unacked.split_off(&(delivery_tag + 1))
+            };
+            let settled = std::mem::replace(&mut state.unacked, rest);
+            settled.into_iter().map(|(_, u)| u).collect::<Vec<_>>()
+        } else {
+            match state.unacked.remove(&delivery_tag) {
+                Some(unacked) => vec![unacked],
+                None => {
+                    return Err(Refusal(
+                        PRECONDITION_FAILED,
+                        format!("unknown delivery tag {}", delivery_tag),
+                    ))
+                }
+            }
+        };
+        let mut touched = HashSet::new();
+        if requeue == Some(true) {
+            for unacked in settled.into_iter().rev() {
+                touched.insert(unacked.queue.clone());
+                self.requeue(unacked);
+            }
+        }
+        // Settling frees room in the prefetch window
+        for queue in self.queues.keys().cloned().collect::<Vec<_>>() {
+            touched.insert(queue);
+        }
+        for queue in touched {
+            self.dispatch(&queue);
+        }
+        Ok(())
+    }
+
+    fn requeue(&mut self, unacked: Unacked) {
+        if let Some(queue) = self.queues.get_mut(&unacked.queue) {
+            let mut message = unacked.message;
+            message.redelivered = true;
+            queue.messages.push_front(message);
+        }
+    }
+}
+
+fn not_found(kind: &str, name: &str) -> Refusal {
+    Refusal(NOT_FOUND, format!("no {} '{}' in vhost '/'", kind, name))
+}
+
+fn matches(kind: &str, binding: &Binding, routing_key: &str, headers: Option<&FieldTable>) -> bool {
+    match kind {
+        "fanout" => true,
+        "topic" => topic_matches(&binding.routing_key, routing_key),
+        "headers" => headers_match(&binding.arguments, headers),
+        _ => binding.routing_key == routing_key,
+    }
+}
+
+pub(crate) fn topic_matches(pattern: &str, routing_key: &str) -> bool {
+    fn matches(pattern: &[&str], words: &[&str]) -> bool {
+        match (pattern.first(), words.first()) {
+            (None, None) => true,
+            (Some(&"#"), _) => {
+                matches(&pattern[1..], words)
+                    || (!words.is_empty() && matches(pattern, &words[1..]))
+            }
+            (Some(&"*"), Some(_)) => matches(&pattern[1..], &words[1..]),
+            (Some(p), Some(w)) if p == w => matches(&pattern[1..], &words[1..]),
+            _ => false,
+        }
+    }
+
+    let pattern = pattern.split('.').collect::<Vec<_>>();
+    let words = if routing_key.is_empty() {
+        Vec::new()
+    } else {
+        routing_key.split('.').collect()
+    };
+    matches(&pattern, &words)
+}
+
+fn headers_match(arguments: &FieldTable, headers: Option<&FieldTable>) -> bool {
+    let any = arguments
+        .inner()
+        .iter()
+        .any(|(k, v)| k.as_str() == "x-match" && v == &AMQPValue::LongString("any".into()));
+    let mut expected = arguments
+        .inner()
+        .iter()
+        .filter(|(k, _)| !k.as_str().starts_with("x-"));
+    let found = |(key, value): (&_, &AMQPValue)| {
+        headers.map_or(false, |headers| {
+            headers
+                .inner()
+                .get(key)
+                .map_or(false, |v| matches!(value, AMQPValue::Void) || v == value)
+        })
+    };
+    if any {
+        expected.any(found)
+    } else {
+        expected.all(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_patterns() {
+        assert!(topic_matches("invoice.*", "invoice.paid"));
+        assert!(!topic_matches("invoice.*", "invoice.paid.late"));
+        assert!(topic_matches("invoice.#", "invoice.paid.late"));
+        assert!(topic_matches("invoice.#", "invoice"));
+        assert!(topic_matches("#", ""));
+        assert!(topic_matches("*.paid", "invoice.paid"));
+        assert!(!topic_matches("*.paid", "paid"));
+        assert!(topic_matches("#.late", "invoice.paid.late"));
+    }
+}
@@ -0,0 +1,117 @@
+use amq_protocol::{
+    frame::{gen_frame, parse_frame, AMQPFrame, WriteContext},
+    protocol::{basic, exchange, queue, AMQPClass},
+};
+use std::io::{self, Read};
+
+/// Serialize a frame to the bytes going on the wire
+pub(crate) fn serialize(frame: &AMQPFrame) -> io::Result<Vec<u8>> {
+    gen_frame(frame)(WriteContext::from(Vec::new()))
+        .map(|ctx| ctx.write)
+        .map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("failed to serialize frame: {:?}", err),
+            )
+        })
+}
+
+/// Read frames from a byte stream, buffering the incomplete ones
+pub(crate) struct FrameReader<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// The next frame, or `None` once the peer closed the stream
+    ///
+    /// Fails with `InvalidData` if the peer sent something which isn't an AMQP frame.
+    pub(crate) fn read_frame(&mut self) -> io::Result<Option<AMQPFrame>> {
+        let mut chunk = [0; 8192];
+        loop {
+            if let Some(frame) = self.parse()? {
+                return Ok(Some(frame));
+            }
+            match self.reader.read(&mut chunk)? {
+                0 => return Ok(None),
+                read => self.buffer.extend_from_slice(&chunk[..read]),
+            }
+        }
+    }
+
+    fn parse(&mut self) -> io::Result<Option<AMQPFrame>> {
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        let (consumed, mut frame) = match parse_frame(&self.buffer[..]) {
+            Ok((rest, frame)) => (self.buffer.len() - rest.len(), frame),
+            Err(err) if err.is_incomplete() => return Ok(None),
+            Err(err) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid frame: {:?}", err),
+                ))
+            }
+        };
+        restore_flags(&mut frame, &self.buffer[..consumed]);
+        self.buffer.drain(..consumed);
+        Ok(Some(frame))
+    }
+}
+
+/// The parser of amq-protocol loses the flags with a dash in their name, such as `no-ack`,
+/// read them back from the raw method
+fn restore_flags(frame: &mut AMQPFrame, raw: &[u8]) {
+    // The arguments follow the frame header and the class and method ids, starting with the
+    // reserved short of these methods
+    let flags = |strings: usize| {
+        let mut position = 13;
+        for _ in 0..strings {
+            position += 1 + usize::from(*raw.get(position)?);
+        }
+        raw.get(position).copied()
+    };
+    let set = |flags: u8, bit: u8| flags & (1 << bit) != 0;
+    match frame {
+        AMQPFrame::Method(_, AMQPClass::Exchange(exchange::AMQPMethod::Declare(declare))) => {
+            if let Some(flags) = flags(2) {
+                declare.auto_delete = set(flags, 2);
+            }
+        }
+        AMQPFrame::Method(_, AMQPClass::Exchange(exchange::AMQPMethod::Delete(delete))) => {
+            if let Some(flags) = flags(1) {
+                delete.if_unused = set(flags, 0);
+            }
+        }
+        AMQPFrame::Method(_, AMQPClass::Queue(queue::AMQPMethod::Declare(declare))) => {
+            if let Some(flags) = flags(1) {
+                declare.auto_delete = set(flags, 3);
+            }
+        }
+        AMQPFrame::Method(_, AMQPClass::Queue(queue::AMQPMethod::Delete(delete))) => {
+            if let Some(flags) = flags(1) {
+                delete.if_unused = set(flags, 0);
+                delete.if_empty = set(flags, 1);
+            }
+        }
+        AMQPFrame::Method(_, AMQPClass::Basic(basic::AMQPMethod::Consume(consume))) => {
+            if let Some(flags) = flags(2) {
+                consume.no_local = set(flags, 0);
+                consume.no_ack = set(flags, 1);
+            }
+        }
+        AMQPFrame::Method(_, AMQPClass::Basic(basic::AMQPMethod::Get(get))) => {
+            if let Some(flags) = flags(1) {
+                get.no_ack = set(flags, 0);
+            }
+        }
+        _ => {}
+    }
+}
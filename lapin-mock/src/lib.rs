@@ -0,0 +1,150 @@
+#![warn(rust_2018_idioms)]
+
+//! lapin-mock
+//!
+//! An in-process AMQP 0.9.1 broker to test code built on lapin without a running RabbitMQ.
+//!
+//! It implements the handshake, channels, direct/fanout/topic/headers exchanges, queues,
+//! consumers, `basic.get`, acks/nacks/rejects, publisher confirms and returns for unroutable
+//! mandatory messages. Everything is kept in memory and lost once the [`MockServer`] is dropped.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use lapin::{options::*, types::FieldTable, BasicProperties, Connection, ConnectionProperties};
+//! use lapin_mock::MockServer;
+//!
+//! fn main() {
+//!     let server = MockServer::start().expect("mock server");
+//!     async_global_executor::block_on(async {
+//!         let conn = Connection::connect(&server.uri(), ConnectionProperties::default())
+//!             .await
+//!             .expect("connection");
+//!         let channel = conn.create_channel().await.expect("channel");
+//!         channel
+//!             .queue_declare("hello", QueueDeclareOptions::default(), FieldTable::default())
+//!             .await
+//!             .expect("queue_declare");
+//!         channel
+//!             .basic_publish(
+//!                 "",
+//!                 "hello",
+//!                 BasicPublishOptions::default(),
+//!                 b"Hello world!".to_vec(),
+//!                 BasicProperties::default(),
+//!             )
+//!             .await
+//!             .expect("basic_publish");
+//!     })
+//! }
+//! ```
+//!
+//! [`MockServer`]: ./struct.MockServer.html
+
+use crate::{broker::Broker, session::Connection};
+use parking_lot::Mutex;
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+use tracing::{debug, error, trace};
+
+pub use amq_protocol::frame::{AMQPContentHeader, AMQPFrame};
+pub use script::{Script, ScriptedServer};
+
+mod broker;
+mod codec;
+mod script;
+mod session;
+
+/// An AMQP broker listening on a random local port
+///
+/// Each client connection is served by its own thread. All the connections share the same
+/// exchanges and queues, so several clients can talk to each other through it.
+#[derive(Debug)]
+pub struct MockServer {
+    broker: Arc<Mutex<Broker>>,
+    address: SocketAddr,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    /// Start listening on `127.0.0.1` with a port picked by the OS
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let broker = Arc::new(Mutex::new(Broker::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let server = Self {
+            broker: broker.clone(),
+            address,
+            stopped: stopped.clone(),
+        };
+        thread::Builder::new()
+            .name("lapin-mock-listener".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match stream {
+                        Ok(stream) => spawn_connection(broker.clone(), stream),
+                        Err(err) => error!(%err, "failed to accept connection"),
+                    }
+                }
+                trace!("mock server stopped");
+            })?;
+        Ok(server)
+    }
+
+    /// The address the server is listening on
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The URI to give to `Connection::connect` to reach this server, on the default vhost
+    pub fn uri(&self) -> String {
+        format!("amqp://{}/%2f", self.address)
+    }
+
+    /// Serve one client connection over an arbitrary duplex, such as an in-memory pipe, until
+    /// the client closes it or goes away
+    ///
+    /// This blocks the current thread, and shares the exchanges and queues of the server.
+    pub fn serve<R: Read, W: Write + Send + 'static>(
+        &self,
+        reader: R,
+        writer: W,
+    ) -> io::Result<()> {
+        Connection::new(self.broker.clone(), reader, writer).run()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake the listener up so that it notices it has been stopped
+        let _ = TcpStream::connect(self.address);
+    }
+}
+
+fn spawn_connection(broker: Arc<Mutex<Broker>>, stream: TcpStream) {
+    let spawned = stream.try_clone().and_then(|writer| {
+        let _ = stream.set_nodelay(true);
+        thread::Builder::new()
+            .name("lapin-mock-connection".into())
+            .spawn(move || {
+                if let Err(err) = Connection::new(broker, stream, writer).run() {
+                    debug!(%err, "connection failed");
+                }
+            })
+    });
+    if let Err(err) = spawned {
+        error!(%err, "failed to serve connection");
+    }
+}
@@ -0,0 +1,333 @@
+use crate::codec::{self, FrameReader};
+use amq_protocol::{
+    frame::{AMQPFrame, ProtocolVersion},
+    protocol::{channel, connection, AMQPClass},
+    types::FieldTable,
+};
+use std::{
+    fmt,
+    io::{self, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::trace;
+
+type Predicate = Box<dyn Fn(&AMQPFrame) -> bool + Send>;
+
+enum Step {
+    Expect(String, Predicate),
+    Silence(Duration),
+    Send(Vec<u8>),
+    Disconnect,
+}
+
+impl fmt::Debug for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Expect(description, _) => write!(f, "Expect({})", description),
+            Step::Silence(duration) => write!(f, "Silence({:?})", duration),
+            Step::Send(bytes) => write!(f, "Send({} bytes)", bytes.len()),
+            Step::Disconnect => write!(f, "Disconnect"),
+        }
+    }
+}
+
+/// The exact sequence of frames a [`ScriptedServer`] exchanges with its client
+///
+/// Each `expect` step waits for the next frame from the client (skipping heartbeats) and fails
+/// the script if it doesn't match, each `send` step writes frames or raw bytes as is, without
+/// any check of their consistency.
+///
+/// [`ScriptedServer`]: ./struct.ScriptedServer.html
+#[derive(Debug, Default)]
+pub struct Script {
+    steps: Vec<Step>,
+}
+
+impl Script {
+    /// An empty script
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Go through the connection handshake, with `frame_max` and `heartbeat` as tune parameters
+    pub fn handshake(self, frame_max: u32, heartbeat: u16) -> Self {
+        self.expect("protocol header", |frame| {
+            matches!(frame, AMQPFrame::ProtocolHeader(version) if *version == ProtocolVersion::amqp_0_9_1())
+        })
+        .send_method(
+            0,
+            AMQPClass::Connection(connection::AMQPMethod::Start(connection::Start {
+                version_major: 0,
+                version_minor: 9,
+                server_properties: FieldTable::default(),
+                mechanisms: "PLAIN AMQPLAIN".into(),
+                locales: "en_US".into(),
+            })),
+        )
+        .expect_method(0, "connection.start-ok", |method| {
+            matches!(
+                method,
+                AMQPClass::Connection(connection::AMQPMethod::StartOk(_))
+            )
+        })
+        .send_method(
+            0,
+            AMQPClass::Connection(connection::AMQPMethod::Tune(connection::Tune {
+                channel_max: 2047,
+                frame_max,
+                heartbeat,
+            })),
+        )
+        .expect_method(0, "connection.tune-ok", |method| {
+            matches!(
+                method,
+                AMQPClass::Connection(connection::AMQPMethod::TuneOk(_))
+            )
+        })
+        .expect_method(0, "connection.open", |method| {
+            matches!(method, AMQPClass::Connection(connection::AMQPMethod::Open(_)))
+        })
+        .send_method(
+            0,
+            AMQPClass::Connection(connection::AMQPMethod::OpenOk(
+                connection::OpenOk::default(),
+            )),
+        )
+    }
+
+    /// Let the client open the channel `id`
+    pub fn open_channel(self, id: u16) -> Self {
+        self.expect_method(id, "channel.open", |method| {
+            matches!(method, AMQPClass::Channel(channel::AMQPMethod::Open(_)))
+        })
+        .send_method(
+            id,
+            AMQPClass::Channel(channel::AMQPMethod::OpenOk(channel::OpenOk::default())),
+        )
+    }
+
+    /// Wait for the next frame from the client and check it against `predicate`
+    pub fn expect<F: Fn(&AMQPFrame) -> bool + Send + 'static>(
+        mut self,
+        description: &str,
+        predicate: F,
+    ) -> Self {
+        self.steps
+            .push(Step::Expect(description.into(), Box::new(predicate)));
+        self
+    }
+
+    /// Wait for the next frame from the client, which must be a method on `channel` matching
+    /// `predicate`
+    pub fn expect_method<F: Fn(&AMQPClass) -> bool + Send + 'static>(
+        self,
+        channel: u16,
+        description: &str,
+        predicate: F,
+    ) -> Self {
+        self.expect(description, move |frame| match frame {
+            AMQPFrame::Method(id, method) => *id == channel && predicate(method),
+            _ => false,
+        })
+    }
+
+    /// Check that the client doesn't send anything but heartbeats for `duration`
+    pub fn expect_silence(mut self, duration: Duration) -> Self {
+        self.steps.push(Step::Silence(duration));
+        self
+    }
+
+    /// Send a frame to the client
+    pub fn send(self, frame: AMQPFrame) -> Self {
+        let bytes = codec::serialize(&frame).expect("invalid frame in script");
+        self.send_raw(bytes)
+    }
+
+    /// Send a method frame to the client
+    pub fn send_method(self, channel: u16, method: AMQPClass) -> Self {
+        self.send(AMQPFrame::Method(channel, method))
+    }
+
+    /// Send raw bytes to the client, to craft truncated or invalid frames
+    pub fn send_raw(mut self, bytes: Vec<u8>) -> Self {
+        self.steps.push(Step::Send(bytes));
+        self
+    }
+
+    /// Close the socket without going through connection.close
+    pub fn disconnect(mut self) -> Self {
+        self.steps.push(Step::Disconnect);
+        self
+    }
+}
+
+/// How long an `expect` step waits for the client
+const EXPECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the client may stay silent once the script is done
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long [`ScriptedServer::finish`] waits for the script to be done
+///
+/// [`ScriptedServer::finish`]: ./struct.ScriptedServer.html#method.finish
+const FINISH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A fake AMQP server accepting a single connection and playing a [`Script`] on it
+///
+/// Once the script is done, the server keeps on recording what the client sends until it
+/// disconnects or stays silent for a second. The connection then stays open until the server
+/// gets dropped, so that the state of the client can be checked before it sees the socket
+/// going away.
+///
+/// [`Script`]: ./struct.Script.html
+#[derive(Debug)]
+pub struct ScriptedServer {
+    address: SocketAddr,
+    result: Receiver<io::Result<Vec<AMQPFrame>>>,
+    // Dropping it lets the server close the connection
+    _shutdown: Sender<()>,
+}
+
+impl ScriptedServer {
+    /// Start listening on `127.0.0.1` with a port picked by the OS
+    pub fn start(script: Script) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let (sender, result) = mpsc::channel();
+        let (shutdown, closed) = mpsc::channel::<()>();
+        thread::Builder::new()
+            .name("lapin-mock-script".into())
+            .spawn(move || {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        let _ = sender.send(Err(err));
+                        return;
+                    }
+                };
+                let _ = sender.send(play(script, &stream));
+                // Wait for the server to be dropped before closing the connection
+                let _ = closed.recv();
+            })?;
+        Ok(Self {
+            address,
+            result,
+            _shutdown: shutdown,
+        })
+    }
+
+    /// The URI to give to `Connection::connect` to reach this server
+    pub fn uri(&self) -> String {
+        format!("amqp://{}/%2f", self.address)
+    }
+
+    /// Wait for the script to be done, returning all the frames the client sent, heartbeats
+    /// included
+    ///
+    /// Fails if the client didn't send what the script expected, or if the script isn't done
+    /// after 30 seconds.
+    pub fn finish(&self) -> io::Result<Vec<AMQPFrame>> {
+        self.result
+            .recv_timeout(FINISH_TIMEOUT)
+            .unwrap_or_else(|err| {
+                Err(match err {
+                    RecvTimeoutError::Timeout => io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "scripted server didn't finish in time",
+                    ),
+                    RecvTimeoutError::Disconnected => {
+                        io::Error::new(io::ErrorKind::Other, "scripted server panicked")
+                    }
+                })
+            })
+    }
+}
+
+fn play(script: Script, stream: &TcpStream) -> io::Result<Vec<AMQPFrame>> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = FrameReader::new(stream.try_clone()?);
+    stream.set_read_timeout(Some(EXPECT_TIMEOUT))?;
+    let mut received = Vec::new();
+    for step in script.steps {
+        trace!(?step, "playing step");
+        match step {
+            Step::Expect(description, predicate) => loop {
+                let frame = reader.read_frame()?.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("client disconnected while we expected {}", description),
+                    )
+                })?;
+                received.push(frame.clone());
+                if let AMQPFrame::Heartbeat(_) = frame {
+                    continue;
+                }
+                if !predicate(&frame) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("expected {}, got {:?}", description, frame),
+                    ));
+                }
+                break;
+            },
+            Step::Silence(duration) => {
+                let deadline = Instant::now() + duration;
+                while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                    if remaining == Duration::default() {
+                        break;
+                    }
+                    stream.set_read_timeout(Some(remaining))?;
+                    match reader.read_frame() {
+                        Ok(Some(AMQPFrame::Heartbeat(channel))) => {
+                            received.push(AMQPFrame::Heartbeat(channel))
+                        }
+                        Ok(Some(frame)) => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("expected silence, got {:?}", frame),
+                            ))
+                        }
+                        Ok(None) => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                "client disconnected while we expected silence",
+                            ))
+                        }
+                        Err(err)
+                            if err.kind() == io::ErrorKind::WouldBlock
+                                || err.kind() == io::ErrorKind::TimedOut =>
+                        {
+                            break
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+                stream.set_read_timeout(Some(EXPECT_TIMEOUT))?;
+            }
+            Step::Send(bytes) => {
+                writer.write_all(&bytes)?;
+                writer.flush()?;
+            }
+            Step::Disconnect => {
+                stream.shutdown(std::net::Shutdown::Both)?;
+                return Ok(received);
+            }
+        }
+    }
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    loop {
+        match reader.read_frame() {
+            Ok(Some(frame)) => received.push(frame),
+            Ok(None) => return Ok(received),
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut
+                    || err.kind() == io::ErrorKind::ConnectionReset =>
+            {
+                return Ok(received)
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
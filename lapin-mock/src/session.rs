@@ -0,0 +1,767 @@
+use crate::{
+    broker::{Broker, Message, Outcome, Refusal, NO_ROUTE},
+    codec::{self, FrameReader},
+};
+use amq_protocol::{
+    frame::{AMQPContentHeader, AMQPFrame, ProtocolVersion},
+    protocol::{basic, channel, confirm, connection, exchange, queue, AMQPClass, BasicProperties},
+    types::{AMQPValue, FieldTable},
+};
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{self, Read, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tracing::{debug, error, trace};
+
+pub(crate) const FRAME_ERROR: u16 = 501;
+pub(crate) const COMMAND_INVALID: u16 = 503;
+pub(crate) const CHANNEL_ERROR: u16 = 504;
+pub(crate) const UNEXPECTED_FRAME: u16 = 505;
+pub(crate) const NOT_ALLOWED: u16 = 530;
+pub(crate) const NOT_IMPLEMENTED: u16 = 540;
+
+const CHANNEL_MAX: u16 = 2047;
+const FRAME_MAX: u32 = 131_072;
+const FRAME_OVERHEAD: usize = 8;
+
+/// The writing half of a client connection, shared with the broker to push deliveries
+pub(crate) struct Session {
+    id: u64,
+    writer: Mutex<Box<dyn Write + Send>>,
+    frame_max: AtomicUsize,
+}
+
+impl Session {
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn send(&self, frames: Vec<AMQPFrame>) {
+        let mut buffer = Vec::new();
+        for frame in &frames {
+            trace!(session=%self.id, ?frame, "sending frame");
+            match codec::serialize(frame) {
+                Ok(bytes) => buffer.extend(bytes),
+                Err(err) => error!(session=%self.id, %err),
+            }
+        }
+        // A client which went away will be noticed by the reading half
+        let mut writer = self.writer.lock();
+        if let Err(err) = writer.write_all(&buffer).and_then(|()| writer.flush()) {
+            debug!(session=%self.id, %err, "failed to write to client");
+        }
+    }
+
+    fn send_method(&self, channel: u16, method: AMQPClass) {
+        self.send(vec![AMQPFrame::Method(channel, method)]);
+    }
+
+    fn send_content(
+        &self,
+        channel: u16,
+        method: AMQPClass,
+        properties: &BasicProperties,
+        body: &[u8],
+    ) {
+        let class_id = method.get_amqp_class_id();
+        let mut frames = vec![
+            AMQPFrame::Method(channel, method),
+            AMQPFrame::Header(
+                channel,
+                class_id,
+                Box::new(AMQPContentHeader {
+                    class_id,
+                    weight: 0,
+                    body_size: body.len() as u64,
+                    properties: properties.clone(),
+                }),
+            ),
+        ];
+        let chunk_size = self.frame_max.load(Ordering::SeqCst) - FRAME_OVERHEAD;
+        frames.extend(
+            body.chunks(chunk_size)
+                .map(|chunk| AMQPFrame::Body(channel, chunk.into())),
+        );
+        self.send(frames);
+    }
+
+    pub(crate) fn send_delivery(
+        &self,
+        channel: u16,
+        consumer_tag: &str,
+        delivery_tag: u64,
+        message: &Message,
+    ) {
+        self.send_content(
+            channel,
+            AMQPClass::Basic(basic::AMQPMethod::Deliver(basic::Deliver {
+                consumer_tag: consumer_tag.into(),
+                delivery_tag,
+                redelivered: message.redelivered,
+                exchange: message.exchange.as_str().into(),
+                routing_key: message.routing_key.as_str().into(),
+            })),
+            &message.properties,
+            &message.body,
+        );
+    }
+
+    pub(crate) fn send_consumer_cancel(&self, channel: u16, consumer_tag: &str) {
+        self.send_method(
+            channel,
+            AMQPClass::Basic(basic::AMQPMethod::Cancel(basic::Cancel {
+                consumer_tag: consumer_tag.into(),
+                nowait: true,
+            })),
+        );
+    }
+
+    fn close_channel(&self, channel: u16, code: u16, text: String, method: &AMQPClass) {
+        self.send_method(
+            channel,
+            AMQPClass::Channel(channel::AMQPMethod::Close(channel::Close {
+                reply_code: code,
+                reply_text: text.into(),
+                class_id: method.get_amqp_class_id(),
+                method_id: method.get_amqp_method_id(),
+            })),
+        );
+    }
+
+    fn close_connection(&self, code: u16, text: &str, class_id: u16, method_id: u16) {
+        self.send_method(
+            0,
+            AMQPClass::Connection(connection::AMQPMethod::Close(connection::Close {
+                reply_code: code,
+                reply_text: text.into(),
+                class_id,
+                method_id,
+            })),
+        );
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session").field("id", &self.id).finish()
+    }
+}
+
+/// A message being published, waiting for its header and body frames
+struct PendingPublish {
+    method: basic::Publish,
+    properties: BasicProperties,
+    body_size: usize,
+    body: Vec<u8>,
+}
+
+enum Flow {
+    Continue,
+    Stop,
+}
+
+/// The reading half of a client connection
+pub(crate) struct Connection<R: Read> {
+    broker: Arc<Mutex<Broker>>,
+    session: Arc<Session>,
+    reader: FrameReader<R>,
+    channels: HashSet<u16>,
+    // Channels we closed, ignoring everything until the client acknowledges it
+    closing: HashSet<u16>,
+    publishes: HashMap<u16, PendingPublish>,
+    // Once we asked the client to close the connection, only close-ok matters
+    closing_connection: bool,
+}
+
+impl<R: Read> Connection<R> {
+    pub(crate) fn new<W: Write + Send + 'static>(
+        broker: Arc<Mutex<Broker>>,
+        reader: R,
+        writer: W,
+    ) -> Self {
+        let id = broker.lock().next_id();
+        Self {
+            broker,
+            session: Arc::new(Session {
+                id,
+                writer: Mutex::new(Box::new(writer)),
+                frame_max: AtomicUsize::new(FRAME_MAX as usize),
+            }),
+            reader: FrameReader::new(reader),
+            channels: HashSet::new(),
+            closing: HashSet::new(),
+            publishes: HashMap::new(),
+            closing_connection: false,
+        }
+    }
+
+    /// Serve the client until it closes the connection or goes away
+    pub(crate) fn run(mut self) -> io::Result<()> {
+        debug!(session=%self.session.id, "client connected");
+        let res = self.serve();
+        self.broker.lock().close_session(self.session.id);
+        debug!(session=%self.session.id, "client disconnected");
+        res
+    }
+
+    fn serve(&mut self) -> io::Result<()> {
+        loop {
+            let frame = match self.reader.read_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return Ok(()),
+                Err(err) => {
+                    if err.kind() == io::ErrorKind::InvalidData {
+                        error!(session=%self.session.id, %err);
+                        self.session
+                            .close_connection(FRAME_ERROR, "FRAME_ERROR", 0, 0);
+                    }
+                    return Err(err);
+                }
+            };
+            trace!(session=%self.session.id, ?frame, "received frame");
+            if let Flow::Stop = self.handle_frame(frame) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn handle_frame(&mut self, frame: AMQPFrame) -> Flow {
+        match frame {
+            AMQPFrame::ProtocolHeader(version) => {
+                if version != ProtocolVersion::amqp_0_9_1() {
+                    // Tell the client which version we speak before hanging up
+                    self.session.send(vec![AMQPFrame::ProtocolHeader(
+                        ProtocolVersion::amqp_0_9_1(),
+                    )]);
+                    return Flow::Stop;
+                }
+                self.session.send_method(
+                    0,
+                    AMQPClass::Connection(connection::AMQPMethod::Start(connection::Start {
+                        version_major: 0,
+                        version_minor: 9,
+                        server_properties: server_properties(),
+                        mechanisms: "PLAIN AMQPLAIN EXTERNAL".into(),
+                        locales: "en_US".into(),
+                    })),
+                );
+                Flow::Continue
+            }
+            AMQPFrame::Heartbeat(_) => {
+                self.session.send(vec![AMQPFrame::Heartbeat(0)]);
+                Flow::Continue
+            }
+            AMQPFrame::Method(0, method) => self.handle_connection_method(method),
+            AMQPFrame::Method(_, _) if self.closing_connection => Flow::Continue,
+            AMQPFrame::Method(channel, method) => {
+                self.handle_channel_method(channel, method);
+                Flow::Continue
+            }
+            AMQPFrame::Header(channel, _, header) => {
+                if self.closing.contains(&channel) {
+                    return Flow::Continue;
+                }
+                match self.publishes.get_mut(&channel) {
+                    Some(publish) => {
+                        publish.properties = header.properties;
+                        publish.body_size = header.body_size as usize;
+                        self.complete_publish(channel)
+                    }
+                    None => self.unexpected_frame(),
+                }
+            }
+            AMQPFrame::Body(channel, payload) => {
+                if self.closing.contains(&channel) {
+                    return Flow::Continue;
+                }
+                match self.publishes.get_mut(&channel) {
+                    Some(publish) => {
+                        publish.body.extend(payload);
+                        self.complete_publish(channel)
+                    }
+                    None => self.unexpected_frame(),
+                }
+            }
+        }
+    }
+
+    fn unexpected_frame(&mut self) -> Flow {
+        self.session
+            .close_connection(UNEXPECTED_FRAME, "UNEXPECTED_FRAME", 0, 0);
+        self.closing_connection = true;
+        Flow::Continue
+    }
+
+    fn handle_connection_method(&mut self, method: AMQPClass) -> Flow {
+        match method {
+            AMQPClass::Connection(connection::AMQPMethod::StartOk(_)) => {
+                self.session.send_method(
+                    0,
+                    AMQPClass::Connection(connection::AMQPMethod::Tune(connection::Tune {
+                        channel_max: CHANNEL_MAX,
+                        frame_max: FRAME_MAX,
+                        heartbeat: 0,
+                    })),
+                );
+            }
+            AMQPClass::Connection(connection::AMQPMethod::TuneOk(tune)) => {
+                if tune.frame_max != 0 && tune.frame_max < FRAME_MAX {
+                    self.session
+                        .frame_max
+                        .store(tune.frame_max as usize, Ordering::SeqCst);
+                }
+            }
+            AMQPClass::Connection(connection::AMQPMethod::Open(_)) => {
+                self.session.send_method(
+                    0,
+                    AMQPClass::Connection(connection::AMQPMethod::OpenOk(
+                        connection::OpenOk::default(),
+                    )),
+                );
+            }
+            AMQPClass::Connection(connection::AMQPMethod::Close(_)) => {
+                self.session.send_method(
+                    0,
+                    AMQPClass::Connection(connection::AMQPMethod::CloseOk(
+                        connection::CloseOk::default(),
+                    )),
+                );
+                return Flow::Stop;
+            }
+            AMQPClass::Connection(connection::AMQPMethod::CloseOk(_)) => return Flow::Stop,
+            AMQPClass::Connection(connection::AMQPMethod::Blocked(_))
+            | AMQPClass::Connection(connection::AMQPMethod::Unblocked(_)) => {}
+            method => {
+                self.session.close_connection(
+                    NOT_IMPLEMENTED,
+                    "NOT_IMPLEMENTED",
+                    method.get_amqp_class_id(),
+                    method.get_amqp_method_id(),
+                );
+                self.closing_connection = true;
+            }
+        }
+        Flow::Continue
+    }
+
+    fn handle_channel_method(&mut self, channel: u16, method: AMQPClass) {
+        if self.closing.contains(&channel) {
+            if let AMQPClass::Channel(channel::AMQPMethod::CloseOk(_)) = method {
+                self.closing.remove(&channel);
+            }
+            if let AMQPClass::Channel(channel::AMQPMethod::Close(_)) = method {
+                self.closing.remove(&channel);
+                self.send_channel_close_ok(channel);
+            }
+            return;
+        }
+        if let AMQPClass::Channel(channel::AMQPMethod::Open(_)) = method {
+            if channel > CHANNEL_MAX || !self.channels.insert(channel) {
+                self.session.close_connection(
+                    CHANNEL_ERROR,
+                    "CHANNEL_ERROR",
+                    method.get_amqp_class_id(),
+                    method.get_amqp_method_id(),
+                );
+                self.closing_connection = true;
+                return;
+            }
+            self.broker.lock().open_channel(self.session.id, channel);
+            self.session.send_method(
+                channel,
+                AMQPClass::Channel(channel::AMQPMethod::OpenOk(channel::OpenOk::default())),
+            );
+            return;
+        }
+        if !self.channels.contains(&channel) {
+            self.session.close_connection(
+                CHANNEL_ERROR,
+                "CHANNEL_ERROR",
+                method.get_amqp_class_id(),
+                method.get_amqp_method_id(),
+            );
+            self.closing_connection = true;
+            return;
+        }
+        if let Err(Refusal(code, text)) = self.handle_method(channel, &method) {
+            debug!(session=%self.session.id, %channel, %code, %text, "closing channel");
+            self.forget_channel(channel);
+            self.closing.insert(channel);
+            self.session.close_channel(channel, code, text, &method);
+        }
+    }
+
+    fn forget_channel(&mut self, channel: u16) {
+        self.channels.remove(&channel);
+        self.publishes.remove(&channel);
+        self.broker.lock().close_channel(self.session.id, channel);
+    }
+
+    fn send_channel_close_ok(&self, channel: u16) {
+        self.session.send_method(
+            channel,
+            AMQPClass::Channel(channel::AMQPMethod::CloseOk(channel::CloseOk::default())),
+        );
+    }
+
+    fn handle_method(&mut self, id: u16, method: &AMQPClass) -> Outcome<()> {
+        let session = self.session.id;
+        match method {
+            AMQPClass::Channel(channel::AMQPMethod::Close(_)) => {
+                self.forget_channel(id);
+                self.send_channel_close_ok(id);
+            }
+            AMQPClass::Channel(channel::AMQPMethod::Flow(flow)) => {
+                self.reply(
+                    id,
+                    false,
+                    AMQPClass::Channel(channel::AMQPMethod::FlowOk(channel::FlowOk {
+                        active: flow.active,
+                    })),
+                );
+            }
+            AMQPClass::Exchange(exchange::AMQPMethod::Declare(declare)) => {
+                self.broker.lock().declare_exchange(
+                    declare.exchange.as_str(),
+                    declare.kind.as_str(),
+                    declare.passive,
+                    declare.durable,
+                    declare.auto_delete,
+                    declare.internal,
+                    &declare.arguments,
+                )?;
+                self.reply(
+                    id,
+                    declare.nowait,
+                    AMQPClass::Exchange(exchange::AMQPMethod::DeclareOk(
+                        exchange::DeclareOk::default(),
+                    )),
+                );
+            }
+            AMQPClass::Exchange(exchange::AMQPMethod::Delete(delete)) => {
+                self.broker
+                    .lock()
+                    .delete_exchange(delete.exchange.as_str(), delete.if_unused)?;
+                self.reply(
+                    id,
+                    delete.nowait,
+                    AMQPClass::Exchange(exchange::AMQPMethod::DeleteOk(
+                        exchange::DeleteOk::default(),
+                    )),
+                );
+            }
+            AMQPClass::Exchange(exchange::AMQPMethod::Bind(bind)) => {
+                self.broker.lock().bind(
+                    bind.source.as_str(),
+                    bind.destination.as_str(),
+                    true,
+                    bind.routing_key.as_str(),
+                    bind.arguments.clone(),
+                )?;
+                self.reply(
+                    id,
+                    bind.nowait,
+                    AMQPClass::Exchange(exchange::AMQPMethod::BindOk(exchange::BindOk::default())),
+                );
+            }
+            AMQPClass::Exchange(exchange::AMQPMethod::Unbind(unbind)) => {
+                self.broker.lock().unbind(
+                    unbind.source.as_str(),
+                    unbind.destination.as_str(),
+                    true,
+                    unbind.routing_key.as_str(),
+                    unbind.arguments.clone(),
+                )?;
+                self.reply(
+                    id,
+                    unbind.nowait,
+                    AMQPClass::Exchange(exchange::AMQPMethod::UnbindOk(
+                        exchange::UnbindOk::default(),
+                    )),
+                );
+            }
+            AMQPClass::Queue(queue::AMQPMethod::Declare(declare)) => {
+                let (queue, message_count, consumer_count) = self.broker.lock().declare_queue(
+                    session,
+                    declare.queue.as_str(),
+                    declare.passive,
+                    declare.durable,
+                    declare.exclusive,
+                    declare.auto_delete,
+                    &declare.arguments,
+                )?;
+                self.reply(
+                    id,
+                    declare.nowait,
+                    AMQPClass::Queue(queue::AMQPMethod::DeclareOk(queue::DeclareOk {
+                        queue: queue.into(),
+                        message_count,
+                        consumer_count,
+                    })),
+                );
+            }
+            AMQPClass::Queue(queue::AMQPMethod::Bind(bind)) => {
+                self.broker.lock().bind(
+                    bind.exchange.as_str(),
+                    bind.queue.as_str(),
+                    false,
+                    bind.routing_key.as_str(),
+                    bind.arguments.clone(),
+                )?;
+                self.reply(
+                    id,
+                    bind.nowait,
+                    AMQPClass::Queue(queue::AMQPMethod::BindOk(queue::BindOk::default())),
+                );
+            }
+            AMQPClass::Queue(queue::AMQPMethod::Unbind(unbind)) => {
+                self.broker.lock().unbind(
+                    unbind.exchange.as_str(),
+                    unbind.queue.as_str(),
+                    false,
+                    unbind.routing_key.as_str(),
+                    unbind.arguments.clone(),
+                )?;
+                self.reply(
+                    id,
+                    false,
+                    AMQPClass::Queue(queue::AMQPMethod::UnbindOk(queue::UnbindOk::default())),
+                );
+            }
+            AMQPClass::Queue(queue::AMQPMethod::Purge(purge)) => {
+                let message_count = self.broker.lock().purge_queue(purge.queue.as_str())?;
+                self.reply(
+                    id,
+                    purge.nowait,
+                    AMQPClass::Queue(queue::AMQPMethod::PurgeOk(queue::PurgeOk { message_count })),
+                );
+            }
+            AMQPClass::Queue(queue::AMQPMethod::Delete(delete)) => {
+                let message_count = self.broker.lock().delete_queue(
+                    delete.queue.as_str(),
+                    delete.if_unused,
+                    delete.if_empty,
+                )?;
+                self.reply(
+                    id,
+                    delete.nowait,
+                    AMQPClass::Queue(queue::AMQPMethod::DeleteOk(queue::DeleteOk {
+                        message_count,
+                    })),
+                );
+            }
+            AMQPClass::Basic(basic::AMQPMethod::Qos(qos)) => {
+                self.broker
+                    .lock()
+                    .set_prefetch(session, id, qos.prefetch_count);
+                self.reply(
+                    id,
+                    false,
+                    AMQPClass::Basic(basic::AMQPMethod::QosOk(basic::QosOk::default())),
+                );
+            }
+            AMQPClass::Basic(basic::AMQPMethod::Consume(consume)) => {
+                let mut broker = self.broker.lock();
+                let consumer_tag = broker.consume(
+                    &self.session,
+                    id,
+                    consume.queue.as_str(),
+                    consume.consumer_tag.as_str(),
+                    consume.no_ack,
+                    consume.exclusive,
+                )?;
+                // The client needs to know about the consumer before getting deliveries
+                self.reply(
+                    id,
+                    consume.nowait,
+                    AMQPClass::Basic(basic::AMQPMethod::ConsumeOk(basic::ConsumeOk {
+                        consumer_tag: consumer_tag.into(),
+                    })),
+                );
+                broker.dispatch_all();
+            }
+            AMQPClass::Basic(basic::AMQPMethod::Cancel(cancel)) => {
+                self.broker
+                    .lock()
+                    .cancel(session, id, cancel.consumer_tag.as_str());
+                self.reply(
+                    id,
+                    cancel.nowait,
+                    AMQPClass::Basic(basic::AMQPMethod::CancelOk(basic::CancelOk {
+                        consumer_tag: cancel.consumer_tag.clone(),
+                    })),
+                );
+            }
+            AMQPClass::Basic(basic::AMQPMethod::Publish(publish)) => {
+                self.publishes.insert(
+                    id,
+                    PendingPublish {
+                        method: publish.clone(),
+                        properties: BasicProperties::default(),
+                        body_size: 0,
+                        body: Vec::new(),
+                    },
+                );
+            }
+            AMQPClass::Basic(basic::AMQPMethod::Get(get)) => {
+                let got = self
+                    .broker
+                    .lock()
+                    .get(session, id, get.queue.as_str(), get.no_ack)?;
+                match got {
+                    Some((delivery_tag, message, message_count)) => self.session.send_content(
+                        id,
+                        AMQPClass::Basic(basic::AMQPMethod::GetOk(basic::GetOk {
+                            delivery_tag,
+                            redelivered: message.redelivered,
+                            exchange: message.exchange.as_str().into(),
+                            routing_key: message.routing_key.as_str().into(),
+                            message_count,
+                        })),
+                        &message.properties,
+                        &message.body,
+                    ),
+                    None => self.reply(
+                        id,
+                        false,
+                        AMQPClass::Basic(basic::AMQPMethod::GetEmpty(basic::GetEmpty::default())),
+                    ),
+                }
+            }
+            AMQPClass::Basic(basic::AMQPMethod::Ack(ack)) => {
+                self.broker
+                    .lock()
+                    .settle(session, id, ack.delivery_tag, ack.multiple, None)?;
+            }
+            AMQPClass::Basic(basic::AMQPMethod::Nack(nack)) => {
+                self.broker.lock().settle(
+                    session,
+                    id,
+                    nack.delivery_tag,
+                    nack.multiple,
+                    Some(nack.requeue),
+                )?;
+            }
+            AMQPClass::Basic(basic::AMQPMethod::Reject(reject)) => {
+                self.broker.lock().settle(
+                    session,
+                    id,
+                    reject.delivery_tag,
+                    false,
+                    Some(reject.requeue),
+                )?;
+            }
+            AMQPClass::Confirm(confirm::AMQPMethod::Select(select)) => {
+                self.broker.lock().set_confirm(session, id);
+                self.reply(
+                    id,
+                    select.nowait,
+                    AMQPClass::Confirm(confirm::AMQPMethod::SelectOk(confirm::SelectOk::default())),
+                );
+            }
+            _ => {
+                return Err(Refusal(
+                    NOT_IMPLEMENTED,
+                    format!(
+                        "method {}.{} is not implemented by lapin-mock",
+                        method.get_amqp_class_id(),
+                        method.get_amqp_method_id()
+                    ),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn reply(&self, channel: u16, nowait: bool, method: AMQPClass) {
+        if !nowait {
+            self.session.send_method(channel, method);
+        }
+    }
+
+    fn complete_publish(&mut self, channel: u16) -> Flow {
+        let done = self
+            .publishes
+            .get(&channel)
+            .map_or(false, |publish| publish.body.len() >= publish.body_size);
+        if !done {
+            return Flow::Continue;
+        }
+        let publish = self.publishes.remove(&channel).expect("publish vanished");
+        let method = AMQPClass::Basic(basic::AMQPMethod::Publish(publish.method.clone()));
+        let mut broker = self.broker.lock();
+        let published = broker.publish(
+            self.session.id,
+            channel,
+            publish.method.exchange.as_str(),
+            publish.method.routing_key.as_str(),
+            publish.properties.clone(),
+            publish.body.clone(),
+        );
+        drop(broker);
+        match published {
+            Ok(published) => {
+                if !published.routed && publish.method.mandatory {
+                    self.session.send_content(
+                        channel,
+                        AMQPClass::Basic(basic::AMQPMethod::Return(basic::Return {
+                            reply_code: NO_ROUTE,
+                            reply_text: "NO_ROUTE".into(),
+                            exchange: publish.method.exchange.clone(),
+                            routing_key: publish.method.routing_key.clone(),
+                        })),
+                        &publish.properties,
+                        &publish.body,
+                    );
+                }
+                if let Some(delivery_tag) = published.confirm_tag {
+                    self.session.send_method(
+                        channel,
+                        AMQPClass::Basic(basic::AMQPMethod::Ack(basic::Ack {
+                            delivery_tag,
+                            multiple: false,
+                        })),
+                    );
+                }
+            }
+            Err(Refusal(code, text)) => {
+                debug!(session=%self.session.id, %channel, %code, %text, "closing channel");
+                self.forget_channel(channel);
+                self.closing.insert(channel);
+                self.session.close_channel(channel, code, text, &method);
+            }
+        }
+        Flow::Continue
+    }
+}
+
+fn server_properties() -> FieldTable {
+    let mut capabilities = FieldTable::default();
+    for capability in &[
+        "publisher_confirms",
+        "exchange_exchange_bindings",
+        "basic.nack",
+        "consumer_cancel_notify",
+        "connection.blocked",
+        "per_consumer_qos",
+    ] {
+        capabilities.insert((*capability).into(), true.into());
+    }
+    let mut properties = FieldTable::default();
+    properties.insert(
+        "product".into(),
+        AMQPValue::LongString(env!("CARGO_PKG_NAME").into()),
+    );
+    properties.insert(
+        "version".into(),
+        AMQPValue::LongString(env!("CARGO_PKG_VERSION").into()),
+    );
+    properties.insert("capabilities".into(), AMQPValue::FieldTable(capabilities));
+    properties
+}
@@ -0,0 +1,129 @@
+use lapin::{
+    options::*,
+    protocol::{AMQPErrorKind, AMQPSoftError},
+    types::FieldTable,
+    BasicProperties, Connection, ConnectionProperties, Error, ExchangeKind,
+};
+use lapin_mock::MockServer;
+
+#[test]
+fn routing_and_settlement() {
+    let server = MockServer::start().expect("mock server");
+
+    async_global_executor::block_on(async {
+        let conn = Connection::connect(&server.uri(), ConnectionProperties::default())
+            .await
+            .expect("connection");
+        let channel = conn.create_channel().await.expect("create_channel");
+
+        channel
+            .exchange_declare(
+                "invoices",
+                ExchangeKind::Topic,
+                ExchangeDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .expect("exchange_declare");
+        let queue = channel
+            .queue_declare("", QueueDeclareOptions::default(), FieldTable::default())
+            .await
+            .expect("queue_declare");
+        assert!(queue.name().as_str().starts_with("amq.gen-"));
+        channel
+            .queue_bind(
+                queue.name().as_str(),
+                "invoices",
+                "invoice.*.paid",
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .expect("queue_bind");
+
+        for routing_key in &["invoice.42.paid", "invoice.42.sent", "invoice.43.paid"] {
+            channel
+                .basic_publish(
+                    "invoices",
+                    routing_key,
+                    BasicPublishOptions::default(),
+                    routing_key.as_bytes().to_vec(),
+                    BasicProperties::default(),
+                )
+                .await
+                .expect("basic_publish");
+        }
+
+        let first = channel
+            .basic_get(queue.name().as_str(), BasicGetOptions::default())
+            .await
+            .expect("basic_get")
+            .expect("first message");
+        assert_eq!(first.delivery.data, b"invoice.42.paid");
+        assert_eq!(first.message_count, 1);
+        channel
+            .basic_nack(
+                first.delivery.delivery_tag,
+                BasicNackOptions {
+                    requeue: true,
+                    ..BasicNackOptions::default()
+                },
+            )
+            .await
+            .expect("basic_nack");
+
+        let requeued = channel
+            .basic_get(queue.name().as_str(), BasicGetOptions { no_ack: true })
+            .await
+            .expect("basic_get")
+            .expect("requeued message");
+        assert_eq!(requeued.delivery.data, b"invoice.42.paid");
+        assert!(requeued.delivery.redelivered);
+
+        let second = channel
+            .basic_get(queue.name().as_str(), BasicGetOptions { no_ack: true })
+            .await
+            .expect("basic_get")
+            .expect("second message");
+        assert_eq!(second.delivery.data, b"invoice.43.paid");
+
+        assert!(channel
+            .basic_get(queue.name().as_str(), BasicGetOptions::default())
+            .await
+            .expect("basic_get")
+            .is_none());
+    })
+}
+
+#[test]
+fn passive_declare_of_missing_queue() {
+    let server = MockServer::start().expect("mock server");
+
+    async_global_executor::block_on(async {
+        let conn = Connection::connect(&server.uri(), ConnectionProperties::default())
+            .await
+            .expect("connection");
+        let channel = conn.create_channel().await.expect("create_channel");
+
+        let error = channel
+            .queue_declare(
+                "missing",
+                QueueDeclareOptions {
+                    passive: true,
+                    ..QueueDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .expect_err("queue shouldn't exist");
+        match error {
+            Error::ProtocolError(error) => {
+                assert_eq!(error.kind(), &AMQPErrorKind::Soft(AMQPSoftError::NOTFOUND))
+            }
+            error => panic!("unexpected error: {}", error),
+        }
+
+        // The connection survives the channel error
+        conn.create_channel().await.expect("create_channel");
+    })
+}
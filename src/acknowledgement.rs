@@ -5,11 +5,7 @@ use crate::{
     Error, Promise, Result,
 };
 use parking_lot::Mutex;
-use std::{
-    collections::{HashMap, HashSet},
-    fmt,
-    sync::Arc,
-};
+use std::{collections::BTreeMap, fmt, sync::Arc};
 
 pub type DeliveryTag = u64;
 
@@ -37,6 +33,10 @@ impl Acknowledgements {
         Some(promise.await)
     }
 
+    pub(crate) fn has_pending(&self) -> bool {
+        !self.0.lock().pending.is_empty()
+    }
+
     pub(crate) fn ack(&self, delivery_tag: DeliveryTag, channel_id: u16) -> AMQPResult {
         self.0.lock().drop_pending(delivery_tag, true, channel_id)
     }
@@ -53,16 +53,12 @@ impl Acknowledgements {
         self.0.lock().drop_all(false);
     }
 
-    pub(crate) fn ack_all_before(&self, delivery_tag: DeliveryTag, channel_id: u16) -> AMQPResult {
-        self.0
-            .lock()
-            .complete_pending_before(delivery_tag, true, channel_id)
+    pub(crate) fn ack_all_before(&self, delivery_tag: DeliveryTag) {
+        self.0.lock().complete_pending_before(delivery_tag, true);
     }
 
-    pub(crate) fn nack_all_before(&self, delivery_tag: DeliveryTag, channel_id: u16) -> AMQPResult {
-        self.0
-            .lock()
-            .complete_pending_before(delivery_tag, false, channel_id)
+    pub(crate) fn nack_all_before(&self, delivery_tag: DeliveryTag) {
+        self.0.lock().complete_pending_before(delivery_tag, false);
     }
 
     pub(crate) fn on_channel_error(&self, channel_id: u16, error: Error) {
@@ -84,7 +80,7 @@ impl fmt::Debug for Acknowledgements {
 
 struct Inner {
     last: Option<(DeliveryTag, Promise<Confirmation>)>,
-    pending: HashMap<DeliveryTag, (u16, ConfirmationBroadcaster)>,
+    pending: BTreeMap<DeliveryTag, (u16, ConfirmationBroadcaster)>,
     returned_messages: ReturnedMessages,
 }
 
@@ -92,7 +88,7 @@ impl Inner {
     fn new(returned_messages: ReturnedMessages) -> Self {
         Self {
             last: None,
-            pending: HashMap::default(),
+            pending: BTreeMap::default(),
             returned_messages,
         }
     }
@@ -121,12 +117,7 @@ impl Inner {
     }
 
     fn drop_all(&mut self, success: bool) {
-        for (_, resolver) in self
-            .pending
-            .drain()
-            .map(|tup| tup.1)
-            .collect::<Vec<(u16, ConfirmationBroadcaster)>>()
-        {
+        for (_, (_, resolver)) in std::mem::take(&mut self.pending) {
             self.complete_pending(success, resolver);
         }
     }
@@ -141,38 +132,19 @@ impl Inner {
             self.complete_pending(success, resolver);
             Ok(())
         } else {
-            Err(AMQPError::new(
-                AMQPSoftError::PRECONDITIONFAILED.into(),
-                format!(
-                    "invalid {} recevied for inexistant delivery_tag {} on channel {}",
-                    if success { "ack" } else { "nack" },
-                    delivery_tag,
-                    channel_id
-                )
-                .into(),
-            ))
+            Err(invalid_delivery_tag(delivery_tag, success, channel_id))
         }
     }
 
-    fn complete_pending_before(
-        &mut self,
-        delivery_tag: DeliveryTag,
-        success: bool,
-        channel_id: u16,
-    ) -> AMQPResult {
-        let mut res = Ok(());
-        for tag in self
-            .pending
-            .keys()
-            .filter(|tag| **tag <= delivery_tag)
-            .cloned()
-            .collect::<HashSet<DeliveryTag>>()
-        {
-            if let Err(err) = self.drop_pending(tag, success, channel_id) {
-                res = Err(err);
-            }
+    fn complete_pending_before(&mut self, delivery_tag: DeliveryTag, success: bool) {
+        // The pending confirmations are sorted by delivery tag, split them in one go
+        let remaining = delivery_tag
+            .checked_add(1)
+            .map(|next| self.pending.split_off(&next))
+            .unwrap_or_default();
+        for (_, (_, resolver)) in std::mem::replace(&mut self.pending, remaining) {
+            self.complete_pending(success, resolver);
         }
-        res
     }
 
     fn on_channel_error(&mut self, channel_id: u16, error: Error) {
@@ -187,3 +159,56 @@ impl Inner {
             .retain(|_, (channel, _)| *channel != channel_id);
     }
 }
+
+fn invalid_delivery_tag(delivery_tag: DeliveryTag, success: bool, channel_id: u16) -> AMQPError {
+    AMQPError::new(
+        AMQPSoftError::PRECONDITIONFAILED.into(),
+        format!(
+            "invalid {} recevied for inexistant delivery_tag {} on channel {}",
+            if success { "ack" } else { "nack" },
+            delivery_tag,
+            channel_id
+        )
+        .into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiple_ack_completes_older_confirms() {
+        let acknowledgements = Acknowledgements::new(ReturnedMessages::default());
+        let first = acknowledgements.register_pending(1, 1);
+        let second = acknowledgements.register_pending(2, 1);
+        let _third = acknowledgements.register_pending(3, 1);
+        acknowledgements.ack_all_before(2);
+        assert_eq!(
+            acknowledgements.0.lock().pending.keys().collect::<Vec<_>>(),
+            vec![&3]
+        );
+        // Nothing left to nack before 2, which is fine
+        acknowledgements.nack_all_before(2);
+        assert_eq!(
+            futures_lite::future::block_on(first),
+            Ok(Confirmation::Ack(None))
+        );
+        assert_eq!(
+            futures_lite::future::block_on(second),
+            Ok(Confirmation::Ack(None))
+        );
+    }
+
+    #[test]
+    fn multiple_ack_up_to_the_last_tag() {
+        let acknowledgements = Acknowledgements::new(ReturnedMessages::default());
+        let confirm = acknowledgements.register_pending(DeliveryTag::MAX, 1);
+        acknowledgements.ack_all_before(DeliveryTag::MAX);
+        assert!(acknowledgements.0.lock().pending.is_empty());
+        assert_eq!(
+            futures_lite::future::block_on(confirm),
+            Ok(Confirmation::Ack(None))
+        );
+    }
+}
@@ -0,0 +1,8 @@
+use std::time::Duration;
+
+/// The delay before the given attempt, counting from 0: `initial` doubled after each attempt,
+/// capped to `max`
+pub(crate) fn exponential(initial: Duration, max: Duration, attempt: usize) -> Duration {
+    let factor = 1u32.checked_shl(attempt as u32).unwrap_or(u32::max_value());
+    initial.checked_mul(factor).unwrap_or(max).min(max)
+}
@@ -0,0 +1,25 @@
+/// What `basic_publish` should do while the server has blocked the connection, which happens
+/// when RabbitMQ raises a memory or disk alarm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockedPublishPolicy {
+    /// Keep queueing the messages, they'll be sent once the connection gets unblocked
+    Queue,
+    /// Wait for the connection to be unblocked before queueing the message
+    Wait,
+    /// Fail right away with [`Error::ConnectionBlocked`]
+    ///
+    /// [`Error::ConnectionBlocked`]: ./enum.Error.html#variant.ConnectionBlocked
+    Fail,
+    /// Queue up to that many bytes of payload, then fail with [`Error::ConnectionBlocked`]
+    ///
+    /// The payload of a message stops counting once it has been written to the socket.
+    ///
+    /// [`Error::ConnectionBlocked`]: ./enum.Error.html#variant.ConnectionBlocked
+    Buffer(usize),
+}
+
+impl Default for BlockedPublishPolicy {
+    fn default() -> Self {
+        Self::Queue
+    }
+}
@@ -0,0 +1,484 @@
+//! Synchronous facade over the async API, for code which isn't async.
+//!
+//! Every call blocks the current thread on the matching async method, so the behaviour is the
+//! same as awaiting it. Don't use it from within an async task, where it would block the
+//! executor.
+//! Connection recovery and topology recording are enabled through the `ConnectionProperties`,
+//! as with the async API.
+//!
+//! ```rust,no_run
+//! use lapin::{blocking::Connection, options::*, types::FieldTable, BasicProperties, ConnectionProperties};
+//!
+//! let conn = Connection::connect("amqp://127.0.0.1:5672/%2f", ConnectionProperties::default())
+//!     .expect("connection");
+//! let channel = conn.create_channel().expect("create_channel");
+//! channel
+//!     .queue_declare("hello", QueueDeclareOptions::default(), FieldTable::default())
+//!     .expect("queue_declare");
+//! let consumer = channel
+//!     .basic_consume("hello", "my_consumer", BasicConsumeOptions::default(), FieldTable::default())
+//!     .expect("basic_consume");
+//! channel
+//!     .basic_publish("", "hello", BasicPublishOptions::default(), b"Hello world!".to_vec(), BasicProperties::default())
+//!     .expect("basic_publish")
+//!     .wait()
+//!     .expect("publisher confirm");
+//! for delivery in consumer {
+//!     let (channel, delivery) = delivery.expect("error in consumer");
+//!     channel
+//!         .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+//!         .expect("ack");
+//! }
+//! ```
+
+use crate::{
+    message::{BasicGetMessage, BasicReturnMessage, Delivery},
+    options::*,
+    publisher_confirm::{self, Confirmation},
+    tcp::OwnedTLSConfig,
+    topology::{Topology, TopologyDrift},
+    types::{Boolean, FieldTable, LongLongUInt, LongUInt, ShortString, ShortUInt},
+    uri::AMQPUri,
+    BasicProperties, ChannelStatus, Configuration, ConnectionEvent, ConnectionProperties,
+    ConnectionStatus, ConsumerState, DeadLetter, Endpoints, Error, ExchangeKind, Queue, Result,
+    RetryPolicy,
+};
+use futures_lite::StreamExt;
+use std::future::Future;
+
+fn wait<F: Future>(future: F) -> F::Output {
+    async_global_executor::block_on(future)
+}
+
+/// Blocking counterpart of [`lapin::Connection`](../struct.Connection.html)
+#[derive(Debug)]
+pub struct Connection(crate::Connection);
+
+impl Connection {
+    /// Connect to an AMQP Server.
+    pub fn connect(uri: &str, options: ConnectionProperties) -> Result<Self> {
+        wait(crate::Connection::connect(uri, options)).map(Self)
+    }
+
+    /// Connect to an AMQP Server.
+    pub fn connect_with_config(
+        uri: &str,
+        options: ConnectionProperties,
+        config: OwnedTLSConfig,
+    ) -> Result<Self> {
+        wait(crate::Connection::connect_with_config(uri, options, config)).map(Self)
+    }
+
+    /// Connect to an AMQP Server.
+    pub fn connect_uri(uri: AMQPUri, options: ConnectionProperties) -> Result<Self> {
+        wait(crate::Connection::connect_uri(uri, options)).map(Self)
+    }
+
+    /// Connect to an AMQP Server.
+    pub fn connect_uri_with_config(
+        uri: AMQPUri,
+        options: ConnectionProperties,
+        config: OwnedTLSConfig,
+    ) -> Result<Self> {
+        wait(crate::Connection::connect_uri_with_config(
+            uri, options, config,
+        ))
+        .map(Self)
+    }
+
+    /// Connect to the first of these AMQP Servers accepting the connection
+    pub fn connect_endpoints(endpoints: Endpoints, options: ConnectionProperties) -> Result<Self> {
+        wait(crate::Connection::connect_endpoints(endpoints, options)).map(Self)
+    }
+
+    /// Connect to the first of these AMQP Servers accepting the connection
+    pub fn connect_endpoints_with_config(
+        endpoints: Endpoints,
+        options: ConnectionProperties,
+        config: OwnedTLSConfig,
+    ) -> Result<Self> {
+        wait(crate::Connection::connect_endpoints_with_config(
+            endpoints, options, config,
+        ))
+        .map(Self)
+    }
+
+    pub fn create_channel(&self) -> Result<Channel> {
+        wait(self.0.create_channel()).map(Channel)
+    }
+
+    /// Block current thread while the connection is still active.
+    pub fn run(self) -> Result<()> {
+        self.0.run()
+    }
+
+    pub fn on_error<E: FnMut(Error) + Send + 'static>(&self, handler: E) {
+        self.0.on_error(handler)
+    }
+
+    pub fn configuration(&self) -> &Configuration {
+        self.0.configuration()
+    }
+
+    pub fn status(&self) -> &ConnectionStatus {
+        self.0.status()
+    }
+
+    /// An iterator over the lifecycle events of this connection, starting from now
+    pub fn events(&self) -> ConnectionEvents {
+        ConnectionEvents(self.0.events())
+    }
+
+    /// Check that the exchanges and queues of this topology exist with the expected parameters
+    pub fn verify_topology(&self, topology: &Topology) -> Result<Vec<TopologyDrift>> {
+        wait(self.0.verify_topology(topology))
+    }
+
+    pub fn close(&self, reply_code: ShortUInt, reply_text: &str) -> Result<()> {
+        wait(self.0.close(reply_code, reply_text))
+    }
+
+    pub fn block(&self, reason: &str) -> Result<()> {
+        wait(self.0.block(reason))
+    }
+
+    pub fn unblock(&self) -> Result<()> {
+        wait(self.0.unblock())
+    }
+
+    pub fn update_secret(&self, new_secret: &str, reason: &str) -> Result<()> {
+        wait(self.0.update_secret(new_secret, reason))
+    }
+
+    /// The async connection this wraps
+    pub fn as_async(&self) -> &crate::Connection {
+        &self.0
+    }
+
+    pub fn into_async(self) -> crate::Connection {
+        self.0
+    }
+}
+
+impl From<crate::Connection> for Connection {
+    fn from(connection: crate::Connection) -> Self {
+        Self(connection)
+    }
+}
+
+/// Blocking counterpart of [`lapin::Channel`](../struct.Channel.html)
+#[derive(Clone, Debug)]
+pub struct Channel(crate::Channel);
+
+impl Channel {
+    pub fn id(&self) -> u16 {
+        self.0.id()
+    }
+
+    pub fn status(&self) -> &ChannelStatus {
+        self.0.status()
+    }
+
+    pub fn close(&self, reply_code: ShortUInt, reply_text: &str) -> Result<()> {
+        wait(self.0.close(reply_code, reply_text))
+    }
+
+    pub fn channel_flow(&self, options: ChannelFlowOptions) -> Result<Boolean> {
+        wait(self.0.channel_flow(options))
+    }
+
+    pub fn exchange_declare(
+        &self,
+        exchange: &str,
+        kind: ExchangeKind,
+        options: ExchangeDeclareOptions,
+        arguments: FieldTable,
+    ) -> Result<()> {
+        wait(self.0.exchange_declare(exchange, kind, options, arguments))
+    }
+
+    pub fn exchange_delete(&self, exchange: &str, options: ExchangeDeleteOptions) -> Result<()> {
+        wait(self.0.exchange_delete(exchange, options))
+    }
+
+    pub fn exchange_bind(
+        &self,
+        destination: &str,
+        source: &str,
+        routing_key: &str,
+        options: ExchangeBindOptions,
+        arguments: FieldTable,
+    ) -> Result<()> {
+        wait(
+            self.0
+                .exchange_bind(destination, source, routing_key, options, arguments),
+        )
+    }
+
+    pub fn exchange_unbind(
+        &self,
+        destination: &str,
+        source: &str,
+        routing_key: &str,
+        options: ExchangeUnbindOptions,
+        arguments: FieldTable,
+    ) -> Result<()> {
+        wait(
+            self.0
+                .exchange_unbind(destination, source, routing_key, options, arguments),
+        )
+    }
+
+    pub fn queue_declare(
+        &self,
+        queue: &str,
+        options: QueueDeclareOptions,
+        arguments: FieldTable,
+    ) -> Result<Queue> {
+        wait(self.0.queue_declare(queue, options, arguments))
+    }
+
+    pub fn queue_bind(
+        &self,
+        queue: &str,
+        exchange: &str,
+        routing_key: &str,
+        options: QueueBindOptions,
+        arguments: FieldTable,
+    ) -> Result<()> {
+        wait(
+            self.0
+                .queue_bind(queue, exchange, routing_key, options, arguments),
+        )
+    }
+
+    pub fn queue_unbind(
+        &self,
+        queue: &str,
+        exchange: &str,
+        routing_key: &str,
+        arguments: FieldTable,
+    ) -> Result<()> {
+        wait(self.0.queue_unbind(queue, exchange, routing_key, arguments))
+    }
+
+    pub fn queue_purge(&self, queue: &str, options: QueuePurgeOptions) -> Result<LongUInt> {
+        wait(self.0.queue_purge(queue, options))
+    }
+
+    pub fn queue_delete(&self, queue: &str, options: QueueDeleteOptions) -> Result<LongUInt> {
+        wait(self.0.queue_delete(queue, options))
+    }
+
+    pub fn basic_qos(&self, prefetch_count: ShortUInt, options: BasicQosOptions) -> Result<()> {
+        wait(self.0.basic_qos(prefetch_count, options))
+    }
+
+    pub fn basic_publish(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        options: BasicPublishOptions,
+        payload: Vec<u8>,
+        properties: BasicProperties,
+    ) -> Result<PublisherConfirm> {
+        wait(
+            self.0
+                .basic_publish(exchange, routing_key, options, payload, properties),
+        )
+        .map(PublisherConfirm)
+    }
+
+    /// Publish a message, retrying it as `policy` says until the server confirms it
+    pub fn basic_publish_with_retry(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        options: BasicPublishOptions,
+        payload: Vec<u8>,
+        properties: BasicProperties,
+        policy: &RetryPolicy,
+    ) -> Result<Confirmation> {
+        wait(self.0.basic_publish_with_retry(
+            exchange,
+            routing_key,
+            options,
+            payload,
+            properties,
+            policy,
+        ))
+    }
+
+    pub fn on_dead_letter<F: FnMut(DeadLetter) + Send + 'static>(&self, handler: F) {
+        self.0.on_dead_letter(handler)
+    }
+
+    /// Declare all the exchanges, queues and bindings of this topology
+    pub fn apply_topology(&self, topology: &Topology) -> Result<()> {
+        wait(self.0.apply_topology(topology))
+    }
+
+    /// Remove the bindings, queues and exchanges of this topology
+    pub fn delete_topology(&self, topology: &Topology) -> Result<()> {
+        wait(self.0.delete_topology(topology))
+    }
+
+    pub fn declared_topology(&self) -> Topology {
+        self.0.declared_topology()
+    }
+
+    pub fn basic_consume(
+        &self,
+        queue: &str,
+        consumer_tag: &str,
+        options: BasicConsumeOptions,
+        arguments: FieldTable,
+    ) -> Result<Consumer> {
+        wait(
+            self.0
+                .basic_consume(queue, consumer_tag, options, arguments),
+        )
+        .map(Consumer)
+    }
+
+    pub fn basic_cancel(&self, consumer_tag: &str, options: BasicCancelOptions) -> Result<()> {
+        wait(self.0.basic_cancel(consumer_tag, options))
+    }
+
+    pub fn basic_get(
+        &self,
+        queue: &str,
+        options: BasicGetOptions,
+    ) -> Result<Option<BasicGetMessage>> {
+        wait(self.0.basic_get(queue, options))
+    }
+
+    pub fn basic_ack(&self, delivery_tag: LongLongUInt, options: BasicAckOptions) -> Result<()> {
+        wait(self.0.basic_ack(delivery_tag, options))
+    }
+
+    pub fn basic_nack(&self, delivery_tag: LongLongUInt, options: BasicNackOptions) -> Result<()> {
+        wait(self.0.basic_nack(delivery_tag, options))
+    }
+
+    pub fn basic_reject(
+        &self,
+        delivery_tag: LongLongUInt,
+        options: BasicRejectOptions,
+    ) -> Result<()> {
+        wait(self.0.basic_reject(delivery_tag, options))
+    }
+
+    pub fn basic_recover(&self, options: BasicRecoverOptions) -> Result<()> {
+        wait(self.0.basic_recover(options))
+    }
+
+    pub fn basic_recover_async(&self, options: BasicRecoverAsyncOptions) -> Result<()> {
+        wait(self.0.basic_recover_async(options))
+    }
+
+    pub fn confirm_select(&self, options: ConfirmSelectOptions) -> Result<()> {
+        wait(self.0.confirm_select(options))
+    }
+
+    pub fn wait_for_confirms(&self) -> Result<Vec<BasicReturnMessage>> {
+        wait(self.0.wait_for_confirms())
+    }
+
+    pub fn tx_select(&self) -> Result<()> {
+        wait(self.0.tx_select())
+    }
+
+    pub fn tx_commit(&self) -> Result<()> {
+        wait(self.0.tx_commit())
+    }
+
+    pub fn tx_rollback(&self) -> Result<()> {
+        wait(self.0.tx_rollback())
+    }
+
+    /// The async channel this wraps
+    pub fn as_async(&self) -> &crate::Channel {
+        &self.0
+    }
+
+    pub fn into_async(self) -> crate::Channel {
+        self.0
+    }
+}
+
+impl From<crate::Channel> for Channel {
+    fn from(channel: crate::Channel) -> Self {
+        Self(channel)
+    }
+}
+
+/// Confirmation of a message published with [`Channel::basic_publish`]
+///
+/// As with the async one, dropping it without waiting hands it over to
+/// [`Channel::wait_for_confirms`].
+///
+/// [`Channel::basic_publish`]: ./struct.Channel.html#method.basic_publish
+/// [`Channel::wait_for_confirms`]: ./struct.Channel.html#method.wait_for_confirms
+#[derive(Debug)]
+pub struct PublisherConfirm(publisher_confirm::PublisherConfirm);
+
+impl PublisherConfirm {
+    /// Block until the server acked or nacked the message, if publisher confirms are enabled
+    pub fn wait(self) -> Result<Confirmation> {
+        wait(self.0)
+    }
+}
+
+/// Blocking counterpart of [`lapin::Consumer`](../struct.Consumer.html), iterating over the
+/// deliveries until the consumer gets canceled
+#[derive(Clone, Debug)]
+pub struct Consumer(crate::Consumer);
+
+impl Consumer {
+    pub fn tag(&self) -> ShortString {
+        self.0.tag()
+    }
+
+    pub fn state(&self) -> ConsumerState {
+        self.0.state()
+    }
+
+    pub fn into_async(self) -> crate::Consumer {
+        self.0
+    }
+}
+
+impl From<crate::Consumer> for Consumer {
+    fn from(consumer: crate::Consumer) -> Self {
+        Self(consumer)
+    }
+}
+
+impl Iterator for Consumer {
+    type Item = Result<(Channel, Delivery)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        wait(self.0.next())
+            .map(|delivery| delivery.map(|(channel, delivery)| (Channel(channel), delivery)))
+    }
+}
+
+/// Blocking counterpart of [`lapin::ConnectionEvents`](../struct.ConnectionEvents.html),
+/// iterating over the events of the connection
+#[derive(Debug)]
+pub struct ConnectionEvents(crate::ConnectionEvents);
+
+impl ConnectionEvents {
+    pub fn into_async(self) -> crate::ConnectionEvents {
+        self.0
+    }
+}
+
+impl Iterator for ConnectionEvents {
+    type Item = ConnectionEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        wait(self.0.next())
+    }
+}
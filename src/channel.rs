@@ -1,10 +1,10 @@
 use crate::{
     acknowledgement::{Acknowledgements, DeliveryTag},
-    auth::Credentials,
     channel_closer::ChannelCloser,
     channel_status::{ChannelState, ChannelStatus},
     connection_closer::ConnectionCloser,
-    connection_status::{ConnectionState, ConnectionStep},
+    connection_events::ConnectionEvent,
+    connection_status::{BlockedPublish, ConnectionState, ConnectionStep},
     consumer::Consumer,
     executor::Executor,
     frames::{ExpectedReply, Frames},
@@ -12,18 +12,25 @@ use crate::{
     internal_rpc::InternalRPCHandle,
     message::{BasicGetMessage, BasicReturnMessage, Delivery},
     protocol::{self, AMQPClass, AMQPError, AMQPHardError},
-    publisher_confirm::PublisherConfirm,
+    publish_retry::{self, DeadLetter, DeadLetterHandler, RetryPolicy},
+    publisher_confirm::{Confirmation, PublisherConfirm},
     queue::Queue,
     queues::Queues,
     returned_messages::ReturnedMessages,
+    sasl::SaslExchange,
     socket_state::SocketStateHandle,
+    topology::{DestinationType, Topology},
+    topology_registry::{
+        BindingRecord, ConsumerRecord, ExchangeBindingRecord, ExchangeRecord, QueueRecord,
+        TopologyRegistry,
+    },
     types::*,
     BasicProperties, Configuration, Connection, ConnectionStatus, Error, ExchangeKind, Promise,
     PromiseResolver, Result,
 };
 use amq_protocol::frame::{AMQPContentHeader, AMQPFrame};
-use std::{convert::TryFrom, fmt, sync::Arc};
-use tracing::{error, info, level_enabled, trace, Level};
+use std::{collections::HashMap, convert::TryFrom, fmt, sync::Arc};
+use tracing::{debug, error, info, level_enabled, trace, Level};
 
 #[cfg(test)]
 use crate::queue::QueueState;
@@ -51,6 +58,8 @@ pub struct Channel {
     internal_rpc: InternalRPCHandle,
     frames: Frames,
     executor: Arc<dyn Executor>,
+    topology: TopologyRegistry,
+    dead_letter_handler: DeadLetterHandler,
     channel_closer: Option<Arc<ChannelCloser>>,
     connection_closer: Option<Arc<ConnectionCloser>>,
 }
@@ -74,6 +83,7 @@ impl fmt::Debug for Channel {
             .field("returned_messages", &self.returned_messages)
             .field("frames", &self.frames)
             .field("executor", &self.executor)
+            .field("topology", &self.topology)
             .finish()
     }
 }
@@ -88,6 +98,7 @@ impl Channel {
         internal_rpc: InternalRPCHandle,
         frames: Frames,
         executor: Arc<dyn Executor>,
+        topology: TopologyRegistry,
         connection_closer: Option<Arc<ConnectionCloser>>,
     ) -> Channel {
         let returned_messages = ReturnedMessages::default();
@@ -114,6 +125,8 @@ impl Channel {
             internal_rpc,
             frames,
             executor,
+            topology,
+            dead_letter_handler: DeadLetterHandler::default(),
             channel_closer,
             connection_closer,
         }
@@ -123,6 +136,10 @@ impl Channel {
         &self.status
     }
 
+    pub(crate) fn executor(&self) -> &Arc<dyn Executor> {
+        &self.executor
+    }
+
     fn set_closed(&self, error: Error) {
         self.set_state(ChannelState::Closed);
         self.error_publisher_confirms(error.clone());
@@ -137,6 +154,10 @@ impl Channel {
         self.internal_rpc.remove_channel(self.id, error);
     }
 
+    pub(crate) fn has_pending_confirms(&self) -> bool {
+        self.acknowledgements.has_pending()
+    }
+
     pub(crate) fn error_publisher_confirms(&self, error: Error) {
         self.acknowledgements.on_channel_error(self.id, error);
     }
@@ -153,6 +174,14 @@ impl Channel {
         self.status.set_state(state);
     }
 
+    pub(crate) fn set_reconnecting(&self, error: Error) {
+        self.status.set_reconnecting();
+        self.error_publisher_confirms(error);
+        self.delivery_tag.reset();
+        // Deliveries received before the disconnection cannot be acked anymore
+        self.queues.drop_prefetched_messages();
+    }
+
     pub fn id(&self) -> u16 {
         self.id
     }
@@ -171,6 +200,8 @@ impl Channel {
             internal_rpc: self.internal_rpc.clone(),
             frames: self.frames.clone(),
             executor: self.executor.clone(),
+            topology: self.topology.clone(),
+            dead_letter_handler: self.dead_letter_handler.clone(),
             channel_closer: None,
             connection_closer: self.connection_closer.clone(),
         }
@@ -214,8 +245,351 @@ impl Channel {
         options: ExchangeDeclareOptions,
         arguments: FieldTable,
     ) -> Result<()> {
-        self.do_exchange_declare(exchange, kind.kind(), options, arguments)
-            .await
+        self.do_exchange_declare(exchange, kind.kind(), options, arguments.clone())
+            .await?;
+        self.topology.register_exchange(ExchangeRecord {
+            name: exchange.into(),
+            kind,
+            options,
+            arguments,
+        });
+        Ok(())
+    }
+
+    /// Delete an exchange
+    pub async fn exchange_delete(
+        &self,
+        exchange: &str,
+        options: ExchangeDeleteOptions,
+    ) -> Result<()> {
+        self.do_exchange_delete(exchange, options).await?;
+        self.topology.deregister_exchange(exchange);
+        Ok(())
+    }
+
+    pub async fn queue_declare(
+        &self,
+        queue: &str,
+        options: QueueDeclareOptions,
+        arguments: FieldTable,
+    ) -> Result<Queue> {
+        let declared = self
+            .do_queue_declare(queue, options, arguments.clone())
+            .await?;
+        self.topology.register_queue(QueueRecord {
+            name: declared.name().clone(),
+            server_named: queue.is_empty(),
+            options,
+            arguments,
+        });
+        Ok(declared)
+    }
+
+    pub async fn queue_bind(
+        &self,
+        queue: &str,
+        exchange: &str,
+        routing_key: &str,
+        options: QueueBindOptions,
+        arguments: FieldTable,
+    ) -> Result<()> {
+        self.do_queue_bind(queue, exchange, routing_key, options, arguments.clone())
+            .await?;
+        self.topology.register_binding(BindingRecord {
+            queue: queue.into(),
+            exchange: exchange.into(),
+            routing_key: routing_key.into(),
+            options,
+            arguments,
+        });
+        Ok(())
+    }
+
+    pub async fn queue_unbind(
+        &self,
+        queue: &str,
+        exchange: &str,
+        routing_key: &str,
+        arguments: FieldTable,
+    ) -> Result<()> {
+        self.do_queue_unbind(queue, exchange, routing_key, arguments.clone())
+            .await?;
+        self.topology
+            .deregister_binding(queue, exchange, routing_key, &arguments);
+        Ok(())
+    }
+
+    pub async fn exchange_bind(
+        &self,
+        destination: &str,
+        source: &str,
+        routing_key: &str,
+        options: ExchangeBindOptions,
+        arguments: FieldTable,
+    ) -> Result<()> {
+        self.do_exchange_bind(destination, source, routing_key, options, arguments.clone())
+            .await?;
+        self.topology
+            .register_exchange_binding(ExchangeBindingRecord {
+                destination: destination.into(),
+                source: source.into(),
+                routing_key: routing_key.into(),
+                options,
+                arguments,
+            });
+        Ok(())
+    }
+
+    pub async fn exchange_unbind(
+        &self,
+        destination: &str,
+        source: &str,
+        routing_key: &str,
+        options: ExchangeUnbindOptions,
+        arguments: FieldTable,
+    ) -> Result<()> {
+        self.do_exchange_unbind(destination, source, routing_key, options, arguments.clone())
+            .await?;
+        self.topology
+            .deregister_exchange_binding(destination, source, routing_key, &arguments);
+        Ok(())
+    }
+
+    pub async fn basic_publish(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        options: BasicPublishOptions,
+        payload: Vec<u8>,
+        properties: BasicProperties,
+    ) -> Result<PublisherConfirm> {
+        let buffered = loop {
+            match self
+                .connection_status
+                .check_blocked_publish(payload.len())?
+            {
+                BlockedPublish::Send => break None,
+                BlockedPublish::Buffer(buffered) => break Some(buffered),
+                BlockedPublish::Wait(unblocked) => {
+                    trace!(channel=%self.id, "connection is blocked, waiting before publishing");
+                    unblocked.await?;
+                }
+            }
+        };
+        let confirm = self
+            .do_basic_publish(exchange, routing_key, options, payload, properties)
+            .await;
+        // The frames have been written, they no longer take room in the buffer
+        drop(buffered);
+        confirm
+    }
+
+    /// Publish a message and wait for its confirmation, republishing it according to `policy`
+    /// when it gets nacked, returned or lost because the connection went down.
+    ///
+    /// Attempts lost to a connection recovery don't count against `policy.max_retries`, the
+    /// message is republished once the channel has been reopened.
+    /// Messages we give up on are handed to the callback registered with [`on_dead_letter`],
+    /// and the outcome of their last attempt is returned.
+    ///
+    /// Nacks and returned messages are only reported with publisher confirms enabled, and
+    /// returned messages only if `options.mandatory` is set.
+    ///
+    /// [`on_dead_letter`]: #method.on_dead_letter
+    pub async fn basic_publish_with_retry(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        options: BasicPublishOptions,
+        payload: Vec<u8>,
+        properties: BasicProperties,
+        policy: &RetryPolicy,
+    ) -> Result<Confirmation> {
+        let mut attempts = 0;
+        let mut retries = 0;
+        loop {
+            if let Some(opened) = self.status.wait_for_open() {
+                trace!(channel=%self.id, "waiting for the channel to be reopened before publishing");
+                opened.await?;
+                continue;
+            }
+            attempts += 1;
+            let outcome = match self
+                .basic_publish(
+                    exchange,
+                    routing_key,
+                    options,
+                    payload.clone(),
+                    properties.clone(),
+                )
+                .await
+            {
+                Ok(confirm) => confirm.await,
+                Err(err) => Err(err),
+            };
+            if publish_retry::delivered(&outcome) {
+                return outcome;
+            }
+            let state = self.status.state();
+            if state != ChannelState::Reconnecting {
+                retries += 1;
+            }
+            if [
+                ChannelState::Closing,
+                ChannelState::Closed,
+                ChannelState::Error,
+            ]
+            .contains(&state)
+                || retries > policy.max_retries
+            {
+                error!(channel=%self.id, %attempts, "giving up on publishing message");
+                self.dead_letter_handler.on_dead_letter(DeadLetter {
+                    exchange: exchange.into(),
+                    routing_key: routing_key.into(),
+                    options,
+                    payload,
+                    properties,
+                    outcome: outcome.clone(),
+                    attempts,
+                });
+                return outcome;
+            }
+            trace!(channel=%self.id, %attempts, "republishing message");
+            self.connection_status
+                .sleep(policy.delay(retries.saturating_sub(1)))
+                .await;
+        }
+    }
+
+    /// Register a callback for the messages [`basic_publish_with_retry`] gave up on
+    ///
+    /// [`basic_publish_with_retry`]: #method.basic_publish_with_retry
+    pub fn on_dead_letter<F: FnMut(DeadLetter) + Send + 'static>(&self, handler: F) {
+        self.dead_letter_handler.set_handler(handler);
+    }
+
+    /// Declare all the exchanges, queues and bindings of this topology
+    ///
+    /// Exchanges are declared first, alternate exchanges before the exchanges referencing
+    /// them, then queues and finally bindings. Declaring what already exists with the same
+    /// parameters is a no-op, so this can safely be called on every start-up.
+    pub async fn apply_topology(&self, topology: &Topology) -> Result<()> {
+        for exchange in topology.ordered_exchanges() {
+            debug!(channel=%self.id(), exchange=%exchange.name, "declaring exchange");
+            self.exchange_declare(
+                &exchange.name,
+                exchange.kind.clone(),
+                exchange.options(false),
+                exchange.arguments.clone(),
+            )
+            .await?;
+        }
+        for queue in &topology.queues {
+            debug!(channel=%self.id(), queue=%queue.name, "declaring queue");
+            self.queue_declare(&queue.name, queue.options(false), queue.arguments.clone())
+                .await?;
+        }
+        for binding in &topology.bindings {
+            match binding.destination_type {
+                DestinationType::Queue => {
+                    self.queue_bind(
+                        &binding.destination,
+                        &binding.source,
+                        &binding.routing_key,
+                        QueueBindOptions::default(),
+                        binding.arguments.clone(),
+                    )
+                    .await?
+                }
+                DestinationType::Exchange => {
+                    self.exchange_bind(
+                        &binding.destination,
+                        &binding.source,
+                        &binding.routing_key,
+                        ExchangeBindOptions::default(),
+                        binding.arguments.clone(),
+                    )
+                    .await?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the bindings, queues and exchanges of this topology, in the reverse order of
+    /// [`apply_topology`]
+    ///
+    /// [`apply_topology`]: #method.apply_topology
+    pub async fn delete_topology(&self, topology: &Topology) -> Result<()> {
+        for binding in topology.bindings.iter().rev() {
+            match binding.destination_type {
+                DestinationType::Queue => {
+                    self.queue_unbind(
+                        &binding.destination,
+                        &binding.source,
+                        &binding.routing_key,
+                        binding.arguments.clone(),
+                    )
+                    .await?
+                }
+                DestinationType::Exchange => {
+                    self.exchange_unbind(
+                        &binding.destination,
+                        &binding.source,
+                        &binding.routing_key,
+                        ExchangeUnbindOptions::default(),
+                        binding.arguments.clone(),
+                    )
+                    .await?
+                }
+            }
+        }
+        for queue in topology.queues.iter().rev() {
+            self.queue_delete(&queue.name, QueueDeleteOptions::default())
+                .await?;
+        }
+        for exchange in topology.ordered_exchanges().into_iter().rev() {
+            self.exchange_delete(&exchange.name, ExchangeDeleteOptions::default())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// What has been declared on this channel, server named queues excepted
+    ///
+    /// Declarations are only recorded with connection recovery or
+    /// `ConnectionProperties::with_topology_recording`.
+    pub fn declared_topology(&self) -> Topology {
+        Topology::from_registry(&self.topology)
+    }
+
+    pub async fn basic_qos(
+        &self,
+        prefetch_count: ShortUInt,
+        options: BasicQosOptions,
+    ) -> Result<()> {
+        self.do_basic_qos(prefetch_count, options).await?;
+        self.topology.set_qos(prefetch_count, options);
+        Ok(())
+    }
+
+    pub async fn basic_consume(
+        &self,
+        queue: &str,
+        consumer_tag: &str,
+        options: BasicConsumeOptions,
+        arguments: FieldTable,
+    ) -> Result<Consumer> {
+        let consumer = self
+            .do_basic_consume(queue, consumer_tag, options, arguments.clone())
+            .await?;
+        self.topology.register_consumer(ConsumerRecord {
+            consumer_tag: consumer.tag(),
+            queue: queue.into(),
+            options,
+            arguments,
+        });
+        Ok(consumer)
     }
 
     pub async fn wait_for_confirms(&self) -> Result<Vec<BasicReturnMessage>> {
@@ -233,6 +607,92 @@ impl Channel {
         Ok(self.returned_messages.drain())
     }
 
+    /// Open the channel again after a reconnection, with its previous prefetch and confirm mode
+    pub(crate) async fn reopen(&self) -> Result<()> {
+        self.set_state(ChannelState::Initial);
+        self.channel_open(self.clone()).await?;
+        if let Some((prefetch_count, options)) = self.topology.qos() {
+            self.do_basic_qos(prefetch_count, options).await?;
+        }
+        if self.topology.confirm() {
+            self.confirm_select(ConfirmSelectOptions::default()).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn recover_exchanges(&self) -> Result<()> {
+        for exchange in self.topology.exchanges() {
+            self.do_exchange_declare(
+                exchange.name.as_str(),
+                exchange.kind.kind(),
+                exchange.options,
+                exchange.arguments,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the new names of the server named queues
+    pub(crate) async fn recover_queues(&self) -> Result<HashMap<ShortString, ShortString>> {
+        let mut renames = HashMap::default();
+        for queue in self.topology.queues() {
+            let name = if queue.server_named {
+                ""
+            } else {
+                queue.name.as_str()
+            };
+            let declared = self
+                .do_queue_declare(name, queue.options, queue.arguments)
+                .await?;
+            if declared.name() != &queue.name {
+                renames.insert(queue.name, declared.name().clone());
+            }
+        }
+        Ok(renames)
+    }
+
+    pub(crate) fn rename_queues(&self, renames: &HashMap<ShortString, ShortString>) {
+        self.topology.rename_queues(renames);
+    }
+
+    pub(crate) async fn recover_bindings(&self) -> Result<()> {
+        for binding in self.topology.bindings() {
+            self.do_queue_bind(
+                binding.queue.as_str(),
+                binding.exchange.as_str(),
+                binding.routing_key.as_str(),
+                binding.options,
+                binding.arguments,
+            )
+            .await?;
+        }
+        for binding in self.topology.exchange_bindings() {
+            self.do_exchange_bind(
+                binding.destination.as_str(),
+                binding.source.as_str(),
+                binding.routing_key.as_str(),
+                binding.options,
+                binding.arguments,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn recover_consumers(&self) -> Result<()> {
+        for consumer in self.topology.consumers() {
+            self.do_basic_consume(
+                consumer.queue.as_str(),
+                consumer.consumer_tag.as_str(),
+                consumer.options,
+                consumer.arguments,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
     #[cfg(test)]
     pub(crate) fn register_queue(&self, queue: QueueState) {
         self.queues.register(queue);
@@ -406,10 +866,10 @@ impl Channel {
         &self,
         resolver: PromiseResolver<Connection>,
         connection: Connection,
-        credentials: Credentials,
+        sasl: Box<dyn SaslExchange>,
     ) {
         self.connection_status
-            .set_connection_step(ConnectionStep::StartOk(resolver, connection, credentials));
+            .set_connection_step(ConnectionStep::StartOk(resolver, connection, sasl));
     }
 
     fn on_connection_open_sent(&self, resolver: PromiseResolver<Connection>) {
@@ -491,7 +951,7 @@ impl Channel {
         trace!(?method, "Server sent connection::Start");
         let state = self.connection_status.state();
         if let (
-            ConnectionState::Connecting,
+            true,
             Some(ConnectionStep::ProtocolHeader(
                 resolver,
                 connection,
@@ -499,9 +959,11 @@ impl Channel {
                 mechanism,
                 mut options,
             )),
-        ) = (state.clone(), self.connection_status.connection_step())
-        {
-            let mechanism_str = mechanism.to_string();
+        ) = (
+            self.connection_status.connecting(),
+            self.connection_status.connection_step(),
+        ) {
+            let mechanism_str = mechanism.name().to_string();
             let locale = options.locale.clone();
 
             if !method
@@ -509,7 +971,7 @@ impl Channel {
                 .split_whitespace()
                 .any(|m| m == mechanism_str)
             {
-                error!(%mechanism, "unsupported mechanism");
+                error!(mechanism=%mechanism_str, "unsupported mechanism");
             }
             if !method.locales.split_whitespace().any(|l| l == locale) {
                 error!(%locale, "unsupported locale");
@@ -547,17 +1009,26 @@ impl Channel {
                 .client_properties
                 .insert("capabilities".into(), AMQPValue::FieldTable(capabilities));
 
+            let mut sasl = mechanism.start(&credentials);
+            let response = match sasl.initial_response() {
+                Ok(response) => response,
+                Err(err) => {
+                    resolver.swear(Err(err.clone()));
+                    self.internal_rpc.set_connection_error(err.clone());
+                    return Err(err);
+                }
+            };
             let channel = self.clone();
             self.internal_rpc.register_internal_future(async move {
                 channel
                     .connection_start_ok(
                         options.client_properties,
                         &mechanism_str,
-                        &credentials.sasl_auth_string(mechanism),
+                        &response,
                         &locale,
                         resolver,
                         connection,
-                        credentials,
+                        sasl,
                     )
                     .await
             });
@@ -574,16 +1045,28 @@ impl Channel {
         trace!(?method, "Server sent connection::Secure");
 
         let state = self.connection_status.state();
-        if let (ConnectionState::Connecting, Some(ConnectionStep::StartOk(.., credentials))) =
-            (state.clone(), self.connection_status.connection_step())
-        {
-            let channel = self.clone();
-            self.internal_rpc.register_internal_future(async move {
-                channel
-                    .connection_secure_ok(&credentials.rabbit_cr_demo_answer())
-                    .await
-            });
-            Ok(())
+        if let (true, Some(ConnectionStep::StartOk(resolver, connection, mut sasl))) = (
+            self.connection_status.connecting(),
+            self.connection_status.connection_step(),
+        ) {
+            match sasl.respond(method.challenge.as_str()) {
+                Ok(response) => {
+                    // The server may send several challenges before tuning the connection
+                    self.connection_status
+                        .set_connection_step(ConnectionStep::StartOk(resolver, connection, sasl));
+                    let channel = self.clone();
+                    self.internal_rpc.register_internal_future(async move {
+                        channel.connection_secure_ok(&response).await
+                    });
+                    Ok(())
+                }
+                Err(err) => {
+                    error!(%err, "Failed to answer the server challenge");
+                    resolver.swear(Err(err.clone()));
+                    self.internal_rpc.set_connection_error(err.clone());
+                    Err(err)
+                }
+            }
         } else {
             error!(?state, "Invalid state");
             let error = Error::InvalidConnectionState(state);
@@ -596,11 +1079,20 @@ impl Channel {
         trace!(?method, "Server sent Connection::Tune");
 
         let state = self.connection_status.state();
-        if let (
-            ConnectionState::Connecting,
-            Some(ConnectionStep::StartOk(resolver, connection, _)),
-        ) = (state.clone(), self.connection_status.connection_step())
-        {
+        if let (true, Some(ConnectionStep::StartOk(resolver, connection, sasl))) = (
+            self.connection_status.connecting(),
+            self.connection_status.connection_step(),
+        ) {
+            if !sasl.completed() {
+                let error = Error::AuthenticationError(
+                    "the server tuned the connection before completing the SASL exchange".into(),
+                );
+                error!(%error);
+                resolver.swear(Err(error.clone()));
+                self.internal_rpc.set_connection_error(error.clone());
+                return Err(error);
+            }
+
             self.tune_connection_configuration(
                 method.channel_max,
                 method.frame_max,
@@ -638,10 +1130,13 @@ impl Channel {
         connection: Connection,
     ) -> Result<()> {
         let state = self.connection_status.state();
-        if let (ConnectionState::Connecting, Some(ConnectionStep::Open(resolver))) =
-            (state.clone(), self.connection_status.connection_step())
-        {
+        if let (true, Some(ConnectionStep::Open(resolver))) = (
+            self.connection_status.connecting(),
+            self.connection_status.connection_step(),
+        ) {
             self.connection_status.set_state(ConnectionState::Connected);
+            self.connection_status
+                .emit(ConnectionEvent::Connected(self.configuration.clone()));
             resolver.swear(Ok(connection));
             Ok(())
         } else {
@@ -668,6 +1163,11 @@ impl Channel {
                 info!(channel=%self.id, ?method, "Connection closed");
                 Error::InvalidConnectionState(ConnectionState::Closed)
             });
+        self.connection_status
+            .emit(ConnectionEvent::ClosedByServer {
+                reply_code: method.reply_code,
+                reply_text: method.reply_text.to_string(),
+            });
         self.internal_rpc.set_connection_closing();
         self.frames.drop_pending(error.clone());
         if let Some(resolver) = self.connection_status.connection_resolver() {
@@ -677,8 +1177,10 @@ impl Channel {
         Ok(())
     }
 
-    fn on_connection_blocked_received(&self, _method: protocol::connection::Blocked) -> Result<()> {
+    fn on_connection_blocked_received(&self, method: protocol::connection::Blocked) -> Result<()> {
         self.connection_status.block();
+        self.connection_status
+            .emit(ConnectionEvent::Blocked(method.reason.to_string()));
         Ok(())
     }
 
@@ -687,6 +1189,7 @@ impl Channel {
         _method: protocol::connection::Unblocked,
     ) -> Result<()> {
         self.connection_status.unblock();
+        self.connection_status.emit(ConnectionEvent::Unblocked);
         self.wake();
         Ok(())
     }
@@ -764,6 +1267,7 @@ impl Channel {
         queue: ShortString,
     ) -> Result<()> {
         self.queues.deregister(queue.as_str());
+        self.topology.deregister_queue(queue.as_str());
         resolver.swear(Ok(method.message_count));
         Ok(())
     }
@@ -831,6 +1335,11 @@ impl Channel {
         channel_closer: Option<Arc<ChannelCloser>>,
         queue: ShortString,
     ) -> Result<()> {
+        if let Some(consumer) = self.queues.get_consumer(method.consumer_tag.as_str()) {
+            // We're resubscribing an existing consumer after a reconnection
+            resolver.swear(Ok(consumer));
+            return Ok(());
+        }
         let consumer = Consumer::new(
             method.consumer_tag.clone(),
             self.executor.clone(),
@@ -863,6 +1372,8 @@ impl Channel {
     fn on_basic_cancel_received(&self, method: protocol::basic::Cancel) -> Result<()> {
         self.queues
             .deregister_consumer(method.consumer_tag.as_str());
+        self.topology
+            .deregister_consumer(method.consumer_tag.as_str());
         if !method.nowait {
             let channel = self.clone();
             self.internal_rpc.register_internal_future(async move {
@@ -875,6 +1386,8 @@ impl Channel {
     fn on_basic_cancel_ok_received(&self, method: protocol::basic::CancelOk) -> Result<()> {
         self.queues
             .deregister_consumer(method.consumer_tag.as_str());
+        self.topology
+            .deregister_consumer(method.consumer_tag.as_str());
         Ok(())
     }
 
@@ -882,15 +1395,7 @@ impl Channel {
         if self.status.confirm() {
             if method.multiple {
                 if method.delivery_tag > 0 {
-                    self.acknowledgements
-                        .ack_all_before(method.delivery_tag, self.id)
-                        .or_else(|err| {
-                            self.acknowledgement_error(
-                                err,
-                                method.get_amqp_class_id(),
-                                method.get_amqp_method_id(),
-                            )
-                        })?;
+                    self.acknowledgements.ack_all_before(method.delivery_tag);
                 } else {
                     self.acknowledgements.ack_all_pending();
                 }
@@ -913,15 +1418,7 @@ impl Channel {
         if self.status.confirm() {
             if method.multiple {
                 if method.delivery_tag > 0 {
-                    self.acknowledgements
-                        .nack_all_before(method.delivery_tag, self.id)
-                        .or_else(|err| {
-                            self.acknowledgement_error(
-                                err,
-                                method.get_amqp_class_id(),
-                                method.get_amqp_method_id(),
-                            )
-                        })?;
+                    self.acknowledgements.nack_all_before(method.delivery_tag);
                 } else {
                     self.acknowledgements.nack_all_pending();
                 }
@@ -958,8 +1455,14 @@ impl Channel {
         Ok(())
     }
 
+    fn on_tx_select_ok_received(&self) -> Result<()> {
+        self.status.set_transaction();
+        Ok(())
+    }
+
     fn on_confirm_select_ok_received(&self) -> Result<()> {
         self.status.set_confirm();
+        self.topology.set_confirm();
         Ok(())
     }
 
@@ -0,0 +1,215 @@
+use crate::{
+    connection::WeakConnection,
+    options::{BasicQosOptions, ConfirmSelectOptions},
+    types::ShortUInt,
+    Channel, ConnectionState, Error, Result,
+};
+use flume::{Receiver, Sender};
+use parking_lot::Mutex;
+use std::{fmt, ops::Deref, sync::Arc};
+use tracing::trace;
+
+/// Configuration of a [`ChannelPool`]
+///
+/// [`ChannelPool`]: ./struct.ChannelPool.html
+#[derive(Clone, Debug)]
+pub struct ChannelPoolConfig {
+    /// Maximum number of channels leased at the same time, capped to the negotiated
+    /// `channel_max` of the connection
+    pub max_size: usize,
+    /// Put the channels in confirm mode when opening them
+    pub confirm: bool,
+    /// Set this prefetch count on the channels when opening them
+    pub prefetch_count: Option<ShortUInt>,
+}
+
+impl Default for ChannelPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 16,
+            confirm: false,
+            prefetch_count: None,
+        }
+    }
+}
+
+/// A pool of channels, created with [`Connection::channel_pool`]
+///
+/// [`get`] hands out a channel nobody else uses until the returned [`PooledChannel`] gets
+/// dropped, waiting for one to be given back when `max_size` of them are already leased.
+/// Channels which aren't connected anymore, because they got closed by the server or the
+/// connection went down, are discarded instead of being reused, as are the ones left in tx
+/// mode, in another confirm mode than the pool's or with unconfirmed publishes, so that a
+/// lease never inherits the transaction or the confirmations of the previous one. Dropping
+/// them closes them.
+///
+/// [`Connection::channel_pool`]: ./struct.Connection.html#method.channel_pool
+/// [`get`]: #method.get
+/// [`PooledChannel`]: ./struct.PooledChannel.html
+#[derive(Clone)]
+pub struct ChannelPool {
+    inner: Arc<Inner>,
+}
+
+/// A channel leased from a [`ChannelPool`], given back to it on drop
+///
+/// [`ChannelPool`]: ./struct.ChannelPool.html
+pub struct PooledChannel {
+    channel: Option<Channel>,
+    permit: Permit,
+}
+
+/// A slot in the pool, freed on drop
+struct Permit(Arc<Inner>);
+
+struct Inner {
+    connection: WeakConnection,
+    config: ChannelPoolConfig,
+    max_size: usize,
+    idle: Mutex<Vec<Channel>>,
+    permits: (Sender<()>, Receiver<()>),
+}
+
+impl ChannelPool {
+    pub(crate) fn new(
+        connection: WeakConnection,
+        config: ChannelPoolConfig,
+        channel_max: u16,
+    ) -> Self {
+        let max_size = config.max_size.min(channel_max.into()).max(1);
+        let permits = flume::bounded(max_size);
+        for _ in 0..max_size {
+            let _ = permits.0.send(());
+        }
+        Self {
+            inner: Arc::new(Inner {
+                connection,
+                config,
+                max_size,
+                idle: Mutex::new(Vec::new()),
+                permits,
+            }),
+        }
+    }
+
+    /// Lease a channel, reusing an idle one if possible
+    pub async fn get(&self) -> Result<PooledChannel> {
+        self.inner
+            .permits
+            .1
+            .recv_async()
+            .await
+            .expect("channel pool permits can't be disconnected");
+        let permit = Permit(self.inner.clone());
+        let channel = match self.inner.take_idle() {
+            Some(channel) => channel,
+            None => self.inner.open().await?,
+        };
+        Ok(PooledChannel {
+            channel: Some(channel),
+            permit,
+        })
+    }
+
+    /// Maximum number of channels leased at the same time
+    pub fn max_size(&self) -> usize {
+        self.inner.max_size
+    }
+
+    /// Number of channels waiting to be leased again
+    pub fn idle(&self) -> usize {
+        self.inner.idle.lock().len()
+    }
+}
+
+impl Inner {
+    fn take_idle(&self) -> Option<Channel> {
+        let mut idle = self.idle.lock();
+        while let Some(channel) = idle.pop() {
+            if channel.status().connected() {
+                return Some(channel);
+            }
+            trace!(channel=%channel.id(), state=?channel.status().state(), "discarding pooled channel");
+        }
+        None
+    }
+
+    async fn open(&self) -> Result<Channel> {
+        let connection = self
+            .connection
+            .upgrade()
+            .ok_or(Error::InvalidConnectionState(ConnectionState::Closed))?;
+        let channel = connection.create_channel().await?;
+        if self.config.confirm {
+            channel
+                .confirm_select(ConfirmSelectOptions::default())
+                .await?;
+        }
+        if let Some(prefetch_count) = self.config.prefetch_count {
+            channel
+                .basic_qos(prefetch_count, BasicQosOptions::default())
+                .await?;
+        }
+        trace!(channel=%channel.id(), "opened pooled channel");
+        Ok(channel)
+    }
+
+    fn give_back(&self, channel: Channel) {
+        let status = channel.status();
+        if !status.connected() {
+            trace!(channel=%channel.id(), state=?status.state(), "discarding pooled channel");
+        } else if status.transaction() || status.confirm() != self.config.confirm {
+            // Neither tx nor confirm mode can be turned off, the next lease would inherit it
+            trace!(channel=%channel.id(), transaction=%status.transaction(), confirm=%status.confirm(), "discarding pooled channel left in another mode");
+        } else if channel.has_pending_confirms() {
+            trace!(channel=%channel.id(), "discarding pooled channel with unconfirmed publishes");
+        } else {
+            self.idle.lock().push(channel);
+        }
+    }
+}
+
+impl PooledChannel {
+    /// Take the channel out of the pool for good, freeing its slot
+    pub fn detach(mut self) -> Channel {
+        self.channel.take().expect("pooled channel already taken")
+    }
+}
+
+impl Deref for PooledChannel {
+    type Target = Channel;
+
+    fn deref(&self) -> &Channel {
+        self.channel.as_ref().expect("pooled channel already taken")
+    }
+}
+
+impl Drop for PooledChannel {
+    fn drop(&mut self) {
+        if let Some(channel) = self.channel.take() {
+            self.permit.0.give_back(channel);
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let _ = self.0.permits.0.send(());
+    }
+}
+
+impl fmt::Debug for ChannelPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChannelPool")
+            .field("config", &self.inner.config)
+            .field("max_size", &self.inner.max_size)
+            .field("idle", &self.idle())
+            .finish()
+    }
+}
+
+impl fmt::Debug for PooledChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PooledChannel").field(&self.channel).finish()
+    }
+}
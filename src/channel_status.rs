@@ -1,7 +1,7 @@
 use crate::{
     channel_receiver_state::ChannelReceiverStates,
     types::{ShortString, ShortUInt},
-    Result,
+    Promise, PromiseResolver, Result,
 };
 use parking_lot::Mutex;
 use std::{fmt, sync::Arc};
@@ -36,12 +36,45 @@ impl ChannelStatus {
         trace!("Publisher confirms activated");
     }
 
+    pub fn transaction(&self) -> bool {
+        self.0.lock().transaction
+    }
+
+    pub(crate) fn set_transaction(&self) {
+        self.0.lock().transaction = true;
+        trace!("Transactions activated");
+    }
+
     pub fn state(&self) -> ChannelState {
         self.0.lock().state.clone()
     }
 
     pub(crate) fn set_state(&self, state: ChannelState) {
-        self.0.lock().state = state;
+        let mut inner = self.0.lock();
+        inner.state = state;
+        inner.notify_state_waiters();
+    }
+
+    pub(crate) fn set_reconnecting(&self) {
+        let mut inner = self.0.lock();
+        inner.notify_state_waiters();
+        *inner = Inner {
+            state: ChannelState::Reconnecting,
+            ..Inner::default()
+        };
+    }
+
+    /// Give a Promise resolved once the channel leaves the Initial or Reconnecting state, if
+    /// it's not open yet
+    pub(crate) fn wait_for_open(&self) -> Option<Promise<()>> {
+        let mut inner = self.0.lock();
+        if [ChannelState::Initial, ChannelState::Reconnecting].contains(&inner.state) {
+            let (promise, resolver) = Promise::new();
+            inner.state_waiters.push(resolver);
+            Some(promise)
+        } else {
+            None
+        }
     }
 
     pub(crate) fn auto_close(&self, id: u16) -> bool {
@@ -119,9 +152,11 @@ impl ChannelStatus {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ChannelState {
     Initial,
     Connected,
+    Reconnecting,
     Closing,
     Closed,
     Error,
@@ -141,6 +176,7 @@ impl fmt::Debug for ChannelStatus {
                 .field("state", &inner.state)
                 .field("receiver_state", &inner.receiver_state)
                 .field("confirm", &inner.confirm)
+                .field("transaction", &inner.transaction)
                 .field("send_flow", &inner.send_flow);
         }
         debug.finish()
@@ -149,18 +185,50 @@ impl fmt::Debug for ChannelStatus {
 
 struct Inner {
     confirm: bool,
+    transaction: bool,
     send_flow: bool,
     state: ChannelState,
     receiver_state: ChannelReceiverStates,
+    state_waiters: Vec<PromiseResolver<()>>,
+}
+
+impl Inner {
+    fn notify_state_waiters(&mut self) {
+        for waiter in self.state_waiters.drain(..) {
+            waiter.swear(Ok(()));
+        }
+    }
 }
 
 impl Default for Inner {
     fn default() -> Self {
         Self {
             confirm: false,
+            transaction: false,
             send_flow: true,
             state: ChannelState::default(),
             receiver_state: ChannelReceiverStates::default(),
+            state_waiters: Vec::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_for_open_resolves_on_state_change() {
+        let status = ChannelStatus::default();
+        let promise = status.wait_for_open().unwrap();
+        assert!(promise.try_wait().is_none());
+        status.set_state(ChannelState::Connected);
+        assert!(matches!(promise.try_wait(), Some(Ok(()))));
+        assert!(status.wait_for_open().is_none());
+
+        status.set_reconnecting();
+        let promise = status.wait_for_open().unwrap();
+        status.set_reconnecting();
+        assert!(matches!(promise.try_wait(), Some(Ok(()))));
+    }
+}
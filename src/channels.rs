@@ -1,5 +1,6 @@
 use crate::{
     connection_closer::ConnectionCloser,
+    connection_events::ConnectionEvent,
     error_handler::ErrorHandler,
     executor::Executor,
     frames::Frames,
@@ -7,6 +8,7 @@ use crate::{
     internal_rpc::InternalRPCHandle,
     protocol::{AMQPClass, AMQPError, AMQPHardError},
     socket_state::SocketStateHandle,
+    topology_registry::TopologyRegistry,
     BasicProperties, Channel, ChannelState, Configuration, ConnectionState, ConnectionStatus,
     Error, Promise, Result,
 };
@@ -33,9 +35,14 @@ impl Channels {
         internal_rpc: InternalRPCHandle,
         frames: Frames,
         executor: Arc<dyn Executor>,
+        record_topology: bool,
     ) -> Self {
         Self {
-            inner: Arc::new(Mutex::new(Inner::new(configuration, waker))),
+            inner: Arc::new(Mutex::new(Inner::new(
+                configuration,
+                waker,
+                record_topology,
+            ))),
             connection_status,
             internal_rpc,
             executor,
@@ -72,6 +79,20 @@ impl Channels {
         self.inner.lock().channels.get(&id).cloned()
     }
 
+    /// All the channels but the channel 0, sorted by id
+    pub(crate) fn list(&self) -> Vec<Channel> {
+        let mut channels = self
+            .inner
+            .lock()
+            .channels
+            .values()
+            .filter(|channel| channel.id() != 0)
+            .cloned()
+            .collect::<Vec<_>>();
+        channels.sort_by_key(Channel::id);
+        channels
+    }
+
     pub(crate) fn remove(&self, id: u16, error: Error) -> Result<()> {
         self.frames.clear_expected_replies(id, error);
         if self.inner.lock().channels.remove(&id).is_some() {
@@ -107,6 +128,7 @@ impl Channels {
 
     pub(crate) fn set_connection_closing(&self) {
         self.connection_status.set_state(ConnectionState::Closing);
+        self.connection_status.emit(ConnectionEvent::Closing);
         for channel in self.inner.lock().channels.values() {
             channel.set_state(ChannelState::Closing);
         }
@@ -122,11 +144,32 @@ impl Channels {
         }
     }
 
+    pub(crate) fn set_connection_reconnecting(&self, error: Error) {
+        error!(%error, "Connection lost, reconnecting");
+        self.connection_status
+            .set_state(ConnectionState::Reconnecting);
+        self.connection_status.unblock();
+        self.connection_status.emit(ConnectionEvent::Reconnecting);
+        self.frames.drop_pending(error.clone());
+        for (id, channel) in self.inner.lock().channels.iter() {
+            if *id != 0 {
+                channel.set_reconnecting(error.clone());
+            }
+        }
+    }
+
     pub(crate) fn set_connection_error(&self, error: Error) {
-        if let ConnectionState::Error = self.connection_status.state() {
+        // Stale failures from the lost connection must not interrupt the recovery
+        if let ConnectionState::Error | ConnectionState::Reconnecting =
+            self.connection_status.state()
+        {
             return;
         }
+        self.abort_recovery(error);
+    }
 
+    /// Error out the connection, even if we were trying to recover it
+    pub(crate) fn abort_recovery(&self, error: Error) {
         error!(%error, "Connection error");
         self.connection_status.set_state(ConnectionState::Error);
         self.frames.drop_pending(error.clone());
@@ -254,6 +297,11 @@ impl Channels {
     pub(crate) fn set_error_handler<E: FnMut(Error) + Send + 'static>(&self, handler: E) {
         self.error_handler.set_handler(handler);
     }
+
+    /// Report an error which doesn't break the connection to the error handler
+    pub(crate) fn report_error(&self, error: Error) {
+        self.error_handler.on_error(error);
+    }
 }
 
 impl fmt::Debug for Channels {
@@ -279,15 +327,17 @@ struct Inner {
     channel_id: IdSequence<u16>,
     configuration: Configuration,
     waker: SocketStateHandle,
+    record_topology: bool,
 }
 
 impl Inner {
-    fn new(configuration: Configuration, waker: SocketStateHandle) -> Self {
+    fn new(configuration: Configuration, waker: SocketStateHandle, record_topology: bool) -> Self {
         Self {
             channels: HashMap::default(),
             channel_id: IdSequence::new(false),
             configuration,
             waker,
+            record_topology,
         }
     }
 
@@ -309,6 +359,7 @@ impl Inner {
             internal_rpc,
             frames,
             executor,
+            TopologyRegistry::new(self.record_topology),
             connection_closer,
         );
         self.channels.insert(id, channel.clone_internal());
@@ -1,26 +1,46 @@
 use crate::{
     channel::Channel,
+    channel_pool::{ChannelPool, ChannelPoolConfig},
     channels::Channels,
     configuration::Configuration,
     connection_closer::ConnectionCloser,
+    connection_events::ConnectionEvents,
     connection_properties::ConnectionProperties,
     connection_status::{ConnectionState, ConnectionStatus, ConnectionStep},
+    credentials_provider::{self, SecretRefresh},
+    endpoints::{self, Endpoint, Endpoints},
     executor::{DefaultExecutor, Executor},
     frames::Frames,
     internal_rpc::{InternalRPC, InternalRPCHandle},
     io_loop::IoLoop,
     reactor::DefaultReactorBuilder,
+    recovery::{Connector, Recovery},
+    sasl, socket_options,
     socket_state::{SocketState, SocketStateHandle},
     tcp::{AMQPUriTcpExt, HandshakeResult, OwnedTLSConfig},
     thread::ThreadHandle,
-    types::ShortUInt,
+    topology::{self, Topology, TopologyDrift},
+    transport::Transport,
+    types::{FieldTable, ShortUInt},
     uri::AMQPUri,
-    Error, Promise, Result,
+    Error, Promise, Result, TcpStream,
 };
 use amq_protocol::frame::{AMQPFrame, ProtocolVersion};
+use async_io::Timer;
 use async_trait::async_trait;
-use std::{fmt, io, sync::Arc};
-use tracing::{level_enabled, Level};
+use futures_lite::future;
+use parking_lot::Mutex;
+use std::{
+    convert::TryFrom,
+    fmt,
+    future::Future,
+    io,
+    sync::{Arc, Weak},
+    time::Duration,
+};
+use tracing::{level_enabled, warn, Level};
+
+type TransportConnect = Box<dyn Fn(&AMQPUri) -> Result<Box<dyn Transport>> + Send + Sync>;
 
 /// A TCP connection to the AMQP server.
 ///
@@ -41,12 +61,35 @@ pub struct Connection {
     closer: Arc<ConnectionCloser>,
 }
 
+/// A handle to a Connection which doesn't keep it alive
+#[derive(Clone)]
+pub(crate) struct WeakConnection {
+    configuration: Configuration,
+    status: ConnectionStatus,
+    channels: Channels,
+    io_loop: ThreadHandle,
+    closer: Weak<ConnectionCloser>,
+}
+
+impl WeakConnection {
+    pub(crate) fn upgrade(&self) -> Option<Connection> {
+        Some(Connection {
+            configuration: self.configuration.clone(),
+            status: self.status.clone(),
+            channels: self.channels.clone(),
+            io_loop: self.io_loop.clone(),
+            closer: self.closer.upgrade()?,
+        })
+    }
+}
+
 impl Connection {
     fn new(
         waker: SocketStateHandle,
         internal_rpc: InternalRPCHandle,
         frames: Frames,
         executor: Arc<dyn Executor>,
+        record_topology: bool,
     ) -> Self {
         let configuration = Configuration::default();
         let status = ConnectionStatus::default();
@@ -57,6 +100,7 @@ impl Connection {
             internal_rpc.clone(),
             frames,
             executor,
+            record_topology,
         );
         let closer = Arc::new(ConnectionCloser::new(status.clone(), internal_rpc));
         let connection = Self {
@@ -94,6 +138,23 @@ impl Connection {
         Connect::connect(uri, options, config).await
     }
 
+    /// Connect to the first of these AMQP Servers accepting the connection
+    pub async fn connect_endpoints(
+        endpoints: Endpoints,
+        options: ConnectionProperties,
+    ) -> Result<Connection> {
+        Connect::connect(endpoints, options, OwnedTLSConfig::default()).await
+    }
+
+    /// Connect to the first of these AMQP Servers accepting the connection
+    pub async fn connect_endpoints_with_config(
+        endpoints: Endpoints,
+        options: ConnectionProperties,
+        config: OwnedTLSConfig,
+    ) -> Result<Connection> {
+        Connect::connect(endpoints, options, config).await
+    }
+
     /// Connect to an AMQP Server.
     pub async fn connect_uri(uri: AMQPUri, options: ConnectionProperties) -> Result<Connection> {
         Connect::connect(uri, options, OwnedTLSConfig::default()).await
@@ -123,6 +184,15 @@ impl Connection {
         channel.clone().channel_open(channel).await
     }
 
+    /// Create a [`ChannelPool`] leasing channels of this connection
+    ///
+    /// Its size is capped to the `channel_max` negotiated with the server.
+    ///
+    /// [`ChannelPool`]: ./struct.ChannelPool.html
+    pub fn channel_pool(&self, config: ChannelPoolConfig) -> ChannelPool {
+        ChannelPool::new(self.downgrade(), config, self.configuration.channel_max())
+    }
+
     /// Block current thread while the connection is still active.
     /// This is useful when you only have a consumer and nothing else keeping your application
     /// "alive".
@@ -132,10 +202,91 @@ impl Connection {
         io_loop.wait("io loop")
     }
 
+    /// A Stream of the lifecycle events of this connection, starting from now
+    ///
+    /// Use [`ConnectionProperties::events`] to get the events of the handshake too.
+    ///
+    /// [`ConnectionProperties::events`]: ./struct.ConnectionProperties.html#method.events
+    pub fn events(&self) -> ConnectionEvents {
+        ConnectionEvents::new(self.status.subscribe())
+    }
+
+    /// Check that the exchanges and queues of this topology exist with the expected parameters
+    ///
+    /// Each of them is declared passively first, then, if it exists, declared again with its
+    /// kind, flags and arguments, which the server refuses if they differ from the existing
+    /// ones. Something deleted in between those two steps would thus get declared again.
+    /// Bindings can't be listed over AMQP 0.9.1 and aren't checked, `apply_topology` can be
+    /// used to make sure they exist as binding is idempotent.
+    /// A failed declaration closes the channel, so this opens its own channels.
+    pub async fn verify_topology(&self, topology: &Topology) -> Result<Vec<TopologyDrift>> {
+        let mut drifts = Vec::new();
+        let mut channel = self.create_channel().await?;
+        for exchange in topology.ordered_exchanges() {
+            let declared = channel
+                .exchange_declare(
+                    &exchange.name,
+                    exchange.kind.clone(),
+                    exchange.options(true),
+                    FieldTable::default(),
+                )
+                .await;
+            if topology::missing(declared)? {
+                warn!(exchange=%exchange.name, "exchange is missing");
+                drifts.push(TopologyDrift::MissingExchange(exchange.name.clone()));
+                channel = self.create_channel().await?;
+                continue;
+            }
+            let declared = channel
+                .exchange_declare(
+                    &exchange.name,
+                    exchange.kind.clone(),
+                    exchange.options(false),
+                    exchange.arguments.clone(),
+                )
+                .await;
+            if let Some(reason) = topology::inequivalent(declared)? {
+                warn!(exchange=%exchange.name, %reason, "exchange differs");
+                drifts.push(TopologyDrift::InequivalentExchange {
+                    name: exchange.name.clone(),
+                    reason,
+                });
+                channel = self.create_channel().await?;
+            }
+        }
+        for queue in &topology.queues {
+            let declared = channel
+                .queue_declare(&queue.name, queue.options(true), FieldTable::default())
+                .await;
+            if topology::missing(declared)? {
+                warn!(queue=%queue.name, "queue is missing");
+                drifts.push(TopologyDrift::MissingQueue(queue.name.clone()));
+                channel = self.create_channel().await?;
+                continue;
+            }
+            let declared = channel
+                .queue_declare(&queue.name, queue.options(false), queue.arguments.clone())
+                .await;
+            if let Some(reason) = topology::inequivalent(declared)? {
+                warn!(queue=%queue.name, %reason, "queue differs");
+                drifts.push(TopologyDrift::InequivalentQueue {
+                    name: queue.name.clone(),
+                    reason,
+                });
+                channel = self.create_channel().await?;
+            }
+        }
+        Ok(drifts)
+    }
+
     pub fn on_error<E: FnMut(Error) + Send + 'static>(&self, handler: E) {
         self.channels.set_error_handler(handler);
     }
 
+    pub(crate) fn report_error(&self, error: Error) {
+        self.channels.report_error(error);
+    }
+
     pub fn configuration(&self) -> &Configuration {
         &self.configuration
     }
@@ -144,6 +295,16 @@ impl Connection {
         &self.status
     }
 
+    pub(crate) fn downgrade(&self) -> WeakConnection {
+        WeakConnection {
+            configuration: self.configuration.clone(),
+            status: self.status.clone(),
+            channels: self.channels.clone(),
+            io_loop: self.io_loop.clone(),
+            closer: Arc::downgrade(&self.closer),
+        }
+    }
+
     pub async fn close(&self, reply_code: ShortUInt, reply_text: &str) -> Result<()> {
         if let Some(channel0) = self.channels.get(0) {
             channel0
@@ -181,21 +342,115 @@ impl Connection {
         }
     }
 
+    /// Connect using `connect` to establish the TCP (and TLS) stream
+    ///
+    /// As `connect` can only be called once, such connections are never recovered, use
+    /// [`recovering_connector`] for that.
+    ///
+    /// [`recovering_connector`]: #method.recovering_connector
     pub async fn connector(
         uri: AMQPUri,
         connect: Box<dyn FnOnce(&AMQPUri) -> HandshakeResult + Send + Sync>,
         mut options: ConnectionProperties,
+    ) -> Result<Connection> {
+        options.recovery_config = None;
+        let connect = Mutex::new(Some(connect));
+        let connect: TransportConnect = Box::new(move |uri| match connect.lock().take() {
+            Some(connect) => Ok(Box::new(TcpStream::try_from(connect(uri))?)),
+            None => Err(io::Error::new(io::ErrorKind::Other, "connector already used").into()),
+        });
+        Self::transport_connector(uri, connect, options).await
+    }
+
+    /// Connect using `connect` to establish the TCP (and TLS) stream, calling it again each
+    /// time the connection needs to be recovered
+    pub async fn recovering_connector(
+        uri: AMQPUri,
+        connect: Box<dyn Fn(&AMQPUri) -> HandshakeResult + Send + Sync>,
+        options: ConnectionProperties,
+    ) -> Result<Connection> {
+        Self::transport_connector(uri, tcp_connector(connect), options).await
+    }
+
+    /// Try to connect to each endpoint until the handshake succeeds
+    pub async fn endpoints_connector(
+        endpoints: Endpoints,
+        connect: Box<dyn Fn(&AMQPUri) -> HandshakeResult + Send + Sync>,
+        options: ConnectionProperties,
+    ) -> Result<Connection> {
+        Self::endpoints_transport_connector(endpoints, tcp_connector(connect), options).await
+    }
+
+    /// Connect over a custom [`Transport`], `connect` being called for each connection attempt
+    ///
+    /// [`Transport`]: ./trait.Transport.html
+    pub async fn transport_connector(
+        uri: AMQPUri,
+        connect: TransportConnect,
+        options: ConnectionProperties,
+    ) -> Result<Connection> {
+        Self::endpoints_transport_connector(uri.into(), connect, options).await
+    }
+
+    /// Try to connect to each endpoint over a custom [`Transport`] until the handshake succeeds
+    ///
+    /// [`Transport`]: ./trait.Transport.html
+    pub async fn endpoints_transport_connector(
+        endpoints: Endpoints,
+        connect: TransportConnect,
+        options: ConnectionProperties,
+    ) -> Result<Connection> {
+        let connect: Connector = Arc::from(connect);
+        let mut last_error = None;
+        for endpoint in endpoints.ordered() {
+            let host = endpoint.uri.authority.host.clone();
+            let port = endpoint.uri.authority.port;
+            match Self::connect_endpoint(endpoint, connect.clone(), options.clone(), &endpoints)
+                .await
+            {
+                Ok(connection) => return Ok(connection),
+                Err(err) => {
+                    warn!(%host, %port, %err, "Failed to connect to endpoint");
+                    last_error = Some(err);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no endpoint to connect to").into()
+        }))
+    }
+
+    async fn connect_endpoint(
+        endpoint: Endpoint,
+        connect: Connector,
+        mut options: ConnectionProperties,
+        endpoints: &Endpoints,
     ) -> Result<Connection> {
         let executor = options
             .executor
             .take()
             .map(Ok)
             .unwrap_or_else(DefaultExecutor::default)?;
+        let uri = endpoint.uri.clone();
+        let (credentials, secret) = {
+            // The provider may read a file or query a token endpoint
+            let uri = uri.clone();
+            let provider = options.credentials_provider.clone();
+            blocking::unblock(move || credentials_provider::credentials(&uri, provider.as_ref()))
+                .await?
+        };
+
+        let connection_timeout = options.connection_timeout(&uri);
+        let handshake_timeout = options.handshake_timeout.or(connection_timeout);
 
-        let (connect_promise, resolver) = pinky_swear::PinkySwear::<HandshakeResult>::new();
-        let connect_uri = uri.clone();
+        let (connect_promise, resolver) =
+            pinky_swear::PinkySwear::<Result<Box<dyn Transport>>>::new();
+        let mut connect_endpoint = endpoint;
+        connect_endpoint.uri.query.connection_timeout =
+            connection_timeout.map(|timeout| timeout.as_millis() as u64);
+        let connector = connect.clone();
         executor.spawn_blocking(Box::new(move || {
-            resolver.swear(connect(&connect_uri));
+            resolver.swear(connect_endpoint.connect(&connector));
         }));
 
         let reactor_builder = options
@@ -211,11 +466,32 @@ impl Connection {
             internal_rpc.handle(),
             frames.clone(),
             executor.clone(),
+            options.recovery_config.is_some() || options.record_topology,
         );
+        let secret_refresh =
+            options
+                .credentials_provider
+                .clone()
+                .zip(secret)
+                .map(|(provider, secret)| SecretRefresh {
+                    connection: conn.downgrade(),
+                    provider,
+                    secret,
+                });
+        let recovery = options.recovery_config.clone().map(|config| Recovery {
+            config,
+            endpoints: endpoints.clone(),
+            connect,
+            options: options.clone(),
+            reactor_builder: reactor_builder.clone(),
+            executor: executor.clone(),
+            connection: conn.downgrade(),
+        });
         let status = conn.status.clone();
         let configuration = conn.configuration.clone();
-        status.set_vhost(&uri.vhost);
-        status.set_username(&uri.authority.userinfo.username);
+        status.set_endpoint(&uri);
+        status.set_blocked_publish_policy(options.blocked_publish_policy);
+        status.set_properties_event_listeners(options.event_listeners.clone());
         if let Some(frame_max) = uri.query.frame_max {
             configuration.set_frame_max(frame_max);
         }
@@ -242,32 +518,56 @@ impl Connection {
             promise_in.set_marker("ProtocolHeader.Ok".into());
         }
         let io_loop_handle = conn.io_loop.clone();
+        let mechanism = options
+            .sasl_mechanism
+            .clone()
+            .unwrap_or_else(|| sasl::from_uri(&uri));
         status.set_state(ConnectionState::Connecting);
         status.set_connection_step(ConnectionStep::ProtocolHeader(
             resolver,
             conn,
-            uri.authority.userinfo.into(),
-            uri.query.auth_mechanism.unwrap_or_default(),
-            options,
+            credentials,
+            mechanism,
+            options.clone(),
         ));
-        let handshake_result = connect_promise.await;
+        let transport = with_timeout(connection_timeout, connect_promise)
+            .await
+            .map_err(timed_out)?;
+        let waker = socket_state.handle();
         let internal_rpc_handle = internal_rpc.handle();
         executor.spawn(Box::pin(internal_rpc.run(channels.clone())));
         IoLoop::new(
             status,
             configuration,
-            channels,
+            channels.clone(),
             internal_rpc_handle,
             frames,
             socket_state,
-            io_loop_handle,
-            handshake_result,
+            io_loop_handle.clone(),
+            transport,
             &*reactor_builder,
-            executor,
+            executor.clone(),
+            recovery,
+            secret_refresh,
         )
-        .and_then(IoLoop::start)?;
-        promise_out.await?;
-        promise_in.await
+        .and_then(|io_loop| io_loop.start(options.io_loop_mode))?;
+        let handshake = with_timeout(handshake_timeout, async move {
+            promise_out.await?;
+            promise_in.await
+        })
+        .await
+        .map_err(timed_out);
+        let connection = match handshake {
+            Err(Error::Timeout) => {
+                // Stop the io loop, which is still waiting for the server
+                channels.set_connection_error(Error::Timeout);
+                waker.wake();
+                let _ = blocking::unblock(move || io_loop_handle.wait("io loop")).await;
+                return Err(Error::Timeout);
+            }
+            handshake => handshake?,
+        };
+        Ok(connection)
     }
 }
 
@@ -281,6 +581,63 @@ impl fmt::Debug for Connection {
     }
 }
 
+fn tcp_connector(
+    connect: Box<dyn Fn(&AMQPUri) -> HandshakeResult + Send + Sync>,
+) -> TransportConnect {
+    Box::new(move |uri| Ok(Box::new(TcpStream::try_from(connect(uri))?)))
+}
+
+/// Fail with `Error::Timeout` if `future` doesn't complete in time
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match timeout {
+        Some(timeout) => {
+            future::or(future, async move {
+                Timer::after(timeout).await;
+                Err(Error::Timeout)
+            })
+            .await
+        }
+        None => future.await,
+    }
+}
+
+/// Report the OS timeouts of the connection attempt as `Error::Timeout` too
+fn timed_out(error: Error) -> Error {
+    match error {
+        Error::IOError(ref err) if err.kind() == io::ErrorKind::TimedOut => Error::Timeout,
+        error => error,
+    }
+}
+
+/// Connect through TCP (and TLS for amqps)
+pub(crate) fn default_connector(
+    config: OwnedTLSConfig,
+    options: &ConnectionProperties,
+) -> TransportConnect {
+    let proxy = options.proxy.clone();
+    let socket_options = options.socket_options.clone();
+    Box::new(move |uri| {
+        let proxy = match proxy.as_ref() {
+            Some(proxy) => proxy.resolve(&uri.authority.host, uri.authority.port)?,
+            None => None,
+        };
+        let stream = if proxy.is_none() && socket_options.is_none() {
+            AMQPUriTcpExt::connect_with_config(uri, config.as_ref())
+        } else {
+            socket_options::connect_uri(
+                uri,
+                proxy.as_ref(),
+                &socket_options.clone().unwrap_or_default(),
+                config.as_ref(),
+            )
+        };
+        Ok(Box::new(TcpStream::try_from(stream)?))
+    })
+}
+
 /// Trait providing a method to connect to an AMQP server
 #[async_trait]
 pub trait Connect {
@@ -299,12 +656,20 @@ impl Connect for AMQPUri {
         options: ConnectionProperties,
         config: OwnedTLSConfig,
     ) -> Result<Connection> {
-        Connection::connector(
-            self,
-            Box::new(move |uri| AMQPUriTcpExt::connect_with_config(uri, config.as_ref())),
-            options,
-        )
-        .await
+        let connect = default_connector(config, &options);
+        Connection::transport_connector(self, connect, options).await
+    }
+}
+
+#[async_trait]
+impl Connect for Endpoints {
+    async fn connect(
+        self,
+        options: ConnectionProperties,
+        config: OwnedTLSConfig,
+    ) -> Result<Connection> {
+        let connect = default_connector(config, &options);
+        Connection::endpoints_transport_connector(self, connect, options).await
     }
 }
 
@@ -315,8 +680,8 @@ impl Connect for &str {
         options: ConnectionProperties,
         config: OwnedTLSConfig,
     ) -> Result<Connection> {
-        match self.parse::<AMQPUri>() {
-            Ok(uri) => Connect::connect(uri, options, config).await,
+        match endpoints::parse_uri(self) {
+            Ok(endpoint) => Connect::connect(Endpoints::from(endpoint), options, config).await,
             Err(err) => Err(io::Error::new(io::ErrorKind::Other, err).into()),
         }
     }
@@ -349,6 +714,7 @@ mod tests {
             internal_rpc.handle(),
             Frames::default(),
             executor.clone(),
+            false,
         );
         conn.status.set_state(ConnectionState::Connected);
         conn.configuration.set_channel_max(2047);
@@ -428,6 +794,7 @@ mod tests {
             internal_rpc.handle(),
             Frames::default(),
             executor.clone(),
+            false,
         );
         conn.status.set_state(ConnectionState::Connected);
         conn.configuration.set_channel_max(2047);
@@ -0,0 +1,118 @@
+use crate::{types::ShortUInt, Configuration};
+use flume::r#async::RecvStream;
+use futures_lite::Stream;
+use parking_lot::Mutex;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+/// Something that happened to the connection, as yielded by [`Connection::events`] and
+/// [`ConnectionProperties::events`]
+///
+/// [`Connection::events`]: ./struct.Connection.html#method.events
+/// [`ConnectionProperties::events`]: ./struct.ConnectionProperties.html#method.events
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum ConnectionEvent {
+    /// The handshake succeeded, with the configuration negotiated with the server. Subscribe
+    /// through [`ConnectionProperties::events`] to get it for the first connection
+    ///
+    /// [`ConnectionProperties::events`]: ./struct.ConnectionProperties.html#method.events
+    Connected(Configuration),
+    /// The server stopped accepting publishes, for the given reason
+    Blocked(String),
+    /// The server accepts publishes again
+    Unblocked,
+    /// The connection is being closed
+    Closing,
+    /// The server closed the connection
+    ClosedByServer {
+        reply_code: ShortUInt,
+        reply_text: String,
+    },
+    /// We didn't hear from the server for two heartbeat intervals, the connection is considered
+    /// lost
+    HeartbeatMissed,
+    /// We lost the connection and are trying to recover it
+    Reconnecting,
+    /// The connection and its channels have been recovered
+    Recovered,
+}
+
+/// A Stream of the [`ConnectionEvent`]s happening after its creation
+///
+/// [`ConnectionEvent`]: ./enum.ConnectionEvent.html
+pub struct ConnectionEvents(RecvStream<'static, ConnectionEvent>);
+
+impl ConnectionEvents {
+    pub(crate) fn new(receiver: flume::Receiver<ConnectionEvent>) -> Self {
+        Self(receiver.into_stream())
+    }
+}
+
+impl Stream for ConnectionEvents {
+    type Item = ConnectionEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
+impl fmt::Debug for ConnectionEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ConnectionEvents").finish()
+    }
+}
+
+/// The listeners of the events of the connections established with some
+/// [`ConnectionProperties`], each connection forwarding its own events to them
+///
+/// [`ConnectionProperties`]: ./struct.ConnectionProperties.html
+#[derive(Clone, Default)]
+pub struct ConnectionEventListeners(Arc<Mutex<Vec<flume::Sender<ConnectionEvent>>>>);
+
+impl ConnectionEventListeners {
+    pub(crate) fn subscribe(&self) -> flume::Receiver<ConnectionEvent> {
+        let (sender, receiver) = flume::unbounded();
+        self.0.lock().push(sender);
+        receiver
+    }
+
+    pub(crate) fn emit(&self, event: ConnectionEvent) {
+        // Forget about the listeners which have been dropped
+        self.0
+            .lock()
+            .retain(|listener| listener.send(event.clone()).is_ok());
+    }
+}
+
+impl fmt::Debug for ConnectionEventListeners {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ConnectionEventListeners").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_reach_live_listeners() {
+        let listeners = ConnectionEventListeners::default();
+        let first = listeners.subscribe();
+        let second = listeners.subscribe();
+        listeners.emit(ConnectionEvent::Blocked("low on memory".into()));
+        drop(second);
+        listeners.emit(ConnectionEvent::Unblocked);
+        assert_eq!(listeners.0.lock().len(), 1);
+        assert!(matches!(
+            first.try_recv(),
+            Ok(ConnectionEvent::Blocked(reason)) if reason == "low on memory"
+        ));
+        assert!(matches!(first.try_recv(), Ok(ConnectionEvent::Unblocked)));
+        assert!(first.try_recv().is_err());
+    }
+}
@@ -0,0 +1,360 @@
+use crate::{
+    connection,
+    executor::{DefaultExecutor, Executor},
+    recovery::Connector,
+    tcp::OwnedTLSConfig,
+    types::ShortUInt,
+    Channel, Connection, ConnectionProperties, ConnectionState, ConnectionStatus, Endpoints, Error,
+    Result,
+};
+use parking_lot::Mutex;
+use std::{
+    fmt, io,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
+use tracing::{trace, warn};
+
+/// Configuration of a [`ConnectionPool`]
+///
+/// [`ConnectionPool`]: ./struct.ConnectionPool.html
+#[derive(Clone, Debug)]
+pub struct ConnectionPoolConfig {
+    /// Number of connections to maintain
+    pub size: usize,
+    /// Delay before each attempt at replacing a failed connection
+    pub retry_delay: Duration,
+}
+
+impl Default for ConnectionPoolConfig {
+    fn default() -> Self {
+        Self {
+            size: 4,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A snapshot of the state of a [`ConnectionPool`]
+///
+/// [`ConnectionPool`]: ./struct.ConnectionPool.html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionPoolMetrics {
+    /// Number of connections the pool maintains
+    pub size: usize,
+    /// Connections channels can currently be created on
+    pub connected: usize,
+    /// Failed connections waiting to be replaced
+    pub replacing: usize,
+    /// Connections replaced since the pool got created
+    pub replaced: u64,
+    /// Failed attempts at replacing a connection
+    pub failed_attempts: u64,
+    /// Channels created through the pool
+    pub channels_created: u64,
+}
+
+/// A fixed number of connections to the same [`Endpoints`], spreading the channels across them
+///
+/// Each connection has its own io loop, so that heavy publishers aren't limited by a single
+/// one. [`create_channel`] picks the connections in turn, skipping those which aren't
+/// connected. Connections failing with an error get replaced in the background, every
+/// `retry_delay` until it works; with [`EndpointSelection::RoundRobin`] they get spread
+/// across the brokers.
+///
+/// The pool takes over the [`Connection::on_error`] handler of its connections, setting
+/// another one on them stops their replacement. Errors which leave the connection usable,
+/// such as a failed credentials refresh, are only logged.
+///
+/// Connections use the default TLS configuration unless one is given to
+/// [`connect_with_tls_config`] or [`connect_endpoints_with_tls_config`].
+///
+/// [`Endpoints`]: ./struct.Endpoints.html
+/// [`create_channel`]: #method.create_channel
+/// [`Connection::on_error`]: ./struct.Connection.html#method.on_error
+/// [`connect_with_tls_config`]: #method.connect_with_tls_config
+/// [`connect_endpoints_with_tls_config`]: #method.connect_endpoints_with_tls_config
+/// [`EndpointSelection::RoundRobin`]: ./enum.EndpointSelection.html#variant.RoundRobin
+#[derive(Clone)]
+pub struct ConnectionPool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    endpoints: Endpoints,
+    connect: Connector,
+    options: ConnectionProperties,
+    executor: Arc<dyn Executor>,
+    config: ConnectionPoolConfig,
+    slots: Vec<Mutex<Slot>>,
+    next: AtomicUsize,
+    closed: AtomicBool,
+    replaced: AtomicU64,
+    failed_attempts: AtomicU64,
+    channels_created: AtomicU64,
+}
+
+#[derive(Default)]
+struct Slot {
+    /// Bumped each time the connection gets replaced, to ignore errors from the previous ones
+    generation: u64,
+    /// None while replacing the connection
+    connection: Option<Arc<Connection>>,
+}
+
+impl ConnectionPool {
+    /// Open `config.size` connections to the brokers from this comma-separated list of URIs
+    pub async fn connect(
+        uri: &str,
+        options: ConnectionProperties,
+        config: ConnectionPoolConfig,
+    ) -> Result<Self> {
+        Self::connect_with_tls_config(uri, options, config, OwnedTLSConfig::default()).await
+    }
+
+    /// Open `config.size` connections to the brokers from this comma-separated list of URIs,
+    /// using this TLS configuration
+    pub async fn connect_with_tls_config(
+        uri: &str,
+        options: ConnectionProperties,
+        config: ConnectionPoolConfig,
+        tls_config: OwnedTLSConfig,
+    ) -> Result<Self> {
+        let endpoints = uri
+            .parse::<Endpoints>()
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        Self::connect_endpoints_with_tls_config(endpoints, options, config, tls_config).await
+    }
+
+    /// Open `config.size` connections to these brokers
+    pub async fn connect_endpoints(
+        endpoints: Endpoints,
+        options: ConnectionProperties,
+        config: ConnectionPoolConfig,
+    ) -> Result<Self> {
+        Self::connect_endpoints_with_tls_config(
+            endpoints,
+            options,
+            config,
+            OwnedTLSConfig::default(),
+        )
+        .await
+    }
+
+    /// Open `config.size` connections to these brokers, using this TLS configuration
+    pub async fn connect_endpoints_with_tls_config(
+        endpoints: Endpoints,
+        mut options: ConnectionProperties,
+        config: ConnectionPoolConfig,
+        tls_config: OwnedTLSConfig,
+    ) -> Result<Self> {
+        let executor = options
+            .executor
+            .clone()
+            .map(Ok)
+            .unwrap_or_else(DefaultExecutor::default)?;
+        // Share the executor between all the connections and the replacement tasks
+        options.executor = Some(executor.clone());
+        let size = config.size.max(1);
+        let connect = Arc::from(connection::default_connector(tls_config, &options));
+        let inner = Arc::new(Inner {
+            endpoints,
+            connect,
+            options,
+            executor,
+            config,
+            slots: (0..size).map(|_| Mutex::new(Slot::default())).collect(),
+            next: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            replaced: AtomicU64::new(0),
+            failed_attempts: AtomicU64::new(0),
+            channels_created: AtomicU64::new(0),
+        });
+        let pool = Self { inner };
+        for index in 0..size {
+            if let Err(err) = pool.inner.open(index, 0).await {
+                // Don't leave the connections we already opened behind
+                let _ = pool.close(200, "OK").await;
+                return Err(err);
+            }
+        }
+        Ok(pool)
+    }
+
+    /// The next connected connection of the pool
+    pub fn connection(&self) -> Result<Arc<Connection>> {
+        let size = self.inner.slots.len();
+        let start = self.inner.next.fetch_add(1, Ordering::Relaxed);
+        (0..size)
+            .find_map(|offset| {
+                let slot = self.inner.slots[(start + offset) % size].lock();
+                slot.connection
+                    .as_ref()
+                    .filter(|connection| connection.status().connected())
+                    .cloned()
+            })
+            .ok_or_else(|| {
+                Error::InvalidConnectionState(if self.inner.closed.load(Ordering::SeqCst) {
+                    ConnectionState::Closed
+                } else {
+                    ConnectionState::Error
+                })
+            })
+    }
+
+    /// Create a channel on the next connected connection of the pool
+    pub async fn create_channel(&self) -> Result<Channel> {
+        let channel = self.connection()?.create_channel().await?;
+        self.inner.channels_created.fetch_add(1, Ordering::Relaxed);
+        Ok(channel)
+    }
+
+    pub fn metrics(&self) -> ConnectionPoolMetrics {
+        let mut metrics = ConnectionPoolMetrics {
+            size: self.inner.slots.len(),
+            replaced: self.inner.replaced.load(Ordering::Relaxed),
+            failed_attempts: self.inner.failed_attempts.load(Ordering::Relaxed),
+            channels_created: self.inner.channels_created.load(Ordering::Relaxed),
+            ..ConnectionPoolMetrics::default()
+        };
+        let closed = self.inner.closed.load(Ordering::SeqCst);
+        for slot in &self.inner.slots {
+            match slot.lock().connection.as_ref() {
+                Some(connection) if connection.status().connected() => metrics.connected += 1,
+                None if !closed => metrics.replacing += 1,
+                _ => {}
+            }
+        }
+        metrics
+    }
+
+    /// Close all the connections and stop replacing them
+    pub async fn close(&self, reply_code: ShortUInt, reply_text: &str) -> Result<()> {
+        self.inner.closed.store(true, Ordering::SeqCst);
+        let mut result = Ok(());
+        for slot in &self.inner.slots {
+            let connection = slot.lock().connection.take();
+            if let Some(connection) = connection {
+                if connection.status().connected() {
+                    if let Err(err) = connection.close(reply_code, reply_text).await {
+                        result = result.and(Err(err));
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+impl Inner {
+    async fn open(self: &Arc<Self>, index: usize, generation: u64) -> Result<()> {
+        let connect = self.connect.clone();
+        let connection = Connection::endpoints_transport_connector(
+            self.endpoints.clone(),
+            Box::new(move |uri| connect(uri)),
+            self.options.clone(),
+        )
+        .await?;
+        if self.closed.load(Ordering::SeqCst) {
+            // The pool got closed while we were connecting
+            let _ = connection.close(200, "OK").await;
+            return Err(Error::InvalidConnectionState(ConnectionState::Closed));
+        }
+        let pool = Arc::downgrade(self);
+        let status = connection.status().clone();
+        connection.on_error(move |err| {
+            if !broken(&status) {
+                warn!(%err, %index, "pooled connection reported an error");
+                return;
+            }
+            if let Some(pool) = pool.upgrade() {
+                warn!(%err, %index, "pooled connection failed");
+                pool.replace(index, generation);
+            }
+        });
+        let errored = broken(connection.status());
+        {
+            let mut slot = self.slots[index].lock();
+            slot.generation = generation;
+            slot.connection = Some(Arc::new(connection));
+        }
+        trace!(%index, %generation, "pooled connection opened");
+        if errored {
+            // The error handler came too late for this one
+            self.replace(index, generation);
+        }
+        Ok(())
+    }
+
+    fn replace(self: &Arc<Self>, index: usize, generation: u64) {
+        if self.closed.load(Ordering::SeqCst) {
+            return;
+        }
+        let failed = {
+            let mut slot = self.slots[index].lock();
+            if slot.generation != generation {
+                return;
+            }
+            match slot.connection.take() {
+                Some(connection) => connection,
+                None => return,
+            }
+        };
+        let pool = Arc::downgrade(self);
+        let delay = self.config.retry_delay;
+        // Wait with the timers of the reactor the failed connection was using
+        let timers = failed.status().clone();
+        self.executor.spawn(Box::pin(reconnect(
+            pool,
+            index,
+            generation + 1,
+            delay,
+            timers,
+        )));
+    }
+}
+
+/// Whether the connection failed for good and needs to be replaced
+fn broken(status: &ConnectionStatus) -> bool {
+    status.errored() || status.closed()
+}
+
+async fn reconnect(
+    pool: Weak<Inner>,
+    index: usize,
+    generation: u64,
+    delay: Duration,
+    timers: ConnectionStatus,
+) {
+    loop {
+        timers.sleep(delay).await;
+        let pool = match pool.upgrade() {
+            Some(pool) if !pool.closed.load(Ordering::SeqCst) => pool,
+            _ => return,
+        };
+        match pool.open(index, generation).await {
+            Ok(()) => {
+                pool.replaced.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            Err(_) if pool.closed.load(Ordering::SeqCst) => return,
+            Err(err) => {
+                warn!(%err, %index, "failed to replace pooled connection");
+                pool.failed_attempts.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ConnectionPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionPool")
+            .field("endpoints", &self.inner.endpoints)
+            .field("config", &self.inner.config)
+            .field("metrics", &self.metrics())
+            .finish()
+    }
+}
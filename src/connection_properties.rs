@@ -1,5 +1,18 @@
-use crate::{executor::Executor, reactor::ReactorBuilder, types::FieldTable};
-use std::sync::Arc;
+use crate::{
+    blocked_publish_policy::BlockedPublishPolicy,
+    connection_events::{ConnectionEventListeners, ConnectionEvents},
+    credentials_provider::CredentialsProvider,
+    executor::Executor,
+    io_loop::IoLoopMode,
+    proxy::{Proxy, ProxyConfig},
+    reactor::ReactorBuilder,
+    recovery::RecoveryConfig,
+    sasl::SaslMechanism,
+    socket_options::SocketOptions,
+    types::FieldTable,
+    uri::AMQPUri,
+};
+use std::{sync::Arc, time::Duration};
 
 #[derive(Clone, Debug)]
 pub struct ConnectionProperties {
@@ -7,6 +20,17 @@ pub struct ConnectionProperties {
     pub client_properties: FieldTable,
     pub executor: Option<Arc<dyn Executor>>,
     pub reactor_builder: Option<Arc<dyn ReactorBuilder>>,
+    pub recovery_config: Option<RecoveryConfig>,
+    pub sasl_mechanism: Option<Arc<dyn SaslMechanism>>,
+    pub credentials_provider: Option<Arc<dyn CredentialsProvider>>,
+    pub blocked_publish_policy: BlockedPublishPolicy,
+    pub record_topology: bool,
+    pub proxy: Option<ProxyConfig>,
+    pub connection_timeout: Option<Duration>,
+    pub handshake_timeout: Option<Duration>,
+    pub socket_options: Option<SocketOptions>,
+    pub io_loop_mode: IoLoopMode,
+    pub event_listeners: ConnectionEventListeners,
 }
 
 impl Default for ConnectionProperties {
@@ -16,6 +40,17 @@ impl Default for ConnectionProperties {
             client_properties: FieldTable::default(),
             executor: None,
             reactor_builder: None,
+            recovery_config: None,
+            sasl_mechanism: None,
+            credentials_provider: None,
+            blocked_publish_policy: BlockedPublishPolicy::default(),
+            record_topology: false,
+            proxy: None,
+            connection_timeout: None,
+            handshake_timeout: None,
+            socket_options: None,
+            io_loop_mode: IoLoopMode::default(),
+            event_listeners: ConnectionEventListeners::default(),
         }
     }
 }
@@ -30,4 +65,90 @@ impl ConnectionProperties {
         self.reactor_builder = Some(Arc::new(reactor_builder));
         self
     }
+
+    /// Automatically reconnect and restore the channels when the connection is lost
+    pub fn with_connection_recovery(mut self, config: RecoveryConfig) -> Self {
+        self.recovery_config = Some(config);
+        self
+    }
+
+    /// Authenticate using this mechanism instead of the one from the URI
+    pub fn with_sasl_mechanism<M: SaslMechanism + 'static>(mut self, mechanism: M) -> Self {
+        self.sasl_mechanism = Some(Arc::new(mechanism));
+        self
+    }
+
+    /// Get the password from this provider and keep it up to date on the server
+    pub fn with_credentials_provider<P: CredentialsProvider + 'static>(
+        mut self,
+        provider: P,
+    ) -> Self {
+        self.credentials_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// What basic_publish should do while the server blocks the connection
+    pub fn with_blocked_publish_policy(mut self, policy: BlockedPublishPolicy) -> Self {
+        self.blocked_publish_policy = policy;
+        self
+    }
+
+    /// Keep track of what the channels declare even without connection recovery, so that it
+    /// can be exported through `Channel::declared_topology`
+    pub fn with_topology_recording(mut self) -> Self {
+        self.record_topology = true;
+        self
+    }
+
+    /// Reach the broker through this proxy
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(ProxyConfig::Proxy(proxy));
+        self
+    }
+
+    /// Reach the broker through the proxy from the `ALL_PROXY` environment variable, unless it
+    /// matches `NO_PROXY`
+    pub fn with_proxy_from_env(mut self) -> Self {
+        self.proxy = Some(ProxyConfig::Environment);
+        self
+    }
+
+    /// Fail with `Error::Timeout` if establishing the TCP connection (through the proxy if any)
+    /// takes longer than this, instead of the `connection_timeout` from the URI
+    pub fn with_connection_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_timeout = Some(timeout);
+        self
+    }
+
+    /// Fail with `Error::Timeout` if the TLS handshake and the AMQP start/tune/open exchange
+    /// take longer than this. Defaults to the connection timeout
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// Tune the TCP socket before going through the AMQP handshake
+    pub fn with_socket_options(mut self, options: SocketOptions) -> Self {
+        self.socket_options = Some(options);
+        self
+    }
+
+    /// Drive the io loop from a dedicated thread (the default) or as a task on the executor,
+    /// woken up by the reactor
+    pub fn with_io_loop_mode(mut self, mode: IoLoopMode) -> Self {
+        self.io_loop_mode = mode;
+        self
+    }
+
+    /// The connection timeout to apply to this endpoint, ours or else the one from its URI
+    pub(crate) fn connection_timeout(&self, uri: &AMQPUri) -> Option<Duration> {
+        self.connection_timeout
+            .or_else(|| uri.query.connection_timeout.map(Duration::from_millis))
+    }
+
+    /// A Stream of the lifecycle events of the connections established with these properties,
+    /// starting with the handshake of the first one
+    pub fn events(&self) -> ConnectionEvents {
+        ConnectionEvents::new(self.event_listeners.subscribe())
+    }
 }
@@ -1,20 +1,57 @@
 use crate::{
-    auth::{Credentials, SASLMechanism},
-    Connection, ConnectionProperties, PromiseResolver,
+    auth::Credentials,
+    blocked_publish_policy::BlockedPublishPolicy,
+    connection_events::{ConnectionEvent, ConnectionEventListeners},
+    reactor::ReactorHandle,
+    sasl::{SaslExchange, SaslMechanism},
+    uri::AMQPUri,
+    Connection, ConnectionProperties, Error, Promise, PromiseResolver, Result,
 };
+use async_io::Timer;
 use parking_lot::Mutex;
-use std::{fmt, sync::Arc};
+use std::{fmt, future::Future, pin::Pin, sync::Arc, time::Duration};
 
 #[derive(Clone, Default)]
 pub struct ConnectionStatus(Arc<Mutex<Inner>>);
 
+/// What a publish has to do while the connection may be blocked
+#[derive(Debug)]
+pub(crate) enum BlockedPublish {
+    /// Go ahead
+    Send,
+    /// Go ahead, the payload counts against `BlockedPublishPolicy::Buffer` until it's written
+    Buffer(BufferedPublish),
+    /// Wait for the connection to be unblocked first
+    Wait(Promise<()>),
+}
+
+/// Payload queued while the connection is blocked, released from the buffer on drop
+#[derive(Debug)]
+pub(crate) struct BufferedPublish {
+    status: ConnectionStatus,
+    size: usize,
+}
+
+impl Drop for BufferedPublish {
+    fn drop(&mut self) {
+        let mut inner = self.status.0.lock();
+        inner.blocked_publish_bytes = inner.blocked_publish_bytes.saturating_sub(self.size);
+    }
+}
+
 impl ConnectionStatus {
     pub fn state(&self) -> ConnectionState {
         self.0.lock().state.clone()
     }
 
     pub(crate) fn set_state(&self, state: ConnectionState) {
-        self.0.lock().state = state;
+        let mut inner = self.0.lock();
+        if let ConnectionState::Closing | ConnectionState::Closed | ConnectionState::Error = state {
+            for waiter in inner.unblock_waiters.drain(..) {
+                waiter.swear(Err(Error::InvalidConnectionState(state.clone())));
+            }
+        }
+        inner.state = state;
     }
 
     pub(crate) fn connection_step(&self) -> Option<ConnectionStep> {
@@ -35,16 +72,58 @@ impl ConnectionStatus {
         self.0.lock().vhost.clone()
     }
 
-    pub(crate) fn set_vhost(&self, vhost: &str) {
-        self.0.lock().vhost = vhost.into();
-    }
-
     pub fn username(&self) -> String {
         self.0.lock().username.clone()
     }
 
-    pub(crate) fn set_username(&self, username: &str) {
-        self.0.lock().username = username.into();
+    /// The host of the node we're connected to
+    pub fn host(&self) -> String {
+        self.0.lock().host.clone()
+    }
+
+    /// The port of the node we're connected to
+    pub fn port(&self) -> u16 {
+        self.0.lock().port
+    }
+
+    pub(crate) fn set_endpoint(&self, uri: &AMQPUri) {
+        let mut inner = self.0.lock();
+        inner.host = uri.authority.host.clone();
+        inner.port = uri.authority.port;
+        inner.vhost = uri.vhost.clone();
+        inner.username = uri.authority.userinfo.username.clone();
+    }
+
+    pub(crate) fn subscribe(&self) -> flume::Receiver<ConnectionEvent> {
+        self.0.lock().event_listeners.subscribe()
+    }
+
+    /// Also forward the events to the listeners of the `ConnectionProperties`
+    pub(crate) fn set_properties_event_listeners(&self, listeners: ConnectionEventListeners) {
+        self.0.lock().properties_event_listeners = Some(listeners);
+    }
+
+    pub(crate) fn emit(&self, event: ConnectionEvent) {
+        let inner = self.0.lock();
+        if let Some(listeners) = inner.properties_event_listeners.as_ref() {
+            listeners.emit(event.clone());
+        }
+        inner.event_listeners.emit(event);
+    }
+
+    /// Use the timers of this reactor, the one the connection is currently registered on
+    pub(crate) fn set_timers(&self, timers: Box<dyn ReactorHandle + Send>) {
+        self.0.lock().timers = Some(timers);
+    }
+
+    /// Complete after `duration`, using the timers of the connection's reactor
+    pub(crate) fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        match self.0.lock().timers.as_ref() {
+            Some(timers) => timers.sleep(duration),
+            None => Box::pin(async move {
+                Timer::after(duration).await;
+            }),
+        }
     }
 
     pub(crate) fn block(&self) {
@@ -52,7 +131,43 @@ impl ConnectionStatus {
     }
 
     pub(crate) fn unblock(&self) {
-        self.0.lock().blocked = false;
+        let mut inner = self.0.lock();
+        inner.blocked = false;
+        for waiter in inner.unblock_waiters.drain(..) {
+            waiter.swear(Ok(()));
+        }
+    }
+
+    pub(crate) fn set_blocked_publish_policy(&self, policy: BlockedPublishPolicy) {
+        self.0.lock().blocked_publish_policy = policy;
+    }
+
+    /// Apply the BlockedPublishPolicy to a publish of that size
+    pub(crate) fn check_blocked_publish(&self, size: usize) -> Result<BlockedPublish> {
+        let mut inner = self.0.lock();
+        if !inner.blocked {
+            return Ok(BlockedPublish::Send);
+        }
+        match inner.blocked_publish_policy {
+            BlockedPublishPolicy::Queue => Ok(BlockedPublish::Send),
+            BlockedPublishPolicy::Wait => {
+                let (promise, resolver) = Promise::new();
+                inner.unblock_waiters.push(resolver);
+                Ok(BlockedPublish::Wait(promise))
+            }
+            BlockedPublishPolicy::Fail => Err(Error::ConnectionBlocked),
+            BlockedPublishPolicy::Buffer(max) => {
+                if inner.blocked_publish_bytes + size > max {
+                    Err(Error::ConnectionBlocked)
+                } else {
+                    inner.blocked_publish_bytes += size;
+                    Ok(BlockedPublish::Buffer(BufferedPublish {
+                        status: self.clone(),
+                        size,
+                    }))
+                }
+            }
+        }
     }
 
     pub fn blocked(&self) -> bool {
@@ -75,6 +190,14 @@ impl ConnectionStatus {
         self.0.lock().state == ConnectionState::Error
     }
 
+    pub fn reconnecting(&self) -> bool {
+        self.0.lock().state == ConnectionState::Reconnecting
+    }
+
+    pub(crate) fn connecting(&self) -> bool {
+        self.0.lock().connecting()
+    }
+
     pub(crate) fn auto_close(&self) -> bool {
         [ConnectionState::Connecting, ConnectionState::Connected].contains(&self.0.lock().state)
     }
@@ -86,18 +209,24 @@ pub(crate) enum ConnectionStep {
         PromiseResolver<Connection>,
         Connection,
         Credentials,
-        SASLMechanism,
+        Arc<dyn SaslMechanism>,
         ConnectionProperties,
     ),
-    StartOk(PromiseResolver<Connection>, Connection, Credentials),
+    StartOk(
+        PromiseResolver<Connection>,
+        Connection,
+        Box<dyn SaslExchange>,
+    ),
     Open(PromiseResolver<Connection>),
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum ConnectionState {
     Initial,
     Connecting,
     Connected,
+    Reconnecting,
     Closing,
     Closed,
     Error,
@@ -117,7 +246,10 @@ impl fmt::Debug for ConnectionStatus {
                 .field("state", &inner.state)
                 .field("vhost", &inner.vhost)
                 .field("username", &inner.username)
-                .field("blocked", &inner.blocked);
+                .field("host", &inner.host)
+                .field("port", &inner.port)
+                .field("blocked", &inner.blocked)
+                .field("blocked_publish_policy", &inner.blocked_publish_policy);
         }
         debug.finish()
     }
@@ -128,7 +260,15 @@ struct Inner {
     state: ConnectionState,
     vhost: String,
     username: String,
+    host: String,
+    port: u16,
     blocked: bool,
+    blocked_publish_policy: BlockedPublishPolicy,
+    blocked_publish_bytes: usize,
+    unblock_waiters: Vec<PromiseResolver<()>>,
+    event_listeners: ConnectionEventListeners,
+    properties_event_listeners: Option<ConnectionEventListeners>,
+    timers: Option<Box<dyn ReactorHandle + Send>>,
 }
 
 impl Default for Inner {
@@ -138,14 +278,26 @@ impl Default for Inner {
             state: ConnectionState::default(),
             vhost: "/".into(),
             username: "guest".into(),
+            host: "localhost".into(),
+            port: 5672,
             blocked: false,
+            blocked_publish_policy: BlockedPublishPolicy::default(),
+            blocked_publish_bytes: 0,
+            unblock_waiters: Vec::default(),
+            event_listeners: ConnectionEventListeners::default(),
+            properties_event_listeners: None,
+            timers: None,
         }
     }
 }
 
 impl Inner {
+    fn connecting(&self) -> bool {
+        [ConnectionState::Connecting, ConnectionState::Reconnecting].contains(&self.state)
+    }
+
     fn connection_resolver(&mut self) -> Option<(PromiseResolver<Connection>, Option<Connection>)> {
-        if let ConnectionState::Connecting = self.state {
+        if self.connecting() {
             self.connection_step
                 .take()
                 .map(|connection_step| match connection_step {
@@ -162,3 +314,51 @@ impl Inner {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocked_publish_policies() {
+        let status = ConnectionStatus::default();
+        assert!(matches!(
+            status.check_blocked_publish(10).unwrap(),
+            BlockedPublish::Send
+        ));
+        status.block();
+        assert!(matches!(
+            status.check_blocked_publish(10).unwrap(),
+            BlockedPublish::Send
+        ));
+
+        status.set_blocked_publish_policy(BlockedPublishPolicy::Fail);
+        assert_eq!(
+            status.check_blocked_publish(10).unwrap_err(),
+            Error::ConnectionBlocked
+        );
+
+        status.set_blocked_publish_policy(BlockedPublishPolicy::Buffer(15));
+        let buffered = status.check_blocked_publish(10).unwrap();
+        assert!(matches!(buffered, BlockedPublish::Buffer(_)));
+        assert!(status.check_blocked_publish(10).is_err());
+        // Unblocking doesn't make room, writing the queued payload does
+        status.unblock();
+        status.block();
+        assert!(status.check_blocked_publish(10).is_err());
+        drop(buffered);
+        assert!(matches!(
+            status.check_blocked_publish(10).unwrap(),
+            BlockedPublish::Buffer(_)
+        ));
+
+        status.set_blocked_publish_policy(BlockedPublishPolicy::Wait);
+        let promise = match status.check_blocked_publish(10).unwrap() {
+            BlockedPublish::Wait(promise) => promise,
+            _ => panic!("should wait for the connection to be unblocked"),
+        };
+        assert!(promise.try_wait().is_none());
+        status.unblock();
+        assert!(matches!(promise.try_wait(), Some(Ok(()))));
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{
+    auth::Credentials, connection::WeakConnection, executor::Executor, reactor::ReactorHandle,
+    uri::AMQPUri, Result,
+};
+use flume::{Receiver, Sender};
+use futures_lite::future;
+use std::{
+    fmt, fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{debug, error};
+
+/// Never poll a provider more often than that, even if its secret is about to expire
+const MIN_REFRESH_DELAY: Duration = Duration::from_secs(1);
+
+/// A password or token along with the time after which the server won't accept it anymore
+#[derive(Clone, Debug, PartialEq)]
+pub struct Secret {
+    pub value: String,
+    pub expires_at: Option<Instant>,
+}
+
+/// Supply the password used to authenticate, typically an OAuth2 token.
+///
+/// The provider is queried when connecting and then polled ahead of the secret's expiry, after
+/// which the new secret is sent to the server through `connection.update-secret`.
+/// Refresh failures are reported through [`Connection::on_error`].
+///
+/// [`Connection::on_error`]: ./struct.Connection.html#method.on_error
+pub trait CredentialsProvider: fmt::Debug + Send + Sync {
+    /// Fetch the current secret
+    fn secret(&self) -> Result<Secret>;
+
+    /// How long before the secret's expiry we should fetch a new one
+    fn refresh_margin(&self) -> Duration {
+        Duration::from_secs(60)
+    }
+}
+
+/// Read the secret from a file, re-reading it periodically to pick up rotated tokens.
+///
+/// This is how tokens are usually exposed to Kubernetes pods through projected volumes.
+#[derive(Clone, Debug)]
+pub struct FileCredentialsProvider {
+    path: PathBuf,
+    poll_interval: Duration,
+}
+
+impl FileCredentialsProvider {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            poll_interval: Duration::from_secs(30),
+        }
+    }
+
+    /// How often we should re-read the file
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+}
+
+impl CredentialsProvider for FileCredentialsProvider {
+    fn secret(&self) -> Result<Secret> {
+        Ok(Secret {
+            value: fs::read_to_string(&self.path)?.trim().into(),
+            expires_at: Some(Instant::now() + self.poll_interval),
+        })
+    }
+
+    fn refresh_margin(&self) -> Duration {
+        Duration::default()
+    }
+}
+
+/// The credentials from the URI, with the password coming from the provider if any
+pub(crate) fn credentials(
+    uri: &AMQPUri,
+    provider: Option<&Arc<dyn CredentialsProvider>>,
+) -> Result<(Credentials, Option<Secret>)> {
+    let username = uri.authority.userinfo.username.clone();
+    Ok(match provider {
+        Some(provider) => {
+            let secret = provider.secret()?;
+            (
+                Credentials::new(username, secret.value.clone()),
+                Some(secret),
+            )
+        }
+        None => (uri.authority.userinfo.clone().into(), None),
+    })
+}
+
+fn refresh_delay(secret: &Secret, margin: Duration) -> Option<Duration> {
+    secret.expires_at.map(|expires_at| {
+        expires_at
+            .checked_sub(margin)
+            .map(|refresh_at| refresh_at.saturating_duration_since(Instant::now()))
+            .unwrap_or_default()
+            .max(MIN_REFRESH_DELAY)
+    })
+}
+
+/// The secret a connection authenticated with, to be kept up to date
+pub(crate) struct SecretRefresh {
+    pub(crate) connection: WeakConnection,
+    pub(crate) provider: Arc<dyn CredentialsProvider>,
+    pub(crate) secret: Secret,
+}
+
+/// Spawn the task refreshing the secret, waiting with the timers of the connection's reactor.
+/// It stops once the returned Sender is dropped.
+pub(crate) fn spawn_refresh(
+    executor: &dyn Executor,
+    refresh: SecretRefresh,
+    timers: Box<dyn ReactorHandle + Send>,
+) -> Sender<()> {
+    let (stop, stopped) = flume::bounded(0);
+    executor.spawn(Box::pin(refresh_secret(refresh, timers, stopped)));
+    stop
+}
+
+/// Keep the secret known by the server up to date for as long as the connection lives
+async fn refresh_secret(
+    refresh: SecretRefresh,
+    timers: Box<dyn ReactorHandle + Send>,
+    stopped: Receiver<()>,
+) {
+    let SecretRefresh {
+        connection,
+        provider,
+        mut secret,
+    } = refresh;
+    while let Some(delay) = refresh_delay(&secret, provider.refresh_margin()) {
+        let sleep = timers.sleep(delay);
+        let stop = future::or(
+            async move {
+                sleep.await;
+                false
+            },
+            async {
+                let _ = stopped.recv_async().await;
+                true
+            },
+        )
+        .await;
+        if stop {
+            // A recovered connection refreshes the secret it authenticated with instead
+            return;
+        }
+        let connection = match connection.upgrade() {
+            Some(connection) => connection,
+            None => return,
+        };
+        if connection.status().closed() || connection.status().errored() {
+            return;
+        }
+        if !connection.status().connected() {
+            // The handshake isn't over yet
+            secret.expires_at = Some(Instant::now() + MIN_REFRESH_DELAY);
+            continue;
+        }
+        let fetcher = provider.clone();
+        let result = match blocking::unblock(move || fetcher.secret()).await {
+            Ok(new_secret) if new_secret.value == secret.value => Ok(new_secret),
+            Ok(new_secret) => {
+                debug!("Sending refreshed secret to the server");
+                connection
+                    .update_secret(&new_secret.value, "credentials refresh")
+                    .await
+                    .map(|()| new_secret)
+            }
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(new_secret) => secret = new_secret,
+            Err(err) => {
+                error!(%err, "Failed to refresh the secret");
+                connection.report_error(err);
+                // Try again soon
+                secret.expires_at = Some(Instant::now() + MIN_REFRESH_DELAY);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh_ahead_of_expiry() {
+        let never = Secret {
+            value: "token".into(),
+            expires_at: None,
+        };
+        assert_eq!(refresh_delay(&never, Duration::from_secs(60)), None);
+
+        let secret = Secret {
+            value: "token".into(),
+            expires_at: Some(Instant::now() + Duration::from_secs(600)),
+        };
+        let delay = refresh_delay(&secret, Duration::from_secs(60)).unwrap();
+        assert!(delay <= Duration::from_secs(540));
+        assert!(delay > Duration::from_secs(530));
+
+        let expired = Secret {
+            value: "token".into(),
+            expires_at: Some(Instant::now()),
+        };
+        assert_eq!(
+            refresh_delay(&expired, Duration::from_secs(60)),
+            Some(MIN_REFRESH_DELAY)
+        );
+    }
+
+    #[test]
+    fn file_provider_reads_trimmed_token() {
+        let path = std::env::temp_dir().join(format!("lapin-token-{}", std::process::id()));
+        fs::write(&path, "my-token\n").unwrap();
+        let provider = FileCredentialsProvider::new(&path);
+        assert_eq!(provider.secret().unwrap().value, "my-token");
+        fs::write(&path, "rotated-token").unwrap();
+        assert_eq!(provider.secret().unwrap().value, "rotated-token");
+        fs::remove_file(&path).unwrap();
+        assert!(provider.secret().is_err());
+    }
+}
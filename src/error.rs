@@ -15,16 +15,21 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[non_exhaustive]
 pub enum Error {
     ChannelsLimitReached,
+    ConnectionBlocked,
     InvalidProtocolVersion(ProtocolVersion),
 
     InvalidChannel(u16),
     InvalidChannelState(ChannelState),
     InvalidConnectionState(ConnectionState),
+    InvalidDefinitions(String),
+    MissingHeartbeat,
 
+    AuthenticationError(String),
     IOError(Arc<io::Error>),
     ParsingError(ParserError),
     ProtocolError(AMQPError),
     SerialisationError(Arc<GenError>),
+    Timeout,
 }
 
 impl Error {
@@ -52,6 +57,10 @@ impl fmt::Display for Error {
                 f,
                 "the maximum number of channels for this connection has been reached"
             ),
+            Error::ConnectionBlocked => write!(
+                f,
+                "the connection is blocked by the server, refusing to publish"
+            ),
             Error::InvalidProtocolVersion(version) => {
                 write!(f, "the server only supports AMQP {}", version)
             }
@@ -61,11 +70,17 @@ impl fmt::Display for Error {
             Error::InvalidConnectionState(state) => {
                 write!(f, "invalid connection state: {:?}", state)
             }
+            Error::InvalidDefinitions(e) => write!(f, "invalid definitions: {}", e),
+            Error::MissingHeartbeat => {
+                write!(f, "no heartbeat received from the server in two intervals")
+            }
 
+            Error::AuthenticationError(e) => write!(f, "authentication error: {}", e),
             Error::IOError(e) => write!(f, "IO error: {}", e),
             Error::ParsingError(e) => write!(f, "failed to parse: {}", e),
             Error::ProtocolError(e) => write!(f, "protocol error: {}", e),
             Error::SerialisationError(e) => write!(f, "failed to serialise: {}", e),
+            Error::Timeout => write!(f, "timed out"),
         }
     }
 }
@@ -95,6 +110,7 @@ impl PartialEq for Error {
 
         match (self, other) {
             (ChannelsLimitReached, ChannelsLimitReached) => true,
+            (ConnectionBlocked, ConnectionBlocked) => true,
             (InvalidProtocolVersion(left_inner), InvalidProtocolVersion(right_version)) => {
                 left_inner == right_version
             }
@@ -106,7 +122,14 @@ impl PartialEq for Error {
             (InvalidConnectionState(left_inner), InvalidConnectionState(right_inner)) => {
                 left_inner == right_inner
             }
+            (InvalidDefinitions(left_inner), InvalidDefinitions(right_inner)) => {
+                left_inner == right_inner
+            }
+            (MissingHeartbeat, MissingHeartbeat) => true,
 
+            (AuthenticationError(left_inner), AuthenticationError(right_inner)) => {
+                left_inner == right_inner
+            }
             (IOError(_), IOError(_)) => {
                 error!("Unable to compare lapin::Error::IOError");
                 false
@@ -117,6 +140,7 @@ impl PartialEq for Error {
                 error!("Unable to compare lapin::Error::SerialisationError");
                 false
             }
+            (Timeout, Timeout) => true,
 
             _ => false,
         }
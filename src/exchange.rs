@@ -1,4 +1,12 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize, Serialize),
+    serde(from = "String", into = "String")
+)]
 pub enum ExchangeKind {
     Custom(String),
     Direct,
@@ -24,3 +32,27 @@ impl ExchangeKind {
         }
     }
 }
+
+impl From<&str> for ExchangeKind {
+    fn from(kind: &str) -> Self {
+        match kind {
+            "direct" => Self::Direct,
+            "fanout" => Self::Fanout,
+            "headers" => Self::Headers,
+            "topic" => Self::Topic,
+            custom => Self::Custom(custom.into()),
+        }
+    }
+}
+
+impl From<String> for ExchangeKind {
+    fn from(kind: String) -> Self {
+        kind.as_str().into()
+    }
+}
+
+impl From<ExchangeKind> for String {
+    fn from(kind: ExchangeKind) -> Self {
+        kind.kind().into()
+    }
+}
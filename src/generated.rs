@@ -313,7 +313,7 @@ impl Channel {
         locale: &str,
         resolver: PromiseResolver<Connection>,
         connection: Connection,
-        credentials: Credentials,
+        sasl: Box<dyn SaslExchange>,
     ) -> Result<()> {
         if !self.status.connected() {
             return Err(Error::InvalidChannelState(self.status.state()));
@@ -333,7 +333,7 @@ impl Channel {
             promise.set_marker("connection.start-ok".into());
         }
         self.send_method_frame(method, send_resolver, None);
-        self.on_connection_start_ok_sent(resolver, connection, credentials);
+        self.on_connection_start_ok_sent(resolver, connection, sasl);
         promise.await
     }
 
@@ -1003,8 +1003,7 @@ impl Channel {
         }
     }
     #[allow(clippy::too_many_arguments)]
-    /// Delete an exchange
-    pub async fn exchange_delete(
+    async fn do_exchange_delete(
         &self,
         exchange: &str,
         options: ExchangeDeleteOptions,
@@ -1064,7 +1063,7 @@ impl Channel {
         }
     }
     #[allow(clippy::too_many_arguments)]
-    pub async fn exchange_bind(
+    async fn do_exchange_bind(
         &self,
         destination: &str,
         source: &str,
@@ -1129,7 +1128,7 @@ impl Channel {
         }
     }
     #[allow(clippy::too_many_arguments)]
-    pub async fn exchange_unbind(
+    async fn do_exchange_unbind(
         &self,
         destination: &str,
         source: &str,
@@ -1194,7 +1193,7 @@ impl Channel {
         }
     }
     #[allow(clippy::too_many_arguments)]
-    pub async fn queue_declare(
+    async fn do_queue_declare(
         &self,
         queue: &str,
         options: QueueDeclareOptions,
@@ -1269,7 +1268,7 @@ impl Channel {
         }
     }
     #[allow(clippy::too_many_arguments)]
-    pub async fn queue_bind(
+    async fn do_queue_bind(
         &self,
         queue: &str,
         exchange: &str,
@@ -1438,7 +1437,7 @@ impl Channel {
         }
     }
     #[allow(clippy::too_many_arguments)]
-    pub async fn queue_unbind(
+    async fn do_queue_unbind(
         &self,
         queue: &str,
         exchange: &str,
@@ -1496,7 +1495,7 @@ impl Channel {
         }
     }
     #[allow(clippy::too_many_arguments)]
-    pub async fn basic_qos(
+    async fn do_basic_qos(
         &self,
         prefetch_count: ShortUInt,
         options: BasicQosOptions,
@@ -1550,7 +1549,7 @@ impl Channel {
         }
     }
     #[allow(clippy::too_many_arguments)]
-    pub async fn basic_consume(
+    async fn do_basic_consume(
         &self,
         queue: &str,
         consumer_tag: &str,
@@ -1711,7 +1710,7 @@ impl Channel {
         }
     }
     #[allow(clippy::too_many_arguments)]
-    pub async fn basic_publish(
+    async fn do_basic_publish(
         &self,
         exchange: &str,
         routing_key: &str,
@@ -2009,7 +2008,7 @@ impl Channel {
 
         match self.frames.next_expected_reply(self.id) {
             Some(Reply::TxSelectOk(resolver)) => {
-                let res = Ok(());
+                let res = self.on_tx_select_ok_received();
                 resolver.swear(res.clone());
                 res
             }
@@ -1,4 +1,4 @@
-use crate::channels::Channels;
+use crate::{channels::Channels, socket_state::SocketStateHandle};
 use parking_lot::Mutex;
 use std::{
     fmt,
@@ -9,17 +9,24 @@ use std::{
 #[derive(Clone)]
 pub struct Heartbeat {
     channels: Channels,
+    waker: SocketStateHandle,
     inner: Arc<Mutex<Inner>>,
 }
 
 impl Heartbeat {
-    pub(crate) fn new(channels: Channels) -> Self {
+    pub(crate) fn new(channels: Channels, waker: SocketStateHandle) -> Self {
         let inner = Default::default();
-        Self { channels, inner }
+        Self {
+            channels,
+            waker,
+            inner,
+        }
     }
 
     pub(crate) fn set_timeout(&self, timeout: Duration) {
-        self.inner.lock().timeout = Some(timeout);
+        let mut inner = self.inner.lock();
+        inner.timeout = Some(timeout);
+        inner.update_last_read();
     }
 
     pub fn get_heartbeat(&self) -> Option<Duration> {
@@ -27,7 +34,12 @@ impl Heartbeat {
     }
 
     pub fn poll_timeout(&self) -> Option<Duration> {
-        self.inner.lock().poll_timeout(&self.channels)
+        let mut inner = self.inner.lock();
+        if inner.server_gone() {
+            // Let the io loop close the connection
+            self.waker.wake();
+        }
+        inner.poll_timeout(&self.channels)
     }
 
     pub fn send(&self) {
@@ -38,6 +50,15 @@ impl Heartbeat {
         self.inner.lock().update_last_write();
     }
 
+    pub(crate) fn update_last_read(&self) {
+        self.inner.lock().update_last_read();
+    }
+
+    /// Whether the server has been silent for two whole heartbeat intervals
+    pub(crate) fn server_gone(&self) -> bool {
+        self.inner.lock().server_gone()
+    }
+
     pub(crate) fn cancel(&self) {
         self.inner.lock().timeout = None;
     }
@@ -51,6 +72,7 @@ impl fmt::Debug for Heartbeat {
 
 struct Inner {
     last_write: Instant,
+    last_read: Instant,
     timeout: Option<Duration>,
 }
 
@@ -58,6 +80,7 @@ impl Default for Inner {
     fn default() -> Self {
         Self {
             last_write: Instant::now(),
+            last_read: Instant::now(),
             timeout: None,
         }
     }
@@ -81,4 +104,39 @@ impl Inner {
     fn update_last_write(&mut self) {
         self.last_write = Instant::now();
     }
+
+    fn update_last_read(&mut self) {
+        self.last_read = Instant::now();
+    }
+
+    fn server_gone(&self) -> bool {
+        self.timeout
+            .map_or(false, |timeout| self.last_read.elapsed() >= timeout * 4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missed_heartbeats() {
+        let mut inner = Inner {
+            timeout: Some(Duration::from_secs(5)),
+            ..Inner::default()
+        };
+        assert!(!inner.server_gone());
+
+        inner.last_read = Instant::now() - Duration::from_secs(11);
+        assert!(!inner.server_gone());
+
+        inner.last_read = Instant::now() - Duration::from_secs(21);
+        assert!(inner.server_gone());
+
+        inner.update_last_read();
+        assert!(!inner.server_gone());
+        inner.timeout = None;
+        inner.last_read = Instant::now() - Duration::from_secs(60);
+        assert!(!inner.server_gone());
+    }
 }
@@ -16,6 +16,10 @@ impl<T: Default + Copy + AddAssign<T> + PartialEq<T> + PartialOrd<T> + From<u8>>
     pub(crate) fn set_max(&self, max: T) {
         self.0.lock().set_max(max)
     }
+
+    pub(crate) fn reset(&self) {
+        self.0.lock().id = T::default();
+    }
 }
 
 impl<T: fmt::Debug> fmt::Debug for IdSequence<T> {
@@ -1,35 +1,60 @@
 use crate::{
+    auth::Credentials,
     buffer::Buffer,
     channels::Channels,
-    connection_status::ConnectionState,
+    connection::Connection,
+    connection_events::ConnectionEvent,
+    connection_status::{ConnectionState, ConnectionStep},
+    credentials_provider::{self, SecretRefresh},
     executor::Executor,
     frames::Frames,
     heartbeat::Heartbeat,
     internal_rpc::InternalRPCHandle,
     protocol::{self, AMQPError, AMQPHardError},
-    reactor::{ReactorBuilder, ReactorHandle, Slot},
+    reactor::{Reactor, ReactorBuilder, ReactorHandle, Slot},
+    recovery::{self, Reconnection, Recovery},
+    sasl,
     socket_state::SocketState,
-    tcp::HandshakeResult,
     thread::ThreadHandle,
-    Configuration, ConnectionStatus, Error, PromiseResolver, Result, TcpStream,
+    transport::Transport,
+    uri::AMQPUri,
+    Configuration, ConnectionStatus, Error, Promise, PromiseResolver, Result,
 };
-use amq_protocol::frame::{gen_frame, parse_frame, AMQPFrame, GenError};
+use amq_protocol::frame::{gen_frame, parse_frame, AMQPFrame, GenError, ProtocolVersion};
+use flume::{Receiver, Sender, TryRecvError};
 use std::{
     collections::VecDeque,
-    convert::TryFrom,
     io::{self, Write},
     sync::Arc,
     thread::Builder as ThreadBuilder,
     time::Duration,
 };
-use tracing::{debug, error, trace};
+use tracing::{debug, error, level_enabled, trace, Level};
 
 const FRAMES_STORAGE: usize = 32;
 
+/// How the io loop of a connection is driven
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoLoopMode {
+    /// A dedicated "lapin-io-loop" thread per connection, blocking while waiting for socket
+    /// events
+    Thread,
+    /// A task spawned on the executor, waiting for the reactor's socket events asynchronously
+    Async,
+}
+
+impl Default for IoLoopMode {
+    fn default() -> Self {
+        IoLoopMode::Thread
+    }
+}
+
 #[derive(Debug, PartialEq)]
 enum Status {
     Initial,
     Connected,
+    /// The socket is gone, waiting for the recovery task to reach the server again
+    Reconnecting,
     Stop,
 }
 
@@ -42,14 +67,21 @@ pub struct IoLoop {
     heartbeat: Heartbeat,
     socket_state: SocketState,
     reactor: Box<dyn ReactorHandle + Send>,
+    executor: Arc<dyn Executor>,
     connection_io_loop_handle: ThreadHandle,
-    stream: TcpStream,
+    stream: Box<dyn Transport>,
     slot: Slot,
     status: Status,
     frame_size: usize,
     receive_buffer: Buffer,
     send_buffer: Buffer,
     serialized_frames: VecDeque<(u64, Option<PromiseResolver<()>>)>,
+    recovery: Option<Recovery>,
+    reconnection: Option<Receiver<Result<Reconnection>>>,
+    /// Dropped to stop refreshing the secret of the previous connection
+    secret_refresh: Option<Sender<()>>,
+    /// The server closed the socket, we stop reading once we handled what it sent before
+    eof: bool,
 }
 
 impl IoLoop {
@@ -62,19 +94,24 @@ impl IoLoop {
         frames: Frames,
         socket_state: SocketState,
         connection_io_loop_handle: ThreadHandle,
-        stream: HandshakeResult,
+        stream: Box<dyn Transport>,
         reactor_builder: &dyn ReactorBuilder,
         executor: Arc<dyn Executor>,
+        recovery: Option<Recovery>,
+        secret_refresh: Option<SecretRefresh>,
     ) -> Result<Self> {
-        let mut stream = TcpStream::try_from(stream)?;
-        let heartbeat = Heartbeat::new(channels.clone());
-        let mut reactor = reactor_builder.build(heartbeat.clone(), executor);
+        let heartbeat = Heartbeat::new(channels.clone(), socket_state.handle());
+        let mut reactor = reactor_builder.build(heartbeat.clone(), executor.clone());
         let reactor_handle = reactor.handle();
+        connection_status.set_timers(reactor.handle());
         let frame_size = std::cmp::max(
             protocol::constants::FRAME_MIN_SIZE as usize,
             configuration.frame_max() as usize,
         );
-        let slot = reactor.register(stream.inner_mut(), socket_state.handle())?;
+        let slot = reactor.register(stream.socket(), socket_state.handle())?;
+        let secret_refresh = secret_refresh.map(|refresh| {
+            credentials_provider::spawn_refresh(&*executor, refresh, reactor.handle())
+        });
 
         Ok(Self {
             connection_status,
@@ -85,6 +122,7 @@ impl IoLoop {
             heartbeat,
             socket_state,
             reactor: reactor_handle,
+            executor,
             connection_io_loop_handle,
             stream,
             slot,
@@ -93,6 +131,10 @@ impl IoLoop {
             receive_buffer: Buffer::with_capacity(FRAMES_STORAGE * frame_size),
             send_buffer: Buffer::with_capacity(FRAMES_STORAGE * frame_size),
             serialized_frames: VecDeque::default(),
+            recovery,
+            reconnection: None,
+            secret_refresh,
+            eof: false,
         })
     }
 
@@ -108,7 +150,7 @@ impl IoLoop {
                 self.heartbeat.set_timeout(heartbeat);
                 self.reactor.start_heartbeat();
             }
-            let peer = self.stream.inner().peer_addr()?;
+            let peer = self.stream.peer().unwrap_or_else(|| "unknown".into());
             debug!(%peer, "Connected");
             self.status = Status::Connected;
         }
@@ -119,6 +161,7 @@ impl IoLoop {
         match self.status {
             Status::Initial => self.finish_setup(),
             Status::Connected => Ok(true),
+            Status::Reconnecting => self.check_reconnection(),
             Status::Stop => Ok(false),
         }
     }
@@ -130,11 +173,17 @@ impl IoLoop {
     }
 
     fn can_write(&mut self) -> bool {
-        self.socket_state.writable() && self.has_data() && !self.connection_status.blocked()
+        self.socket_state.writable()
+            && self.has_data()
+            && !self.connection_status.blocked()
+            && self.status != Status::Reconnecting
     }
 
     fn can_read(&mut self) -> bool {
-        self.socket_state.readable() && self.receive_buffer.available_space() > 0
+        self.socket_state.readable()
+            && self.receive_buffer.available_space() > 0
+            && !self.eof
+            && self.status != Status::Reconnecting
     }
 
     fn can_parse(&self) -> bool {
@@ -149,8 +198,17 @@ impl IoLoop {
             && !self.connection_status.errored()
     }
 
-    pub fn start(mut self) -> Result<()> {
+    pub(crate) fn start(self, mode: IoLoopMode) -> Result<()> {
         let waker = self.socket_state.handle();
+        match mode {
+            IoLoopMode::Thread => self.start_thread()?,
+            IoLoopMode::Async => self.start_task(),
+        }
+        waker.wake();
+        Ok(())
+    }
+
+    fn start_thread(mut self) -> Result<()> {
         let handle = self.connection_io_loop_handle.clone();
         handle.register(
             ThreadBuilder::new()
@@ -161,15 +219,36 @@ impl IoLoop {
                             self.critical_error(err)?;
                         }
                     }
-                    self.internal_rpc.stop();
-                    self.heartbeat.cancel();
+                    self.stop();
                     Ok(())
                 })?,
         );
-        waker.wake();
         Ok(())
     }
 
+    fn start_task(self) {
+        let done = self.connection_io_loop_handle.register_task();
+        let executor = self.executor.clone();
+        executor.spawn(Box::pin(async move {
+            let _ = done.send(self.run_until_stopped().await);
+        }));
+    }
+
+    async fn run_until_stopped(mut self) -> Result<()> {
+        while self.should_continue() {
+            if let Err(err) = self.run_async().await {
+                self.critical_error(err)?;
+            }
+        }
+        self.stop();
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.internal_rpc.stop();
+        self.heartbeat.cancel();
+    }
+
     fn poll_socket_events(&mut self) {
         self.socket_state.poll_events();
     }
@@ -181,10 +260,34 @@ impl IoLoop {
     }
 
     fn run(&mut self) -> Result<()> {
+        if self.prepare()? {
+            if self.should_wait() {
+                self.socket_state.wait();
+            }
+            self.process()?;
+        } else if self.status == Status::Reconnecting {
+            self.socket_state.wait();
+        }
+        Ok(())
+    }
+
+    async fn run_async(&mut self) -> Result<()> {
+        if self.prepare()? {
+            if self.should_wait() {
+                self.socket_state.wait_async().await;
+            }
+            self.process()?;
+        } else if self.status == Status::Reconnecting {
+            self.socket_state.wait_async().await;
+        }
+        Ok(())
+    }
+
+    fn prepare(&mut self) -> Result<bool> {
         trace!("io_loop run");
         self.poll_socket_events();
         if !self.ensure_setup()? {
-            return Ok(());
+            return Ok(false);
         }
         self.check_connection_state();
         trace!(
@@ -193,9 +296,14 @@ impl IoLoop {
             has_data=%self.has_data(),
             "io_loop do_run",
         );
-        if !self.can_read() && !self.can_write() && self.should_continue() {
-            self.socket_state.wait();
-        }
+        Ok(true)
+    }
+
+    fn should_wait(&mut self) -> bool {
+        !self.can_read() && !self.can_write() && self.should_continue()
+    }
+
+    fn process(&mut self) -> Result<()> {
         self.poll_socket_events();
         if self.stream.is_handshaking() {
             self.stream.handshake()?;
@@ -209,8 +317,21 @@ impl IoLoop {
         if self.should_continue() {
             self.read()?;
         }
+        if self.heartbeat.server_gone() {
+            self.connection_status
+                .emit(ConnectionEvent::HeartbeatMissed);
+            return Err(Error::MissingHeartbeat);
+        }
         self.handle_frames()?;
+        if self.eof && self.connection_status.closing() {
+            // The server may close the socket right after close-ok, before we handled it
+            self.channels
+                .set_connection_closed(Error::InvalidConnectionState(ConnectionState::Closed));
+        }
         self.check_connection_state();
+        if self.eof && self.should_continue() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
         trace!(
             can_read=%self.socket_state.readable(),
             can_write=%self.socket_state.writable(),
@@ -222,19 +343,190 @@ impl IoLoop {
     }
 
     fn critical_error(&mut self, error: Error) -> Result<()> {
+        if let Some(recovery) = self.recovery.clone() {
+            // We already gave up on recovery if we're stopped
+            if self.status != Status::Stop && self.can_recover(&error) {
+                return self.recover(recovery, error);
+            }
+        }
         if let Some(resolver) = self.connection_status.connection_resolver() {
             resolver.swear(Err(error.clone()));
         }
         self.status = Status::Stop;
-        self.channels.set_connection_error(error.clone());
+        if self.connection_status.reconnecting() {
+            self.channels.abort_recovery(error.clone());
+        } else {
+            self.channels.set_connection_error(error.clone());
+        }
+        self.drop_serialized_frames(error.clone());
+        Err(error)
+    }
+
+    fn drop_serialized_frames(&mut self, error: Error) {
         for (_, resolver) in std::mem::take(&mut self.serialized_frames) {
             if let Some(resolver) = resolver {
                 resolver.swear(Err(error.clone()));
             }
         }
+    }
+
+    fn can_recover(&self, error: &Error) -> bool {
+        // We only recover connections that were successfully established once, and only
+        // from network failures, half-open connections included
+        if let Error::IOError(_) | Error::MissingHeartbeat = error {
+            self.connection_status.connected() || self.connection_status.reconnecting()
+        } else {
+            false
+        }
+    }
+
+    fn recover(&mut self, recovery: Recovery, error: Error) -> Result<()> {
+        self.heartbeat.cancel();
+        if let Some(resolver) = self.connection_status.connection_resolver() {
+            resolver.swear(Err(error.clone()));
+        }
+        self.channels.set_connection_reconnecting(error.clone());
+        self.drop_serialized_frames(error.clone());
+        // Whatever is left of the old socket is useless now
+        self.reactor.unregister(self.slot);
+        self.secret_refresh = None;
+        self.eof = false;
+        self.receive_buffer = Buffer::with_capacity(FRAMES_STORAGE * self.frame_size);
+        self.send_buffer = Buffer::with_capacity(FRAMES_STORAGE * self.frame_size);
+
+        let (sender, receiver) = flume::bounded(1);
+        self.reconnection = Some(receiver);
+        self.status = Status::Reconnecting;
+        recovery.executor.spawn(Box::pin(recovery::reconnect(
+            recovery.clone(),
+            error,
+            self.heartbeat.clone(),
+            self.connection_status.clone(),
+            self.socket_state.handle(),
+            sender,
+        )));
+        Ok(())
+    }
+
+    fn check_reconnection(&mut self) -> Result<bool> {
+        let reconnection = match self.reconnection.as_ref().map(Receiver::try_recv) {
+            Some(Err(TryRecvError::Empty)) => return Ok(false),
+            Some(Ok(reconnection)) => reconnection,
+            Some(Err(TryRecvError::Disconnected)) | None => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "connection recovery was interrupted",
+            )
+            .into()),
+        };
+        self.reconnection = None;
+        let recovery = self.recovery.clone();
+        let connection = recovery
+            .as_ref()
+            .and_then(|recovery| recovery.connection.upgrade());
+        match (reconnection, recovery, connection) {
+            (Ok(reconnection), Some(recovery), Some(connection)) => {
+                if let (Some(provider), Some(secret)) = (
+                    recovery.options.credentials_provider.clone(),
+                    reconnection.secret,
+                ) {
+                    let refresh = SecretRefresh {
+                        connection: recovery.connection.clone(),
+                        provider,
+                        secret,
+                    };
+                    self.secret_refresh = Some(credentials_provider::spawn_refresh(
+                        &*self.executor,
+                        refresh,
+                        reconnection.reactor.handle(),
+                    ));
+                }
+                self.reset_stream(reconnection.reactor, reconnection.stream)?;
+                self.connection_status.set_endpoint(&reconnection.uri);
+                self.start_handshake(
+                    &recovery,
+                    &reconnection.uri,
+                    reconnection.credentials,
+                    connection,
+                );
+                self.finish_setup()
+            }
+            (Ok(_), ..) => self.give_up(Error::InvalidConnectionState(ConnectionState::Closed)),
+            (Err(error), ..) => self.give_up(error),
+        }
+    }
+
+    fn give_up(&mut self, error: Error) -> Result<bool> {
+        error!(%error, "Giving up on connection recovery");
+        // Stopping first so that critical_error aborts the recovery instead of trying again
+        self.status = Status::Stop;
         Err(error)
     }
 
+    fn reset_stream(
+        &mut self,
+        mut reactor: Box<dyn Reactor + Send>,
+        stream: Box<dyn Transport>,
+    ) -> Result<()> {
+        self.reactor.unregister(self.slot);
+        self.slot = reactor.register(stream.socket(), self.socket_state.handle())?;
+        self.reactor = reactor.handle();
+        self.connection_status.set_timers(reactor.handle());
+        self.stream = stream;
+        self.status = Status::Initial;
+        self.socket_state.reset();
+        Ok(())
+    }
+
+    fn start_handshake(
+        &mut self,
+        recovery: &Recovery,
+        uri: &AMQPUri,
+        credentials: Credentials,
+        connection: Connection,
+    ) {
+        let channel0 = match self.channels.get(0) {
+            Some(channel0) => channel0,
+            None => return,
+        };
+        let (promise_out, resolver) = Promise::new();
+        if level_enabled!(Level::TRACE) {
+            promise_out.set_marker("ProtocolHeader".into());
+        }
+        channel0.send_frame(
+            AMQPFrame::ProtocolHeader(ProtocolVersion::amqp_0_9_1()),
+            resolver,
+            None,
+        );
+        let (promise_in, resolver) = Promise::new();
+        if level_enabled!(Level::TRACE) {
+            promise_in.set_marker("ProtocolHeader.Ok".into());
+        }
+        self.connection_status
+            .set_connection_step(ConnectionStep::ProtocolHeader(
+                resolver,
+                connection,
+                credentials,
+                recovery
+                    .options
+                    .sasl_mechanism
+                    .clone()
+                    .unwrap_or_else(|| sasl::from_uri(uri)),
+                recovery.options.clone(),
+            ));
+        let channels = self.channels.clone();
+        let connection_status = self.connection_status.clone();
+        recovery.executor.spawn(Box::pin(async move {
+            let handshake = async move {
+                promise_out.await?;
+                promise_in.await
+            };
+            match handshake.await {
+                Ok(_) => recovery::recover_channels(channels, connection_status).await,
+                Err(err) => error!(%err, "Failed to reopen the connection"),
+            }
+        }));
+    }
+
     fn handle_read_result(&mut self, result: Result<()>) -> Result<()> {
         if let Err(e) = self
             .socket_state
@@ -339,12 +631,12 @@ impl IoLoop {
 
                 if sz > 0 {
                     trace!("read {} bytes", sz);
+                    self.heartbeat.update_last_read();
                     self.receive_buffer.fill(sz);
                 } else {
-                    error!("Socket was readable but we read 0, marking as wouldblock");
-                    self.handle_read_result(
-                        Err(io::Error::from(io::ErrorKind::WouldBlock).into()),
-                    )?;
+                    // Polling the socket again would wake us up right away, forever
+                    debug!("Socket was readable but we read 0, the server closed it");
+                    self.eof = true;
                 }
                 Ok(())
             }
@@ -420,3 +712,9 @@ impl IoLoop {
         }
     }
 }
+
+impl Drop for IoLoop {
+    fn drop(&mut self) {
+        self.reactor.unregister(self.slot);
+    }
+}
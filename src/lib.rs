@@ -16,6 +16,8 @@
 //! * `rustls`: enable amqps support through rustls (preferred over openssl when set, uses rustls-native-certs by default)
 //! * `rustls-native-certs`: same as rustls, be ensure we'll still use rustls-native-certs even if the default for rustls changes
 //! * `rustls-webpki-roots-certs`: same as rustls but using webkit-roots instead of rustls-native-certs
+//! * `serde`: make `topology::Topology` (de)serializable
+//! * `definitions`: import and export RabbitMQ's definitions.json through `topology::Topology` (implies `serde`)
 //!
 //! ## Example
 //!
@@ -104,44 +106,68 @@ pub use amq_protocol::{
     tcp, types, uri,
 };
 
+pub use blocked_publish_policy::BlockedPublishPolicy;
 pub use channel::{options, Channel};
+pub use channel_pool::{ChannelPool, ChannelPoolConfig, PooledChannel};
 pub use channel_status::{ChannelState, ChannelStatus};
 pub use configuration::Configuration;
 pub use connection::{Connect, Connection};
+pub use connection_events::{ConnectionEvent, ConnectionEventListeners, ConnectionEvents};
+pub use connection_pool::{ConnectionPool, ConnectionPoolConfig, ConnectionPoolMetrics};
 pub use connection_properties::ConnectionProperties;
 pub use connection_status::{ConnectionState, ConnectionStatus};
 pub use consumer::{Consumer, ConsumerDelegate};
 pub use consumer_status::ConsumerState;
+pub use credentials_provider::{CredentialsProvider, FileCredentialsProvider, Secret};
+pub use endpoints::{EndpointSelection, Endpoints};
 pub use error::{Error, Result};
 pub use exchange::ExchangeKind;
+pub use io_loop::IoLoopMode;
+pub use proxy::{Proxy, ProxyConfig, ProxyKind};
+pub use publish_retry::{DeadLetter, RetryPolicy};
+pub use publisher::{Publisher, PublisherConfirmations};
 pub use queue::Queue;
+pub use recovery::RecoveryConfig;
+pub use rpc::{RpcClient, RpcServer, DIRECT_REPLY_TO};
+pub use socket_options::{KeepaliveOptions, SocketOptions};
 pub use stream::TcpStream;
+pub use transport::{OwnedTransportSocket, Transport, TransportSocket};
 
+pub mod blocking;
 pub mod executor;
 pub mod heartbeat;
 pub mod message;
 pub mod publisher_confirm;
 pub mod reactor;
+pub mod sasl;
 pub mod socket_state;
+pub mod topology;
 
 type Promise<T> = pinky_swear::PinkySwear<Result<T>>;
 type PromiseResolver<T> = pinky_swear::Pinky<Result<T>>;
 
 mod acknowledgement;
+mod backoff;
+mod blocked_publish_policy;
 mod buffer;
 mod channel;
 mod channel_closer;
+mod channel_pool;
 mod channel_receiver_state;
 mod channel_status;
 mod channels;
 mod configuration;
 mod connection;
 mod connection_closer;
+mod connection_events;
+mod connection_pool;
 mod connection_properties;
 mod connection_status;
 mod consumer;
 mod consumer_canceler;
 mod consumer_status;
+mod credentials_provider;
+mod endpoints;
 mod error;
 mod error_handler;
 mod exchange;
@@ -150,9 +176,18 @@ mod id_sequence;
 mod internal_rpc;
 mod io_loop;
 mod parsing;
+mod proxy;
+mod publish_retry;
+mod publisher;
 mod queue;
 mod queues;
+mod recovery;
 mod returned_messages;
+mod rpc;
+mod socket_options;
 mod stream;
 mod thread;
+mod topology_registry;
+mod transport;
+mod unix;
 mod wakers;
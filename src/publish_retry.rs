@@ -0,0 +1,107 @@
+use crate::{
+    backoff, options::BasicPublishOptions, publisher_confirm::Confirmation, BasicProperties, Result,
+};
+use parking_lot::Mutex;
+use std::{fmt, sync::Arc, time::Duration};
+
+/// How [`Channel::basic_publish_with_retry`] should republish a message which didn't make it
+///
+/// [`Channel::basic_publish_with_retry`]: ./struct.Channel.html#method.basic_publish_with_retry
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// How many times we republish a message before giving up on it
+    pub max_retries: usize,
+    /// Delay before republishing, doubled after each attempt
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between two attempts
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub(crate) fn delay(&self, attempt: usize) -> Duration {
+        backoff::exponential(self.initial_delay, self.max_delay, attempt)
+    }
+}
+
+/// A message we gave up republishing, handed to the callback registered with
+/// [`Channel::on_dead_letter`]
+///
+/// [`Channel::on_dead_letter`]: ./struct.Channel.html#method.on_dead_letter
+#[derive(Clone, Debug)]
+pub struct DeadLetter {
+    pub exchange: String,
+    pub routing_key: String,
+    pub options: BasicPublishOptions,
+    pub payload: Vec<u8>,
+    pub properties: BasicProperties,
+    /// The outcome of the last attempt: a nack, a returned message or an error
+    pub outcome: Result<Confirmation>,
+    /// How many times we published this message
+    pub attempts: usize,
+}
+
+type DeadLetterFn = Box<dyn FnMut(DeadLetter) + Send + 'static>;
+
+#[derive(Clone, Default)]
+pub(crate) struct DeadLetterHandler(Arc<Mutex<Option<DeadLetterFn>>>);
+
+impl DeadLetterHandler {
+    pub(crate) fn set_handler<F: FnMut(DeadLetter) + Send + 'static>(&self, handler: F) {
+        *self.0.lock() = Some(Box::new(handler));
+    }
+
+    pub(crate) fn on_dead_letter(&self, dead_letter: DeadLetter) {
+        if let Some(handler) = self.0.lock().as_mut() {
+            handler(dead_letter)
+        }
+    }
+}
+
+impl fmt::Debug for DeadLetterHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DeadLetterHandler").finish()
+    }
+}
+
+/// Whether the outcome of a publish means the message made it to a queue
+pub(crate) fn delivered(outcome: &Result<Confirmation>) -> bool {
+    matches!(
+        outcome,
+        Ok(Confirmation::Ack(None)) | Ok(Confirmation::NotRequested)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{message::BasicReturnMessage, Error};
+
+    #[test]
+    fn nacks_returns_and_errors_are_not_delivered() {
+        assert!(delivered(&Ok(Confirmation::Ack(None))));
+        assert!(delivered(&Ok(Confirmation::NotRequested)));
+        assert!(!delivered(&Ok(Confirmation::Nack(None))));
+        assert!(!delivered(&Ok(Confirmation::Ack(Some(Box::new(
+            BasicReturnMessage::new("".into(), "key".into(), 312, "NO_ROUTE".into())
+        ))))));
+        assert!(!delivered(&Err(Error::ChannelsLimitReached)));
+    }
+
+    #[test]
+    fn delay_backs_off() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(400));
+        assert_eq!(policy.delay(10), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,324 @@
+use crate::{
+    options::{BasicPublishOptions, ConfirmSelectOptions},
+    publisher_confirm::{Confirmation, PublisherConfirm},
+    BasicProperties, Channel, Result,
+};
+use futures_lite::Stream;
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+use tracing::trace;
+
+/// Publish messages with publisher confirms, keeping at most `window` of them unconfirmed.
+///
+/// `publish` waits for some room in the window before sending the message, and the outcome of
+/// each message is yielded, along with the token you gave to identify it, by the
+/// [`PublisherConfirmations`] stream.
+///
+/// [`PublisherConfirmations`]: ./struct.PublisherConfirmations.html
+pub struct Publisher<T> {
+    channel: Channel,
+    inner: Arc<Mutex<Inner<T>>>,
+}
+
+/// The Stream of the outcome of the messages sent through a [`Publisher`]
+///
+/// It ends once all the clones of the [`Publisher`] have been dropped and all their messages
+/// have been confirmed.
+///
+/// [`Publisher`]: ./struct.Publisher.html
+pub struct PublisherConfirmations<T> {
+    inner: Arc<Mutex<Inner<T>>>,
+}
+
+impl<T: Send + 'static> Publisher<T> {
+    /// Create a Publisher on this channel, switching it to confirm mode if needed
+    pub async fn new(channel: Channel, window: usize) -> Result<Self> {
+        if !channel.status().confirm() {
+            channel
+                .confirm_select(ConfirmSelectOptions::default())
+                .await?;
+        }
+        let inner = Arc::new(Mutex::new(Inner::new(window)));
+        channel.executor().spawn(Box::pin(Driver(inner.clone())));
+        Ok(Self { channel, inner })
+    }
+
+    /// Publish a message once there is room for it in the window
+    ///
+    /// The token will be yielded along with the message's confirmation.
+    pub async fn publish(
+        &self,
+        token: T,
+        exchange: &str,
+        routing_key: &str,
+        options: BasicPublishOptions,
+        payload: Vec<u8>,
+        properties: BasicProperties,
+    ) -> Result<()> {
+        // Given back if we fail or get dropped before the message is out
+        let slot = Capacity(&self.inner).await;
+        let confirm = self
+            .channel
+            .basic_publish(exchange, routing_key, options, payload, properties)
+            .await?;
+        self.inner.lock().track(Pending {
+            token,
+            confirm,
+            slot,
+        });
+        Ok(())
+    }
+
+    /// The number of messages waiting for their confirmation
+    pub fn in_flight(&self) -> usize {
+        self.inner.lock().in_flight
+    }
+
+    /// The Stream of the outcome of the messages sent through this Publisher
+    pub fn confirmations(&self) -> PublisherConfirmations<T> {
+        PublisherConfirmations {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Clone for Publisher<T> {
+    fn clone(&self) -> Self {
+        self.inner.lock().publishers += 1;
+        Self {
+            channel: self.channel.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Publisher<T> {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock();
+        inner.publishers -= 1;
+        inner.wake_consumers();
+    }
+}
+
+impl<T> fmt::Debug for Publisher<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Publisher");
+        debug.field("channel", &self.channel);
+        if let Some(inner) = self.inner.try_lock() {
+            debug
+                .field("window", &inner.window)
+                .field("in_flight", &inner.in_flight);
+        }
+        debug.finish()
+    }
+}
+
+impl<T> Stream for PublisherConfirmations<T> {
+    type Item = (T, Result<Confirmation>);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut inner = self.inner.lock();
+        if let Some(confirmation) = inner.confirmations.pop_front() {
+            Poll::Ready(Some(confirmation))
+        } else if inner.publishers == 0 && inner.in_flight == 0 {
+            Poll::Ready(None)
+        } else {
+            inner.confirmations_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> fmt::Debug for PublisherConfirmations<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PublisherConfirmations").finish()
+    }
+}
+
+/// A slot in the window, freed on drop
+struct WindowSlot<T>(Arc<Mutex<Inner<T>>>);
+
+impl<T> Drop for WindowSlot<T> {
+    fn drop(&mut self) {
+        self.0.lock().release();
+    }
+}
+
+struct Capacity<'a, T>(&'a Arc<Mutex<Inner<T>>>);
+
+impl<T> Future for Capacity<'_, T> {
+    type Output = WindowSlot<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.0.lock().try_acquire(cx.waker()) {
+            Poll::Ready(WindowSlot(self.0.clone()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// A message waiting for its confirmation, keeping its slot in the window until then
+struct Pending<T> {
+    token: T,
+    confirm: PublisherConfirm,
+    slot: WindowSlot<T>,
+}
+
+/// The single task waiting for the confirmations of all the messages of a Publisher
+struct Driver<T>(Arc<Mutex<Inner<T>>>);
+
+impl<T> Future for Driver<T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let pending = {
+            let mut inner = self.0.lock();
+            inner.driver_waker = Some(cx.waker().clone());
+            mem::take(&mut inner.pending)
+        };
+        let mut still_pending = Vec::with_capacity(pending.len());
+        let mut confirmations = Vec::new();
+        let mut slots = Vec::new();
+        for mut message in pending {
+            match Pin::new(&mut message.confirm).poll(cx) {
+                Poll::Ready(confirmation) => {
+                    confirmations.push((message.token, confirmation));
+                    slots.push(message.slot);
+                }
+                Poll::Pending => still_pending.push(message),
+            }
+        }
+        let done = {
+            let mut inner = self.0.lock();
+            inner.pending.extend(still_pending);
+            inner.complete(confirmations);
+            inner.publishers == 0 && inner.pending.is_empty()
+        };
+        // Free the slots once their confirmations are queued, they need the lock
+        drop(slots);
+        if done {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+struct Inner<T> {
+    window: usize,
+    in_flight: usize,
+    publishers: usize,
+    capacity_wakers: Vec<Waker>,
+    pending: Vec<Pending<T>>,
+    driver_waker: Option<Waker>,
+    confirmations: VecDeque<(T, Result<Confirmation>)>,
+    confirmations_waker: Option<Waker>,
+}
+
+impl<T> Inner<T> {
+    fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            in_flight: 0,
+            publishers: 1,
+            capacity_wakers: Vec::default(),
+            pending: Vec::default(),
+            driver_waker: None,
+            confirmations: VecDeque::default(),
+            confirmations_waker: None,
+        }
+    }
+
+    fn try_acquire(&mut self, waker: &Waker) -> bool {
+        if self.in_flight < self.window {
+            self.in_flight += 1;
+            true
+        } else {
+            trace!(in_flight=%self.in_flight, "publisher window is full");
+            if !self.capacity_wakers.iter().any(|w| w.will_wake(waker)) {
+                self.capacity_wakers.push(waker.clone());
+            }
+            false
+        }
+    }
+
+    fn release(&mut self) {
+        self.in_flight -= 1;
+        // Wake everyone up as some of the publish calls waiting for capacity may have been dropped
+        for waker in self.capacity_wakers.drain(..) {
+            waker.wake();
+        }
+        // The confirmations stream ends once the last slot is freed
+        if let Some(waker) = self.confirmations_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn track(&mut self, message: Pending<T>) {
+        self.pending.push(message);
+        if let Some(waker) = self.driver_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn complete(&mut self, confirmations: Vec<(T, Result<Confirmation>)>) {
+        if confirmations.is_empty() {
+            return;
+        }
+        self.confirmations.extend(confirmations);
+        if let Some(waker) = self.confirmations_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Let the confirmations stream and the driver check whether they're done
+    fn wake_consumers(&mut self) {
+        if let Some(waker) = self.confirmations_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.driver_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn counting_waker(counter: Arc<AtomicUsize>) -> Waker {
+        waker_fn::waker_fn(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    #[test]
+    fn window_limits_in_flight_messages() {
+        let woken = Arc::new(AtomicUsize::new(0));
+        let waker = counting_waker(woken.clone());
+        let inner = Arc::new(Mutex::new(Inner::<&str>::new(2)));
+        let mut cx = Context::from_waker(&waker);
+        let mut acquire = || Pin::new(&mut Capacity(&inner)).poll(&mut cx);
+        let first = acquire();
+        let _second = acquire();
+        assert!(acquire().is_pending());
+        assert_eq!(woken.load(Ordering::SeqCst), 0);
+
+        // Dropping the slot, like a canceled publish would, makes room again
+        drop(first);
+        assert_eq!(woken.load(Ordering::SeqCst), 1);
+        let third = acquire();
+        assert!(third.is_ready());
+        assert_eq!(inner.lock().in_flight, 2);
+    }
+}
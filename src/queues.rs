@@ -49,6 +49,13 @@ impl Queues {
         });
     }
 
+    pub(crate) fn get_consumer(&self, consumer_tag: &str) -> Option<Consumer> {
+        self.queues
+            .lock()
+            .values_mut()
+            .find_map(|queue| queue.get_consumer(consumer_tag).cloned())
+    }
+
     pub(crate) fn deregister_consumer(&self, consumer_tag: &str) {
         for queue in self.queues.lock().values_mut() {
             queue.deregister_consumer(consumer_tag);
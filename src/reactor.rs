@@ -2,12 +2,14 @@ use crate::{
     executor::Executor,
     heartbeat::Heartbeat,
     socket_state::{SocketEvent, SocketStateHandle},
-    tcp::{TcpStream, TcpStreamWrapper},
+    transport::{OwnedTransportSocket, TransportSocket},
     Result,
 };
 use async_io::{Async, Timer};
+use flume::{Receiver, Sender};
+use futures_lite::future;
 use parking_lot::Mutex;
-use std::{collections::HashMap, fmt, sync::Arc};
+use std::{collections::HashMap, fmt, future::Future, pin::Pin, sync::Arc, time::Duration};
 
 pub type Slot = usize;
 
@@ -16,8 +18,11 @@ pub trait ReactorBuilder: fmt::Debug + Send + Sync {
 }
 
 pub trait Reactor: fmt::Debug + Send {
-    fn register(&mut self, socket: &mut TcpStream, socket_state: SocketStateHandle)
-        -> Result<Slot>;
+    fn register(
+        &mut self,
+        socket: TransportSocket,
+        socket_state: SocketStateHandle,
+    ) -> Result<Slot>;
     fn handle(&self) -> Box<dyn ReactorHandle + Send> {
         Box::new(DummyHandle)
     }
@@ -27,6 +32,14 @@ pub trait ReactorHandle {
     fn start_heartbeat(&self) {}
     fn poll_read(&self, _slot: Slot) {}
     fn poll_write(&self, _slot: Slot) {}
+    /// Stop polling the socket of this slot, which is about to be closed
+    fn unregister(&self, _slot: Slot) {}
+    /// Complete after `duration`, using the timers of this reactor
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {
+            Timer::after(duration).await;
+        })
+    }
 }
 
 #[derive(Clone)]
@@ -52,10 +65,10 @@ pub(crate) struct DefaultReactor(DefaultReactorHandle);
 impl Reactor for DefaultReactor {
     fn register(
         &mut self,
-        socket: &mut TcpStream,
+        socket: TransportSocket,
         socket_state: SocketStateHandle,
     ) -> Result<Slot> {
-        let socket = Arc::new(Async::new(unsafe { TcpStreamWrapper::new(socket) })?);
+        let socket = Arc::new(Async::new(socket.try_clone()?)?);
         let slot = self.0.inner.lock().register(socket, socket_state)?;
         self.0.poll_read(slot);
         self.0.poll_write(slot);
@@ -87,35 +100,59 @@ impl ReactorHandle for DefaultReactorHandle {
     }
 
     fn poll_read(&self, slot: usize) {
-        if let Some((socket, socket_state)) = self.inner.lock().slots.get(&slot) {
-            self.executor
-                .spawn(Box::pin(poll_read(socket.clone(), socket_state.clone())));
+        if let Some(registration) = self.inner.lock().slots.get(&slot) {
+            self.executor.spawn(Box::pin(poll_read(
+                registration.socket.clone(),
+                registration.socket_state.clone(),
+                registration.unregistered.1.clone(),
+            )));
         }
     }
 
     fn poll_write(&self, slot: usize) {
-        if let Some((socket, socket_state)) = self.inner.lock().slots.get(&slot) {
-            self.executor
-                .spawn(Box::pin(poll_write(socket.clone(), socket_state.clone())));
+        if let Some(registration) = self.inner.lock().slots.get(&slot) {
+            self.executor.spawn(Box::pin(poll_write(
+                registration.socket.clone(),
+                registration.socket_state.clone(),
+                registration.unregistered.1.clone(),
+            )));
         }
     }
+
+    fn unregister(&self, slot: usize) {
+        // Dropping the sender wakes the polling tasks up, releasing the socket
+        self.inner.lock().slots.remove(&slot);
+    }
 }
 
 #[derive(Default)]
 struct Inner {
     slot: Slot,
-    slots: HashMap<usize, (Arc<Async<TcpStreamWrapper>>, SocketStateHandle)>,
+    slots: HashMap<usize, Registration>,
+}
+
+struct Registration {
+    socket: Arc<Async<OwnedTransportSocket>>,
+    socket_state: SocketStateHandle,
+    unregistered: (Sender<()>, Receiver<()>),
 }
 
 impl Inner {
     fn register(
         &mut self,
-        socket: Arc<Async<TcpStreamWrapper>>,
+        socket: Arc<Async<OwnedTransportSocket>>,
         socket_state: SocketStateHandle,
     ) -> Result<usize> {
         let slot = self.slot;
         self.slot += 1;
-        self.slots.insert(slot, (socket, socket_state));
+        self.slots.insert(
+            slot,
+            Registration {
+                socket,
+                socket_state,
+                unregistered: flume::bounded(0),
+            },
+        );
         Ok(slot)
     }
 }
@@ -126,14 +163,37 @@ async fn heartbeat(heartbeat: Heartbeat) {
     }
 }
 
-async fn poll_read(socket: Arc<Async<TcpStreamWrapper>>, socket_state: SocketStateHandle) {
-    socket.readable().await.unwrap();
-    socket_state.send(SocketEvent::Readable);
+async fn poll_read(
+    socket: Arc<Async<OwnedTransportSocket>>,
+    socket_state: SocketStateHandle,
+    unregistered: Receiver<()>,
+) {
+    let readable = async {
+        socket.readable().await.unwrap();
+        true
+    };
+    if future::or(readable, unregistered_event(unregistered)).await {
+        socket_state.send(SocketEvent::Readable);
+    }
+}
+
+async fn poll_write(
+    socket: Arc<Async<OwnedTransportSocket>>,
+    socket_state: SocketStateHandle,
+    unregistered: Receiver<()>,
+) {
+    let writable = async {
+        socket.writable().await.unwrap();
+        true
+    };
+    if future::or(writable, unregistered_event(unregistered)).await {
+        socket_state.send(SocketEvent::Writable);
+    }
 }
 
-async fn poll_write(socket: Arc<Async<TcpStreamWrapper>>, socket_state: SocketStateHandle) {
-    socket.writable().await.unwrap();
-    socket_state.send(SocketEvent::Writable);
+async fn unregistered_event(unregistered: Receiver<()>) -> bool {
+    let _ = unregistered.recv_async().await;
+    false
 }
 
 impl fmt::Debug for DefaultReactorBuilder {
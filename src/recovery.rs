@@ -0,0 +1,239 @@
+use crate::{
+    auth::Credentials,
+    backoff,
+    channels::Channels,
+    connection::WeakConnection,
+    connection_events::ConnectionEvent,
+    credentials_provider::{self, Secret},
+    endpoints::Endpoints,
+    executor::Executor,
+    heartbeat::Heartbeat,
+    reactor::{Reactor, ReactorBuilder},
+    socket_state::SocketStateHandle,
+    transport::Transport,
+    types::ShortString,
+    uri::AMQPUri,
+    Channel, ConnectionProperties, ConnectionStatus, Error, Result,
+};
+use flume::Sender;
+use std::{collections::HashMap, fmt, io, sync::Arc, time::Duration};
+use tracing::{debug, error, info, warn};
+
+/// Configuration of the automatic connection recovery.
+///
+/// When set through [`ConnectionProperties::with_connection_recovery`], losing the socket no
+/// longer errors the connection out: lapin reconnects, reopens the channels with their
+/// original ids, prefetch and confirm mode, redeclares the exchanges, queues and bindings they
+/// declared and resubscribes their consumers, so that existing [`Consumer`]s keep working.
+///
+/// [`ConnectionProperties::with_connection_recovery`]: ./struct.ConnectionProperties.html#method.with_connection_recovery
+/// [`Consumer`]: ./struct.Consumer.html
+#[derive(Clone, Debug)]
+pub struct RecoveryConfig {
+    /// Delay before the first reconnection attempt, doubled after each failed attempt
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between two reconnection attempts
+    pub max_delay: Duration,
+    /// Give up after that many failed attempts, retry forever if None
+    pub max_attempts: Option<usize>,
+}
+
+impl Default for RecoveryConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            max_attempts: None,
+        }
+    }
+}
+
+impl RecoveryConfig {
+    pub(crate) fn delay(&self, attempt: usize) -> Duration {
+        backoff::exponential(self.initial_delay, self.max_delay, attempt)
+    }
+
+    pub(crate) fn can_retry(&self, attempt: usize) -> bool {
+        self.max_attempts.map_or(true, |max| attempt < max)
+    }
+}
+
+pub(crate) type Connector = Arc<dyn Fn(&AMQPUri) -> Result<Box<dyn Transport>> + Send + Sync>;
+
+/// Everything the IoLoop needs to establish the connection again
+#[derive(Clone)]
+pub(crate) struct Recovery {
+    pub(crate) config: RecoveryConfig,
+    pub(crate) endpoints: Endpoints,
+    pub(crate) connect: Connector,
+    pub(crate) options: ConnectionProperties,
+    pub(crate) reactor_builder: Arc<dyn ReactorBuilder>,
+    pub(crate) executor: Arc<dyn Executor>,
+    pub(crate) connection: WeakConnection,
+}
+
+impl fmt::Debug for Recovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recovery")
+            .field("config", &self.config)
+            .field("endpoints", &self.endpoints)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+/// A new transport to one of the endpoints, for the IoLoop to resume on
+pub(crate) struct Reconnection {
+    pub(crate) uri: AMQPUri,
+    pub(crate) stream: Box<dyn Transport>,
+    pub(crate) reactor: Box<dyn Reactor + Send>,
+    pub(crate) credentials: Credentials,
+    pub(crate) secret: Option<Secret>,
+}
+
+/// Reach one of the endpoints again, handing the result over to the IoLoop through `sender`.
+///
+/// The delays between the attempts use the timers of the reactor the new transport will be
+/// registered on, and the blocking connection attempts run through `Executor::spawn_blocking`,
+/// so that neither the IoLoop nor the executor are stuck while the server is away.
+pub(crate) async fn reconnect(
+    recovery: Recovery,
+    mut error: Error,
+    heartbeat: Heartbeat,
+    connection_status: ConnectionStatus,
+    io_loop: SocketStateHandle,
+    sender: Sender<Result<Reconnection>>,
+) {
+    let reactor = recovery
+        .reactor_builder
+        .build(heartbeat, recovery.executor.clone());
+    let timers = reactor.handle();
+    let mut attempt = 0;
+    let result = loop {
+        if !recovery.config.can_retry(attempt) {
+            break Err(error);
+        }
+        timers.sleep(recovery.config.delay(attempt)).await;
+        attempt += 1;
+        if recovery.connection.upgrade().is_none() || !connection_status.reconnecting() {
+            debug!("Connection dropped while reconnecting, giving up");
+            break Err(error);
+        }
+        match connect(&recovery).await {
+            Ok((uri, stream, credentials, secret)) => {
+                break Ok(Reconnection {
+                    uri,
+                    stream,
+                    reactor,
+                    credentials,
+                    secret,
+                })
+            }
+            Err(err) => {
+                warn!(%err, %attempt, "Failed to reconnect");
+                error = err;
+            }
+        }
+    };
+    let _ = sender.send(result);
+    io_loop.wake();
+}
+
+/// The endpoint we reached, with the credentials to authenticate with
+type Reached = (AMQPUri, Box<dyn Transport>, Credentials, Option<Secret>);
+
+async fn connect(recovery: &Recovery) -> Result<Reached> {
+    let (promise, resolver) = pinky_swear::PinkySwear::new();
+    let endpoints = recovery.endpoints.clone();
+    let connect = recovery.connect.clone();
+    let options = recovery.options.clone();
+    recovery.executor.spawn_blocking(Box::new(move || {
+        resolver.swear(connect_endpoints(&endpoints, &connect, &options));
+    }));
+    promise.await
+}
+
+fn connect_endpoints(
+    endpoints: &Endpoints,
+    connect: &Connector,
+    options: &ConnectionProperties,
+) -> Result<Reached> {
+    let mut last_error = None;
+    for mut endpoint in endpoints.ordered() {
+        endpoint.uri.query.connection_timeout = options
+            .connection_timeout(&endpoint.uri)
+            .map(|timeout| timeout.as_millis() as u64);
+        let uri = endpoint.uri.clone();
+        let connected = endpoint.connect(connect).and_then(|stream| {
+            credentials_provider::credentials(&uri, options.credentials_provider.as_ref())
+                .map(|(credentials, secret)| (stream, credentials, secret))
+        });
+        match connected {
+            Ok((stream, credentials, secret)) => return Ok((uri, stream, credentials, secret)),
+            Err(err) => {
+                warn!(host=%uri.authority.host, port=%uri.authority.port, %err, "Failed to reach endpoint");
+                last_error = Some(err);
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "no endpoint to connect to").into()
+    }))
+}
+
+/// Reopen the channels and replay their topology once the connection is back
+pub(crate) async fn recover_channels(channels: Channels, connection_status: ConnectionStatus) {
+    let channels = channels.list();
+
+    for channel in &channels {
+        report(channel, channel.reopen().await);
+    }
+    for channel in &channels {
+        report(channel, channel.recover_exchanges().await);
+    }
+    let mut renames = HashMap::<ShortString, ShortString>::default();
+    for channel in &channels {
+        match channel.recover_queues().await {
+            Ok(channel_renames) => renames.extend(channel_renames),
+            Err(err) => report(channel, Err(err)),
+        }
+    }
+    for channel in &channels {
+        channel.rename_queues(&renames);
+    }
+    for channel in &channels {
+        report(channel, channel.recover_bindings().await);
+    }
+    for channel in &channels {
+        report(channel, channel.recover_consumers().await);
+    }
+    info!("Connection recovered");
+    connection_status.emit(ConnectionEvent::Recovered);
+}
+
+fn report(channel: &Channel, result: Result<()>) {
+    if let Err(err) = result {
+        error!(channel=%channel.id(), %err, "Failed to recover channel");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_is_capped() {
+        let config = RecoveryConfig {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            max_attempts: Some(3),
+        };
+        assert_eq!(config.delay(0), Duration::from_secs(1));
+        assert_eq!(config.delay(1), Duration::from_secs(2));
+        assert_eq!(config.delay(3), Duration::from_secs(8));
+        assert_eq!(config.delay(4), Duration::from_secs(10));
+        assert_eq!(config.delay(64), Duration::from_secs(10));
+        assert!(config.can_retry(2));
+        assert!(!config.can_retry(3));
+    }
+}
@@ -0,0 +1,284 @@
+use crate::{
+    message::{Delivery, DeliveryResult},
+    options::{BasicAckOptions, BasicConsumeOptions, BasicPublishOptions},
+    types::{FieldTable, ShortString},
+    BasicProperties, Channel, Consumer, Error, Result,
+};
+use futures_lite::{future, StreamExt};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tracing::{trace, warn};
+
+/// The pseudo-queue RabbitMQ uses for [direct reply-to](https://www.rabbitmq.com/direct-reply-to.html)
+pub const DIRECT_REPLY_TO: &str = "amq.rabbitmq.reply-to";
+
+/// Make RPC calls over RabbitMQ's [direct reply-to](https://www.rabbitmq.com/direct-reply-to.html)
+///
+/// The client consumes `amq.rabbitmq.reply-to` on its channel, publishes each request with a
+/// fresh `correlation_id` and matches the replies back to the pending calls.
+/// Calls failing to get a reply in time fail with [`Error::Timeout`].
+///
+/// [`Error::Timeout`]: ./enum.Error.html#variant.Timeout
+#[derive(Clone)]
+pub struct RpcClient {
+    channel: Channel,
+    pending: PendingCalls,
+    next_id: Arc<AtomicU64>,
+    timeout: Duration,
+}
+
+impl RpcClient {
+    /// Start consuming replies on this channel
+    ///
+    /// The channel should be dedicated to this client, as direct reply-to only lets us consume
+    /// replies from the channel we publish requests on.
+    pub async fn new(channel: Channel) -> Result<Self> {
+        let consumer = channel
+            .basic_consume(
+                DIRECT_REPLY_TO,
+                "",
+                BasicConsumeOptions {
+                    no_ack: true,
+                    ..BasicConsumeOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+        let pending = PendingCalls::default();
+        let replies = pending.clone();
+        consumer.set_delegate(move |delivery: DeliveryResult| {
+            let replies = replies.clone();
+            async move { replies.on_delivery(delivery) }
+        });
+        Ok(Self {
+            channel,
+            pending,
+            next_id: Arc::new(AtomicU64::new(1)),
+            timeout: Duration::from_secs(30),
+        })
+    }
+
+    /// How long calls wait for their reply by default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Publish a request and wait for its reply, using the default timeout
+    pub async fn call(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        payload: Vec<u8>,
+        properties: BasicProperties,
+    ) -> Result<Delivery> {
+        self.call_with_timeout(exchange, routing_key, payload, properties, self.timeout)
+            .await
+    }
+
+    /// Publish a request and wait at most `timeout` for its reply
+    pub async fn call_with_timeout(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        payload: Vec<u8>,
+        properties: BasicProperties,
+        timeout: Duration,
+    ) -> Result<Delivery> {
+        let correlation_id = self.next_id.fetch_add(1, Ordering::SeqCst).to_string();
+        let reply = self.pending.register(correlation_id.clone());
+        let properties = properties
+            .with_correlation_id(correlation_id.as_str().into())
+            .with_reply_to(DIRECT_REPLY_TO.into());
+        trace!(channel=%self.channel.id(), %correlation_id, "sending RPC request");
+        let published = self
+            .channel
+            .basic_publish(
+                exchange,
+                routing_key,
+                BasicPublishOptions::default(),
+                payload,
+                properties,
+            )
+            .await;
+        if let Err(err) = published {
+            self.pending.forget(&correlation_id);
+            return Err(err);
+        }
+        let channel = self.channel.clone();
+        let result = future::or(
+            async move {
+                reply
+                    .recv_async()
+                    .await
+                    // The consumer went away along with the pending calls
+                    .unwrap_or_else(|_| Err(Error::InvalidChannelState(channel.status().state())))
+            },
+            async move {
+                async_io::Timer::after(timeout).await;
+                Err(Error::Timeout)
+            },
+        )
+        .await;
+        self.pending.forget(&correlation_id);
+        result
+    }
+}
+
+impl fmt::Debug for RpcClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcClient")
+            .field("channel", &self.channel)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Serve RPC requests consumed from a queue
+///
+/// Each reply is published to the request's `reply_to` with its `correlation_id`, after which
+/// the request is acked, unless it was consumed with `no_ack`.
+#[derive(Debug)]
+pub struct RpcServer {
+    channel: Channel,
+    consumer: Consumer,
+    no_ack: bool,
+}
+
+impl RpcServer {
+    /// Start consuming requests from `queue`
+    pub async fn new(
+        channel: Channel,
+        queue: &str,
+        options: BasicConsumeOptions,
+        arguments: FieldTable,
+    ) -> Result<Self> {
+        let no_ack = options.no_ack;
+        let consumer = channel.basic_consume(queue, "", options, arguments).await?;
+        Ok(Self {
+            channel,
+            consumer,
+            no_ack,
+        })
+    }
+
+    /// Answer the requests with `handler` until the consumer gets canceled
+    pub async fn serve<F, Fut>(mut self, handler: F) -> Result<()>
+    where
+        F: Fn(Delivery) -> Fut,
+        Fut: Future<Output = (Vec<u8>, BasicProperties)>,
+    {
+        while let Some(delivery) = self.consumer.next().await {
+            let (channel, request) = delivery?;
+            let delivery_tag = request.delivery_tag;
+            let reply_to = request.properties.reply_to().clone();
+            let correlation_id = request.properties.correlation_id().clone();
+            let (payload, mut properties) = handler(request).await;
+            match reply_to {
+                Some(reply_to) => {
+                    if let Some(correlation_id) = correlation_id {
+                        properties = properties.with_correlation_id(correlation_id);
+                    }
+                    channel
+                        .basic_publish(
+                            "",
+                            reply_to.as_str(),
+                            BasicPublishOptions::default(),
+                            payload,
+                            properties,
+                        )
+                        .await?;
+                }
+                None => {
+                    warn!(channel=%self.channel.id(), %delivery_tag, "RPC request without reply_to, dropping the reply");
+                }
+            }
+            if !self.no_ack {
+                channel
+                    .basic_ack(delivery_tag, BasicAckOptions::default())
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+struct PendingCalls(Arc<Mutex<HashMap<String, flume::Sender<Result<Delivery>>>>>);
+
+impl PendingCalls {
+    fn register(&self, correlation_id: String) -> flume::Receiver<Result<Delivery>> {
+        let (sender, receiver) = flume::bounded(1);
+        self.0.lock().insert(correlation_id, sender);
+        receiver
+    }
+
+    fn forget(&self, correlation_id: &str) {
+        self.0.lock().remove(correlation_id);
+    }
+
+    fn on_delivery(&self, delivery: DeliveryResult) {
+        match delivery {
+            Ok(Some((_, delivery))) => self.resolve(delivery),
+            // The consumer got canceled, dropping the senders fails the pending calls
+            Ok(None) => self.0.lock().clear(),
+            Err(err) => self.fail(err),
+        }
+    }
+
+    fn resolve(&self, reply: Delivery) {
+        let correlation_id: Option<ShortString> = reply.properties.correlation_id().clone();
+        match correlation_id.and_then(|id| self.0.lock().remove(id.as_str())) {
+            Some(call) => {
+                let _ = call.send(Ok(reply));
+            }
+            None => warn!("received an RPC reply matching no pending call"),
+        }
+    }
+
+    fn fail(&self, error: Error) {
+        for (_, call) in self.0.lock().drain() {
+            let _ = call.send(Err(error.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(correlation_id: &str) -> Delivery {
+        let mut delivery = Delivery::new(1, "".into(), DIRECT_REPLY_TO.into(), false);
+        delivery.properties = BasicProperties::default().with_correlation_id(correlation_id.into());
+        delivery
+    }
+
+    #[test]
+    fn replies_reach_their_call() {
+        let pending = PendingCalls::default();
+        let first = pending.register("1".into());
+        let second = pending.register("2".into());
+        pending.resolve(reply("2"));
+        pending.resolve(reply("3"));
+        assert!(first.try_recv().is_err());
+        assert!(matches!(
+            second.try_recv(),
+            Ok(Ok(delivery)) if delivery.properties.correlation_id() == &Some("2".into())
+        ));
+        pending.fail(Error::ChannelsLimitReached);
+        assert!(matches!(
+            first.try_recv(),
+            Ok(Err(Error::ChannelsLimitReached))
+        ));
+        assert!(pending.0.lock().is_empty());
+    }
+}
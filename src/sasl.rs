@@ -0,0 +1,415 @@
+//! SASL mechanisms used to authenticate against the server
+//!
+//! The mechanism is picked from the `auth_mechanism` parameter of the URI unless one was set
+//! through [`ConnectionProperties::with_sasl_mechanism`], which lets you plug your own
+//! implementation of [`SaslMechanism`] to talk to brokers using custom auth backends.
+//!
+//! [`ConnectionProperties::with_sasl_mechanism`]: ../struct.ConnectionProperties.html#method.with_sasl_mechanism
+//! [`SaslMechanism`]: ./trait.SaslMechanism.html
+
+use crate::{
+    auth::{Credentials, SASLMechanism},
+    uri::AMQPUri,
+    Error, Result,
+};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+use std::{fmt, mem, sync::Arc};
+
+/// An authentication mechanism, as advertised by the server in `connection.start`
+pub trait SaslMechanism: fmt::Debug + Send + Sync {
+    /// The name of the mechanism, such as "PLAIN"
+    fn name(&self) -> &str;
+
+    /// Start a new authentication exchange with the server using these credentials
+    fn start(&self, credentials: &Credentials) -> Box<dyn SaslExchange>;
+}
+
+/// The client side of one authentication exchange
+pub trait SaslExchange: Send {
+    /// The response sent along with `connection.start-ok`
+    fn initial_response(&mut self) -> Result<String>;
+
+    /// Answer a challenge sent by the server through `connection.secure`
+    fn respond(&mut self, challenge: &str) -> Result<String> {
+        Err(Error::AuthenticationError(format!(
+            "unexpected challenge: {}",
+            challenge
+        )))
+    }
+
+    /// Whether the server may end the exchange now, checked when it tunes the connection
+    ///
+    /// Mechanisms authenticating the server must not let it skip that step.
+    fn completed(&self) -> bool {
+        true
+    }
+}
+
+/// The PLAIN mechanism, sending the username and password in clear text
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Plain;
+
+impl SaslMechanism for Plain {
+    fn name(&self) -> &str {
+        "PLAIN"
+    }
+
+    fn start(&self, credentials: &Credentials) -> Box<dyn SaslExchange> {
+        Box::new(SimpleExchange::new(
+            credentials.sasl_auth_string(SASLMechanism::Plain),
+        ))
+    }
+}
+
+/// The AMQPLAIN mechanism, sending the username and password in clear text as a field table
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AMQPlain;
+
+impl SaslMechanism for AMQPlain {
+    fn name(&self) -> &str {
+        "AMQPLAIN"
+    }
+
+    fn start(&self, credentials: &Credentials) -> Box<dyn SaslExchange> {
+        Box::new(SimpleExchange::new(
+            credentials.sasl_auth_string(SASLMechanism::AMQPlain),
+        ))
+    }
+}
+
+/// The EXTERNAL mechanism, relying on the TLS client certificate for authentication
+#[derive(Clone, Copy, Debug, Default)]
+pub struct External;
+
+impl SaslMechanism for External {
+    fn name(&self) -> &str {
+        "EXTERNAL"
+    }
+
+    fn start(&self, credentials: &Credentials) -> Box<dyn SaslExchange> {
+        Box::new(SimpleExchange::new(
+            credentials.sasl_auth_string(SASLMechanism::External),
+        ))
+    }
+}
+
+/// The RABBIT-CR-DEMO mechanism, only meant for testing challenge/response with RabbitMQ
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RabbitCrDemo;
+
+impl SaslMechanism for RabbitCrDemo {
+    fn name(&self) -> &str {
+        "RABBIT-CR-DEMO"
+    }
+
+    fn start(&self, credentials: &Credentials) -> Box<dyn SaslExchange> {
+        let mut exchange =
+            SimpleExchange::new(credentials.sasl_auth_string(SASLMechanism::RabbitCrDemo));
+        exchange.answer = Some(credentials.rabbit_cr_demo_answer());
+        Box::new(exchange)
+    }
+}
+
+/// The SCRAM-SHA-256 mechanism as described in RFC 7677, which never sends the password
+/// and authenticates the server too
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScramSha256;
+
+impl SaslMechanism for ScramSha256 {
+    fn name(&self) -> &str {
+        "SCRAM-SHA-256"
+    }
+
+    fn start(&self, credentials: &Credentials) -> Box<dyn SaslExchange> {
+        Box::new(ScramExchange::new(credentials.clone(), None))
+    }
+}
+
+pub(crate) fn from_uri(uri: &AMQPUri) -> Arc<dyn SaslMechanism> {
+    match uri.query.auth_mechanism.unwrap_or_default() {
+        SASLMechanism::AMQPlain => Arc::new(AMQPlain),
+        SASLMechanism::External => Arc::new(External),
+        SASLMechanism::Plain => Arc::new(Plain),
+        SASLMechanism::RabbitCrDemo => Arc::new(RabbitCrDemo),
+    }
+}
+
+struct SimpleExchange {
+    response: String,
+    answer: Option<String>,
+}
+
+impl SimpleExchange {
+    fn new(response: String) -> Self {
+        Self {
+            response,
+            answer: None,
+        }
+    }
+}
+
+impl SaslExchange for SimpleExchange {
+    fn initial_response(&mut self) -> Result<String> {
+        Ok(self.response.clone())
+    }
+
+    fn respond(&mut self, challenge: &str) -> Result<String> {
+        self.answer.take().ok_or_else(|| {
+            Error::AuthenticationError(format!("unexpected challenge: {}", challenge))
+        })
+    }
+}
+
+/// We never use channel binding
+const GS2_HEADER: &str = "n,,";
+const NONCE_LEN: usize = 18;
+
+enum ScramState {
+    Initial(Option<String>),
+    ClientFirstSent {
+        nonce: String,
+        client_first_bare: String,
+    },
+    ClientFinalSent {
+        server_signature: Vec<u8>,
+    },
+    ServerVerified,
+    Done,
+}
+
+struct ScramExchange {
+    credentials: Credentials,
+    state: ScramState,
+}
+
+impl ScramExchange {
+    fn new(credentials: Credentials, nonce: Option<String>) -> Self {
+        Self {
+            credentials,
+            state: ScramState::Initial(nonce),
+        }
+    }
+
+    fn client_final(
+        &self,
+        nonce: &str,
+        client_first_bare: &str,
+        server_first: &str,
+    ) -> Result<(String, Vec<u8>)> {
+        let mut server_nonce = None;
+        let mut salt = None;
+        let mut iterations = None;
+        for (key, value) in scram_attributes(server_first)? {
+            match key {
+                "r" => server_nonce = Some(value),
+                "s" => salt = Some(base64::decode(value).map_err(scram_error)?),
+                "i" => iterations = Some(value.parse::<u32>().map_err(scram_error)?),
+                "m" => return Err(scram_error("unsupported mandatory extension")),
+                _ => {}
+            }
+        }
+        let server_nonce = server_nonce.ok_or_else(|| scram_error("missing nonce"))?;
+        let salt = salt.ok_or_else(|| scram_error("missing salt"))?;
+        let iterations = iterations
+            .filter(|iterations| *iterations > 0)
+            .ok_or_else(|| scram_error("missing iteration count"))?;
+        if server_nonce.len() <= nonce.len() || !server_nonce.starts_with(nonce) {
+            return Err(scram_error("server nonce doesn't extend ours"));
+        }
+
+        let mut salted_password = [0u8; 32];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(
+            self.credentials.password().as_bytes(),
+            &salt,
+            iterations,
+            &mut salted_password,
+        );
+        let client_key = hmac(&salted_password, b"Client Key");
+        let stored_key = Sha256::digest(&client_key);
+        let client_final_without_proof =
+            format!("c={},r={}", base64::encode(GS2_HEADER), server_nonce);
+        let auth_message = format!(
+            "{},{},{}",
+            client_first_bare, server_first, client_final_without_proof
+        );
+        let client_signature = hmac(&stored_key, auth_message.as_bytes());
+        let client_proof = client_key
+            .iter()
+            .zip(client_signature.iter())
+            .map(|(key, signature)| key ^ signature)
+            .collect::<Vec<u8>>();
+        let server_key = hmac(&salted_password, b"Server Key");
+        let server_signature = hmac(&server_key, auth_message.as_bytes());
+        Ok((
+            format!(
+                "{},p={}",
+                client_final_without_proof,
+                base64::encode(&client_proof)
+            ),
+            server_signature,
+        ))
+    }
+}
+
+impl SaslExchange for ScramExchange {
+    fn initial_response(&mut self) -> Result<String> {
+        let nonce = match mem::replace(&mut self.state, ScramState::Done) {
+            ScramState::Initial(Some(nonce)) => nonce,
+            ScramState::Initial(None) => generate_nonce()?,
+            _ => return Err(scram_error("exchange already started")),
+        };
+        let client_first_bare = format!("n={},r={}", sasl_name(self.credentials.username()), nonce);
+        let response = format!("{}{}", GS2_HEADER, client_first_bare);
+        self.state = ScramState::ClientFirstSent {
+            nonce,
+            client_first_bare,
+        };
+        Ok(response)
+    }
+
+    fn respond(&mut self, challenge: &str) -> Result<String> {
+        match mem::replace(&mut self.state, ScramState::Done) {
+            ScramState::ClientFirstSent {
+                nonce,
+                client_first_bare,
+            } => {
+                let (response, server_signature) =
+                    self.client_final(&nonce, &client_first_bare, challenge)?;
+                self.state = ScramState::ClientFinalSent { server_signature };
+                Ok(response)
+            }
+            ScramState::ClientFinalSent { server_signature } => {
+                for (key, value) in scram_attributes(challenge)? {
+                    match key {
+                        "e" => return Err(scram_error(format!("server error: {}", value))),
+                        "v" if base64::decode(value).map_err(scram_error)? == server_signature => {
+                            self.state = ScramState::ServerVerified;
+                            return Ok(String::default());
+                        }
+                        "v" => return Err(scram_error("invalid server signature")),
+                        _ => {}
+                    }
+                }
+                Err(scram_error("missing server signature"))
+            }
+            _ => Err(scram_error(format!("unexpected challenge: {}", challenge))),
+        }
+    }
+
+    fn completed(&self) -> bool {
+        matches!(self.state, ScramState::ServerVerified)
+    }
+}
+
+fn scram_attributes(message: &str) -> Result<Vec<(&str, &str)>> {
+    message
+        .split(',')
+        .map(|attribute| {
+            let mut parts = attribute.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key.len() == 1 => Ok((key, value)),
+                _ => Err(scram_error(format!("invalid attribute: {}", attribute))),
+            }
+        })
+        .collect()
+}
+
+fn sasl_name(username: &str) -> String {
+    username.replace('=', "=3D").replace(',', "=2C")
+}
+
+fn generate_nonce() -> Result<String> {
+    let mut bytes = [0u8; NONCE_LEN];
+    getrandom::getrandom(&mut bytes).map_err(scram_error)?;
+    Ok(base64::encode(bytes))
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC can take keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn scram_error<E: fmt::Display>(error: E) -> Error {
+    Error::AuthenticationError(format!("SCRAM-SHA-256: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_responses() {
+        let credentials = Credentials::default();
+        assert_eq!(
+            Plain.start(&credentials).initial_response().unwrap(),
+            "\0guest\0guest"
+        );
+        assert_eq!(External.start(&credentials).initial_response().unwrap(), "");
+        assert!(Plain.start(&credentials).respond("challenge").is_err());
+        let mut cr_demo = RabbitCrDemo.start(&credentials);
+        assert_eq!(cr_demo.initial_response().unwrap(), "guest");
+        assert_eq!(
+            cr_demo.respond("Please tell me your password").unwrap(),
+            "My password is guest"
+        );
+    }
+
+    // Test vector from RFC 7677
+    #[test]
+    fn scram_sha_256_exchange() {
+        let mut exchange = ScramExchange::new(
+            Credentials::new("user".into(), "pencil".into()),
+            Some("rOprNGfwEbeRWgbNEkqO".into()),
+        );
+        assert_eq!(
+            exchange.initial_response().unwrap(),
+            "n,,n=user,r=rOprNGfwEbeRWgbNEkqO"
+        );
+        assert_eq!(
+            exchange
+                .respond("r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096")
+                .unwrap(),
+            "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+        );
+        assert!(!exchange.completed());
+        assert_eq!(
+            exchange
+                .respond("v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+                .unwrap(),
+            ""
+        );
+        assert!(exchange.completed());
+    }
+
+    #[test]
+    fn scram_sha_256_rejects_bad_server() {
+        let mut exchange = ScramExchange::new(
+            Credentials::new("user".into(), "pencil".into()),
+            Some("rOprNGfwEbeRWgbNEkqO".into()),
+        );
+        exchange.initial_response().unwrap();
+        assert!(exchange
+            .respond("r=someoneelse,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096")
+            .is_err());
+
+        let mut exchange = ScramExchange::new(
+            Credentials::new("user".into(), "pencil".into()),
+            Some("rOprNGfwEbeRWgbNEkqO".into()),
+        );
+        exchange.initial_response().unwrap();
+        exchange
+            .respond("r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096")
+            .unwrap();
+        assert!(exchange
+            .respond("v=AAAATRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+            .is_err());
+        assert!(!exchange.completed());
+    }
+
+    #[test]
+    fn sasl_name_escaping() {
+        assert_eq!(sasl_name("a=b,c"), "a=3Db=2Cc");
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{
+    proxy::Proxy,
+    tcp::{self, HandshakeResult, TLSConfig},
+    uri::{AMQPScheme, AMQPUri},
+};
+use socket2::{Domain, Protocol, SockAddr, Socket, TcpKeepalive, Type};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+use tracing::trace;
+
+/// Tuning of the TCP socket connecting to the broker (or to the proxy), applied before the
+/// AMQP handshake
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// Disable Nagle's algorithm (TCP_NODELAY)
+    pub nodelay: bool,
+    /// Enable TCP keepalive (SO_KEEPALIVE) with these parameters
+    pub keepalive: Option<KeepaliveOptions>,
+    /// Size of the send buffer (SO_SNDBUF)
+    pub send_buffer_size: Option<usize>,
+    /// Size of the receive buffer (SO_RCVBUF)
+    pub recv_buffer_size: Option<usize>,
+    /// Local address to bind to before connecting
+    pub local_address: Option<IpAddr>,
+    /// Network interface to bind to (SO_BINDTODEVICE), only supported on Linux and Android
+    pub interface: Option<String>,
+}
+
+/// TCP keepalive parameters, the system defaults are used for those left unset
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeepaliveOptions {
+    /// How long the connection must be idle before the first probe is sent
+    pub idle: Option<Duration>,
+    /// Delay between two probes, where supported
+    pub interval: Option<Duration>,
+    /// Number of unanswered probes before dropping the connection, where supported
+    pub count: Option<u32>,
+}
+
+impl SocketOptions {
+    fn apply_before_connect(&self, socket: &Socket) -> io::Result<()> {
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(interface) = self.interface.as_ref() {
+            bind_device(socket, interface)?;
+        }
+        if let Some(ip) = self.local_address {
+            socket.bind(&SocketAddr::new(ip, 0).into())?;
+        }
+        Ok(())
+    }
+
+    fn apply_after_connect(&self, socket: &Socket) -> io::Result<()> {
+        if self.nodelay {
+            socket.set_nodelay(true)?;
+        }
+        if let Some(keepalive) = self.keepalive.as_ref() {
+            socket.set_keepalive(true)?;
+            socket.set_tcp_keepalive(&keepalive.to_tcp_keepalive())?;
+        }
+        Ok(())
+    }
+
+    /// Connect to the first address `host` resolves to which accepts the connection
+    pub(crate) fn connect(
+        &self,
+        host: &str,
+        port: u16,
+        timeout: Option<Duration>,
+    ) -> io::Result<TcpStream> {
+        let mut last_error = None;
+        for addr in (host, port).to_socket_addrs()? {
+            if self
+                .local_address
+                .map_or(false, |ip| ip.is_ipv4() != addr.is_ipv4())
+            {
+                continue;
+            }
+            match self.connect_addr(addr, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => {
+                    trace!(%addr, %err, "Failed to connect.");
+                    last_error = Some(err);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} didn't resolve to any usable address", host),
+            )
+        }))
+    }
+
+    fn connect_addr(&self, addr: SocketAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        self.apply_before_connect(&socket)?;
+        let addr = SockAddr::from(addr);
+        match timeout {
+            Some(timeout) => socket.connect_timeout(&addr, timeout)?,
+            None => socket.connect(&addr)?,
+        }
+        self.apply_after_connect(&socket)?;
+        Ok(socket.into())
+    }
+}
+
+impl KeepaliveOptions {
+    fn to_tcp_keepalive(&self) -> TcpKeepalive {
+        let mut keepalive = TcpKeepalive::new();
+        if let Some(idle) = self.idle {
+            keepalive = keepalive.with_time(idle);
+        }
+        #[cfg(any(
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "linux",
+            target_os = "netbsd",
+            target_vendor = "apple",
+            windows,
+        ))]
+        {
+            if let Some(interval) = self.interval {
+                keepalive = keepalive.with_interval(interval);
+            }
+        }
+        #[cfg(any(
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "linux",
+            target_os = "netbsd",
+            target_vendor = "apple",
+        ))]
+        {
+            if let Some(count) = self.count {
+                keepalive = keepalive.with_retries(count);
+            }
+        }
+        keepalive
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn bind_device(socket: &Socket, interface: &str) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+fn bind_device(_socket: &Socket, _interface: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "binding to an interface isn't supported on this platform",
+    ))
+}
+
+/// Connect to the broker, through the proxy if any, then go through the TLS handshake for
+/// amqps
+pub(crate) fn connect_uri(
+    uri: &AMQPUri,
+    proxy: Option<&Proxy>,
+    options: &SocketOptions,
+    config: TLSConfig<'_, '_, '_>,
+) -> HandshakeResult {
+    let timeout = uri.query.connection_timeout.map(Duration::from_millis);
+    let (host, port) = (uri.authority.host.as_str(), uri.authority.port);
+    let stream = match proxy {
+        Some(proxy) => proxy.tunnel(host, port, timeout, options)?,
+        None => options.connect(host, port, timeout)?,
+    };
+    // mio expects the socket to already be in non-blocking mode
+    stream.set_nonblocking(true)?;
+    let stream = tcp::TcpStream::from_std(stream)?;
+    match uri.scheme {
+        AMQPScheme::AMQP => Ok(stream),
+        AMQPScheme::AMQPS => stream.into_tls(host, config),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn apply_socket_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let options = SocketOptions {
+            nodelay: true,
+            keepalive: Some(KeepaliveOptions {
+                idle: Some(Duration::from_secs(30)),
+                interval: Some(Duration::from_secs(5)),
+                count: Some(3),
+            }),
+            recv_buffer_size: Some(64 * 1024),
+            local_address: Some("127.0.0.1".parse().unwrap()),
+            ..SocketOptions::default()
+        };
+        let stream = options
+            .connect("localhost", port, Some(Duration::from_secs(5)))
+            .unwrap();
+        assert!(stream.nodelay().unwrap());
+        assert_eq!(
+            stream.local_addr().unwrap().ip(),
+            options.local_address.unwrap()
+        );
+        let socket = Socket::from(stream);
+        assert!(socket.keepalive().unwrap());
+        assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+    }
+}
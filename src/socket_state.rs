@@ -49,6 +49,11 @@ impl SocketState {
         self.handle_event(self.events.recv().expect("waiting for socket event failed"))
     }
 
+    pub(crate) async fn wait_async(&mut self) {
+        let event = self.events.recv_async().await;
+        self.handle_event(event.expect("waiting for socket event failed"))
+    }
+
     pub(crate) fn handle_read_result(
         &mut self,
         result: Result<()>,
@@ -75,6 +80,11 @@ impl SocketState {
         Ok(())
     }
 
+    pub(crate) fn reset(&mut self) {
+        self.readable = true;
+        self.writable = true;
+    }
+
     pub(crate) fn handle(&self) -> SocketStateHandle {
         self.handle.clone()
     }
@@ -1,4 +1,8 @@
-use crate::{tcp, Error, Result};
+use crate::{
+    tcp,
+    transport::{Transport, TransportSocket},
+    Error, Result,
+};
 use std::{
     convert::TryFrom,
     fmt,
@@ -27,21 +31,24 @@ impl TryFrom<tcp::HandshakeResult> for TcpStream {
 }
 
 impl TcpStream {
-    pub(crate) fn inner(&self) -> &tcp::TcpStream {
+    fn inner(&self) -> &tcp::TcpStream {
         match self.0 {
             Inner::Connected(ref stream) => stream,
             Inner::Handshaking(ref handshaker) => handshaker.as_ref().unwrap().get_ref(),
         }
     }
+}
 
-    pub(crate) fn inner_mut(&mut self) -> &mut tcp::TcpStream {
-        match self.0 {
-            Inner::Connected(ref mut stream) => stream,
-            Inner::Handshaking(ref mut handshaker) => handshaker.as_mut().unwrap().get_mut(),
-        }
+impl Transport for TcpStream {
+    fn socket(&self) -> TransportSocket {
+        TransportSocket::new(self.inner())
+    }
+
+    fn peer(&self) -> Option<String> {
+        self.inner().peer_addr().ok().map(|peer| peer.to_string())
     }
 
-    pub(crate) fn is_handshaking(&self) -> bool {
+    fn is_handshaking(&self) -> bool {
         if let Inner::Handshaking(_) = self.0 {
             true
         } else {
@@ -49,7 +56,7 @@ impl TcpStream {
         }
     }
 
-    pub(crate) fn handshake(&mut self) -> Result<()> {
+    fn handshake(&mut self) -> Result<()> {
         if let Inner::Handshaking(ref mut handshaker) = self.0 {
             match handshaker.take().unwrap().handshake() {
                 Ok(stream) => self.0 = Inner::Connected(stream),
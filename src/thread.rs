@@ -1,11 +1,17 @@
 use crate::Result;
+use flume::{Receiver, Sender};
 use parking_lot::Mutex;
 use std::{sync::Arc, thread};
 
 pub type JoinHandle = thread::JoinHandle<Result<()>>;
 
+enum Handle {
+    Thread(JoinHandle),
+    Task(Receiver<Result<()>>),
+}
+
 #[derive(Clone)]
-pub struct ThreadHandle(Arc<Mutex<Option<JoinHandle>>>);
+pub struct ThreadHandle(Arc<Mutex<Option<Handle>>>);
 
 impl Default for ThreadHandle {
     fn default() -> Self {
@@ -15,18 +21,32 @@ impl Default for ThreadHandle {
 
 impl ThreadHandle {
     pub(crate) fn register(&self, handle: JoinHandle) {
-        *self.0.lock() = Some(handle);
+        *self.0.lock() = Some(Handle::Thread(handle));
     }
 
-    fn take(&self) -> Option<JoinHandle> {
+    /// Track a task spawned on the executor, which reports its result through the returned sender
+    pub(crate) fn register_task(&self) -> Sender<Result<()>> {
+        let (sender, receiver) = flume::bounded(1);
+        *self.0.lock() = Some(Handle::Task(receiver));
+        sender
+    }
+
+    fn take(&self) -> Option<Handle> {
         self.0.lock().take()
     }
 
     pub(crate) fn wait(&self, context: &'static str) -> Result<()> {
-        if let Some(handle) = self.take() {
-            if handle.thread().id() != thread::current().id() {
+        match self.take() {
+            Some(Handle::Thread(handle)) if handle.thread().id() != thread::current().id() => {
                 handle.join().expect(context)?;
             }
+            Some(Handle::Task(receiver)) => {
+                // The task got dropped along with the executor if the sender is gone
+                if let Ok(result) = receiver.recv() {
+                    result?;
+                }
+            }
+            _ => {}
         }
         Ok(())
     }
@@ -0,0 +1,704 @@
+use crate::{
+    options::{ExchangeDeclareOptions, QueueDeclareOptions},
+    protocol::{AMQPErrorKind, AMQPSoftError},
+    topology_registry::TopologyRegistry,
+    types::{AMQPValue, FieldTable},
+    Error, ExchangeKind, Result,
+};
+use std::collections::HashSet;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A set of exchanges, queues and bindings to declare together
+///
+/// The serialized form (with the `serde` feature) follows the one of RabbitMQ's definitions
+/// exports, with plain values for the arguments.
+///
+/// ```rust
+/// use lapin::{topology::*, ExchangeKind};
+///
+/// let topology = Topology::default()
+///     .with_exchange(ExchangeDefinition::new("events", ExchangeKind::Topic).durable())
+///     .with_queue(QueueDefinition::new("billing").durable())
+///     .with_binding(BindingDefinition::queue("events", "billing", "invoice.*"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Topology {
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub exchanges: Vec<ExchangeDefinition>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub queues: Vec<QueueDefinition>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bindings: Vec<BindingDefinition>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct ExchangeDefinition {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(rename = "type", default))]
+    pub kind: ExchangeKind,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub durable: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub auto_delete: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub internal: bool,
+    #[cfg_attr(feature = "serde", serde(default, with = "arguments"))]
+    pub arguments: FieldTable,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct QueueDefinition {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub durable: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub exclusive: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub auto_delete: bool,
+    #[cfg_attr(feature = "serde", serde(default, with = "arguments"))]
+    pub arguments: FieldTable,
+}
+
+/// Whether a binding routes messages to a queue or to another exchange
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize, Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum DestinationType {
+    Queue,
+    Exchange,
+}
+
+impl Default for DestinationType {
+    fn default() -> Self {
+        DestinationType::Queue
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct BindingDefinition {
+    /// The exchange messages come from
+    pub source: String,
+    /// The queue or exchange messages are routed to
+    pub destination: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub destination_type: DestinationType,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub routing_key: String,
+    #[cfg_attr(feature = "serde", serde(default, with = "arguments"))]
+    pub arguments: FieldTable,
+}
+
+/// A difference between a [`Topology`] and what actually exists on the server
+///
+/// [`Topology`]: ./struct.Topology.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TopologyDrift {
+    MissingExchange(String),
+    MissingQueue(String),
+    /// The exchange exists with another kind, durability, auto_delete, internal or arguments
+    InequivalentExchange {
+        name: String,
+        reason: String,
+    },
+    /// The queue exists with another durability, exclusivity, auto_delete or arguments
+    InequivalentQueue {
+        name: String,
+        reason: String,
+    },
+}
+
+impl Topology {
+    pub fn with_exchange(mut self, exchange: ExchangeDefinition) -> Self {
+        self.exchanges.push(exchange);
+        self
+    }
+
+    pub fn with_queue(mut self, queue: QueueDefinition) -> Self {
+        self.queues.push(queue);
+        self
+    }
+
+    pub fn with_binding(mut self, binding: BindingDefinition) -> Self {
+        self.bindings.push(binding);
+        self
+    }
+
+    /// The exchanges, ordered so that alternate exchanges get declared before the exchanges
+    /// using them
+    pub(crate) fn ordered_exchanges(&self) -> Vec<&ExchangeDefinition> {
+        fn visit<'a>(
+            topology: &'a Topology,
+            exchange: &'a ExchangeDefinition,
+            visited: &mut HashSet<&'a str>,
+            ordered: &mut Vec<&'a ExchangeDefinition>,
+        ) {
+            if !visited.insert(exchange.name.as_str()) {
+                return;
+            }
+            if let Some(alternate) = string_argument(&exchange.arguments, "alternate-exchange") {
+                if let Some(dependency) = topology.exchanges.iter().find(|e| e.name == alternate) {
+                    visit(topology, dependency, visited, ordered);
+                }
+            }
+            ordered.push(exchange);
+        }
+
+        let mut visited = HashSet::new();
+        let mut ordered = Vec::with_capacity(self.exchanges.len());
+        for exchange in &self.exchanges {
+            visit(self, exchange, &mut visited, &mut ordered);
+        }
+        ordered
+    }
+
+    pub(crate) fn from_registry(registry: &TopologyRegistry) -> Self {
+        let queues = registry.queues();
+        let server_named = |name: &str| {
+            queues
+                .iter()
+                .any(|q| q.server_named && q.name.as_str() == name)
+        };
+        let exchanges = registry
+            .exchanges()
+            .into_iter()
+            .map(|exchange| ExchangeDefinition {
+                name: exchange.name.as_str().into(),
+                kind: exchange.kind,
+                durable: exchange.options.durable,
+                auto_delete: exchange.options.auto_delete,
+                internal: exchange.options.internal,
+                arguments: exchange.arguments,
+            })
+            .collect();
+        let bindings = registry
+            .bindings()
+            .into_iter()
+            .filter(|binding| !server_named(binding.queue.as_str()))
+            .map(|binding| {
+                BindingDefinition::queue(
+                    binding.exchange.as_str(),
+                    binding.queue.as_str(),
+                    binding.routing_key.as_str(),
+                )
+                .with_arguments(binding.arguments)
+            })
+            .chain(registry.exchange_bindings().into_iter().map(|binding| {
+                BindingDefinition::exchange(
+                    binding.source.as_str(),
+                    binding.destination.as_str(),
+                    binding.routing_key.as_str(),
+                )
+                .with_arguments(binding.arguments)
+            }))
+            .collect();
+        let queues = queues
+            .iter()
+            .filter(|queue| !queue.server_named)
+            .map(|queue| QueueDefinition {
+                name: queue.name.as_str().into(),
+                durable: queue.options.durable,
+                exclusive: queue.options.exclusive,
+                auto_delete: queue.options.auto_delete,
+                arguments: queue.arguments.clone(),
+            })
+            .collect();
+        Self {
+            exchanges,
+            queues,
+            bindings,
+        }
+    }
+}
+
+#[cfg(feature = "definitions")]
+impl Topology {
+    /// Parse the `definitions.json` exported by RabbitMQ's management plugin, keeping what
+    /// belongs to `vhost`
+    ///
+    /// The server's own `amq.*` exchanges are skipped as they cannot be declared.
+    /// Fails with `Error::InvalidDefinitions` if they cannot be parsed.
+    pub fn from_definitions(definitions: &str, vhost: &str) -> Result<Self> {
+        let definitions: Definitions = serde_json::from_str(definitions)
+            .map_err(|err| Error::InvalidDefinitions(err.to_string()))?;
+        Ok(Self {
+            exchanges: definitions
+                .exchanges
+                .into_iter()
+                .filter(|e| e.vhost == vhost && !e.definition.name.starts_with("amq."))
+                .map(|e| e.definition)
+                .collect(),
+            queues: definitions
+                .queues
+                .into_iter()
+                .filter(|q| q.vhost == vhost)
+                .map(|q| q.definition)
+                .collect(),
+            bindings: definitions
+                .bindings
+                .into_iter()
+                .filter(|b| b.vhost == vhost)
+                .map(|b| b.definition)
+                .collect(),
+        })
+    }
+
+    /// Export this topology in the `definitions.json` format for `vhost`, which can be
+    /// imported through RabbitMQ's management plugin
+    pub fn to_definitions(&self, vhost: &str) -> Result<String> {
+        fn vhosted<T: Clone>(definitions: &[T], vhost: &str) -> Vec<Vhosted<T>> {
+            definitions
+                .iter()
+                .map(|definition| Vhosted {
+                    vhost: vhost.into(),
+                    definition: definition.clone(),
+                })
+                .collect()
+        }
+
+        let definitions = Definitions {
+            exchanges: vhosted(&self.exchanges, vhost),
+            queues: vhosted(&self.queues, vhost),
+            bindings: vhosted(&self.bindings, vhost),
+        };
+        serde_json::to_string_pretty(&definitions)
+            .map_err(|err| Error::InvalidDefinitions(err.to_string()))
+    }
+}
+
+/// The part of RabbitMQ's definitions we care about, the rest (users, policies...) is ignored
+#[cfg(feature = "definitions")]
+#[derive(Deserialize, Serialize)]
+struct Definitions {
+    #[serde(default)]
+    exchanges: Vec<Vhosted<ExchangeDefinition>>,
+    #[serde(default)]
+    queues: Vec<Vhosted<QueueDefinition>>,
+    #[serde(default)]
+    bindings: Vec<Vhosted<BindingDefinition>>,
+}
+
+#[cfg(feature = "definitions")]
+#[derive(Deserialize, Serialize)]
+struct Vhosted<T> {
+    vhost: String,
+    #[serde(flatten)]
+    definition: T,
+}
+
+impl ExchangeDefinition {
+    pub fn new(name: &str, kind: ExchangeKind) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            ..Self::default()
+        }
+    }
+
+    pub fn durable(mut self) -> Self {
+        self.durable = true;
+        self
+    }
+
+    pub fn auto_delete(mut self) -> Self {
+        self.auto_delete = true;
+        self
+    }
+
+    pub fn internal(mut self) -> Self {
+        self.internal = true;
+        self
+    }
+
+    pub fn with_arguments(mut self, arguments: FieldTable) -> Self {
+        self.arguments = arguments;
+        self
+    }
+
+    pub(crate) fn options(&self, passive: bool) -> ExchangeDeclareOptions {
+        ExchangeDeclareOptions {
+            passive,
+            durable: self.durable,
+            auto_delete: self.auto_delete,
+            internal: self.internal,
+            nowait: false,
+        }
+    }
+}
+
+impl QueueDefinition {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn durable(mut self) -> Self {
+        self.durable = true;
+        self
+    }
+
+    pub fn exclusive(mut self) -> Self {
+        self.exclusive = true;
+        self
+    }
+
+    pub fn auto_delete(mut self) -> Self {
+        self.auto_delete = true;
+        self
+    }
+
+    pub fn with_arguments(mut self, arguments: FieldTable) -> Self {
+        self.arguments = arguments;
+        self
+    }
+
+    pub(crate) fn options(&self, passive: bool) -> QueueDeclareOptions {
+        QueueDeclareOptions {
+            passive,
+            durable: self.durable,
+            exclusive: self.exclusive,
+            auto_delete: self.auto_delete,
+            nowait: false,
+        }
+    }
+}
+
+impl BindingDefinition {
+    /// Route messages from the `source` exchange to the `destination` queue
+    pub fn queue(source: &str, destination: &str, routing_key: &str) -> Self {
+        Self {
+            source: source.into(),
+            destination: destination.into(),
+            destination_type: DestinationType::Queue,
+            routing_key: routing_key.into(),
+            arguments: FieldTable::default(),
+        }
+    }
+
+    /// Route messages from the `source` exchange to the `destination` exchange
+    pub fn exchange(source: &str, destination: &str, routing_key: &str) -> Self {
+        Self {
+            destination_type: DestinationType::Exchange,
+            ..Self::queue(source, destination, routing_key)
+        }
+    }
+
+    pub fn with_arguments(mut self, arguments: FieldTable) -> Self {
+        self.arguments = arguments;
+        self
+    }
+}
+
+/// Whether a passive declaration failed because the entity doesn't exist
+pub(crate) fn missing<T>(declared: Result<T>) -> Result<bool> {
+    match declared {
+        Ok(_) => Ok(false),
+        Err(Error::ProtocolError(error))
+            if error.kind() == &AMQPErrorKind::Soft(AMQPSoftError::NOTFOUND) =>
+        {
+            Ok(true)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Why the server refused to declare again an existing entity, if it's been declared with
+/// other parameters
+pub(crate) fn inequivalent<T>(declared: Result<T>) -> Result<Option<String>> {
+    match declared {
+        Ok(_) => Ok(None),
+        Err(Error::ProtocolError(error))
+            if error.kind() == &AMQPErrorKind::Soft(AMQPSoftError::PRECONDITIONFAILED) =>
+        {
+            Ok(Some(error.get_message().to_string()))
+        }
+        Err(error) => Err(error),
+    }
+}
+
+fn string_argument(arguments: &FieldTable, key: &str) -> Option<String> {
+    arguments
+        .inner()
+        .iter()
+        .find(|(k, _)| k.as_str() == key)
+        .and_then(|(_, value)| match value {
+            AMQPValue::LongString(s) => Some(s.to_string()),
+            AMQPValue::ShortString(s) => Some(s.to_string()),
+            _ => None,
+        })
+}
+
+/// (De)serialize a FieldTable from plain values, as found in RabbitMQ's definitions
+#[cfg(feature = "serde")]
+pub(crate) mod arguments {
+    use crate::types::{AMQPValue, FieldArray, FieldTable};
+    use serde::{
+        de::{self, Deserializer, MapAccess, SeqAccess, Visitor},
+        ser::{SerializeMap, SerializeSeq, Serializer},
+        Deserialize, Serialize,
+    };
+    use std::fmt;
+
+    pub(crate) fn serialize<S: Serializer>(
+        arguments: &FieldTable,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        PlainTable(arguments).serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<FieldTable, D::Error> {
+        match PlainValue::deserialize(deserializer)?.0 {
+            AMQPValue::FieldTable(arguments) => Ok(arguments),
+            AMQPValue::Void => Ok(FieldTable::default()),
+            _ => Err(de::Error::custom("arguments should be a map")),
+        }
+    }
+
+    struct PlainTable<'a>(&'a FieldTable);
+
+    impl Serialize for PlainTable<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(self.0.inner().len()))?;
+            for (key, value) in self.0.inner() {
+                map.serialize_entry(key.as_str(), &PlainValueRef(value))?;
+            }
+            map.end()
+        }
+    }
+
+    struct PlainValueRef<'a>(&'a AMQPValue);
+
+    impl Serialize for PlainValueRef<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self.0 {
+                AMQPValue::Boolean(v) => serializer.serialize_bool(*v),
+                AMQPValue::ShortShortInt(v) => serializer.serialize_i8(*v),
+                AMQPValue::ShortShortUInt(v) => serializer.serialize_u8(*v),
+                AMQPValue::ShortInt(v) => serializer.serialize_i16(*v),
+                AMQPValue::ShortUInt(v) => serializer.serialize_u16(*v),
+                AMQPValue::LongInt(v) => serializer.serialize_i32(*v),
+                AMQPValue::LongUInt(v) => serializer.serialize_u32(*v),
+                AMQPValue::LongLongInt(v) => serializer.serialize_i64(*v),
+                AMQPValue::Float(v) => serializer.serialize_f32(*v),
+                AMQPValue::Double(v) => serializer.serialize_f64(*v),
+                AMQPValue::DecimalValue(v) => {
+                    serializer.serialize_f64(f64::from(v.value) / 10f64.powi(i32::from(v.scale)))
+                }
+                AMQPValue::ShortString(v) => serializer.serialize_str(v.as_str()),
+                AMQPValue::LongString(v) => serializer.serialize_str(&v.to_string()),
+                AMQPValue::FieldArray(v) => {
+                    let mut seq = serializer.serialize_seq(Some(v.as_slice().len()))?;
+                    for value in v.as_slice() {
+                        seq.serialize_element(&PlainValueRef(value))?;
+                    }
+                    seq.end()
+                }
+                AMQPValue::Timestamp(v) => serializer.serialize_u64(*v),
+                AMQPValue::FieldTable(v) => PlainTable(v).serialize(serializer),
+                AMQPValue::ByteArray(v) => serializer.serialize_bytes(v.as_slice()),
+                AMQPValue::Void => serializer.serialize_unit(),
+            }
+        }
+    }
+
+    struct PlainValue(AMQPValue);
+
+    impl<'de> Deserialize<'de> for PlainValue {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(PlainValueVisitor)
+        }
+    }
+
+    struct PlainValueVisitor;
+
+    impl<'de> Visitor<'de> for PlainValueVisitor {
+        type Value = PlainValue;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("a plain argument value")
+        }
+
+        fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+            Ok(PlainValue(AMQPValue::Boolean(v)))
+        }
+
+        fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+            Ok(PlainValue(AMQPValue::LongLongInt(v)))
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            // RabbitMQ doesn't support unsigned 64 bits integers
+            if v > i64::max_value() as u64 {
+                return Err(E::custom(format!("{} doesn't fit in a signed integer", v)));
+            }
+            Ok(PlainValue(AMQPValue::LongLongInt(v as i64)))
+        }
+
+        fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+            Ok(PlainValue(AMQPValue::Double(v)))
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(PlainValue(AMQPValue::LongString(v.into())))
+        }
+
+        fn visit_unit<E>(self) -> Result<Self::Value, E> {
+            Ok(PlainValue(AMQPValue::Void))
+        }
+
+        fn visit_none<E>(self) -> Result<Self::Value, E> {
+            Ok(PlainValue(AMQPValue::Void))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut values = Vec::new();
+            while let Some(PlainValue(value)) = seq.next_element()? {
+                values.push(value);
+            }
+            Ok(PlainValue(AMQPValue::FieldArray(FieldArray::from(values))))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut table = FieldTable::default();
+            while let Some((key, PlainValue(value))) = map.next_entry::<String, PlainValue>()? {
+                table.insert(key.into(), value);
+            }
+            Ok(PlainValue(AMQPValue::FieldTable(table)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternate_exchanges_come_first() {
+        let mut arguments = FieldTable::default();
+        arguments.insert(
+            "alternate-exchange".into(),
+            AMQPValue::LongString("unrouted".into()),
+        );
+        let topology = Topology::default()
+            .with_exchange(
+                ExchangeDefinition::new("events", ExchangeKind::Topic).with_arguments(arguments),
+            )
+            .with_exchange(ExchangeDefinition::new("audit", ExchangeKind::Fanout))
+            .with_exchange(ExchangeDefinition::new("unrouted", ExchangeKind::Fanout));
+        let names = topology
+            .ordered_exchanges()
+            .into_iter()
+            .map(|e| e.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["unrouted", "events", "audit"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_plain_arguments() {
+        let topology: Topology = serde_json::from_str(
+            r#"{
+                "exchanges": [{"name": "delayed", "type": "x-delayed-message", "durable": true,
+                               "arguments": {"x-delayed-type": "direct"}}],
+                "queues": [{"name": "jobs", "arguments": {"x-max-length": 100, "x-lazy": true}}],
+                "bindings": [{"source": "delayed", "destination": "jobs", "routing_key": "job"}]
+            }"#,
+        )
+        .unwrap();
+        let exchange = &topology.exchanges[0];
+        assert_eq!(
+            exchange.kind,
+            ExchangeKind::Custom("x-delayed-message".into())
+        );
+        assert!(exchange.durable);
+        assert_eq!(
+            string_argument(&exchange.arguments, "x-delayed-type"),
+            Some("direct".into())
+        );
+        let mut arguments = FieldTable::default();
+        arguments.insert("x-lazy".into(), AMQPValue::Boolean(true));
+        arguments.insert("x-max-length".into(), AMQPValue::LongLongInt(100));
+        assert_eq!(topology.queues[0].arguments, arguments);
+        assert_eq!(
+            topology.bindings[0],
+            BindingDefinition::queue("delayed", "jobs", "job")
+        );
+        let reparsed: Topology =
+            serde_json::from_str(&serde_json::to_string(&topology).unwrap()).unwrap();
+        assert_eq!(reparsed, topology);
+    }
+
+    #[cfg(feature = "definitions")]
+    #[test]
+    fn import_definitions_of_a_vhost() {
+        let definitions = r##"{
+            "rabbit_version": "3.8.9",
+            "users": [{"name": "guest", "tags": "administrator"}],
+            "exchanges": [
+                {"name": "amq.direct", "vhost": "/", "type": "direct", "durable": true,
+                 "auto_delete": false, "internal": false, "arguments": {}},
+                {"name": "events", "vhost": "/", "type": "topic", "durable": true,
+                 "auto_delete": false, "internal": false, "arguments": {}},
+                {"name": "events", "vhost": "staging", "type": "fanout", "durable": true,
+                 "auto_delete": false, "internal": false, "arguments": {}}
+            ],
+            "queues": [
+                {"name": "billing", "vhost": "/", "durable": true, "auto_delete": false,
+                 "arguments": {"x-queue-type": "quorum"}}
+            ],
+            "bindings": [
+                {"source": "events", "vhost": "/", "destination": "billing",
+                 "destination_type": "queue", "routing_key": "invoice.*", "arguments": {}},
+                {"source": "events", "vhost": "/", "destination": "audit",
+                 "destination_type": "exchange", "routing_key": "#", "arguments": {}}
+            ]
+        }"##;
+        let topology = Topology::from_definitions(definitions, "/").unwrap();
+        let mut arguments = FieldTable::default();
+        arguments.insert(
+            "x-queue-type".into(),
+            AMQPValue::LongString("quorum".into()),
+        );
+        let expected = Topology::default()
+            .with_exchange(ExchangeDefinition::new("events", ExchangeKind::Topic).durable())
+            .with_queue(
+                QueueDefinition::new("billing")
+                    .durable()
+                    .with_arguments(arguments),
+            )
+            .with_binding(BindingDefinition::queue("events", "billing", "invoice.*"))
+            .with_binding(BindingDefinition::exchange("events", "audit", "#"));
+        assert_eq!(topology, expected);
+
+        let exported = topology.to_definitions("/").unwrap();
+        assert_eq!(
+            Topology::from_definitions(&exported, "/").unwrap(),
+            expected
+        );
+        assert_eq!(
+            Topology::from_definitions(&exported, "staging").unwrap(),
+            Topology::default()
+        );
+    }
+
+    #[cfg(feature = "definitions")]
+    #[test]
+    fn invalid_definitions() {
+        assert!(matches!(
+            Topology::from_definitions(r#"{"exchanges": 42}"#, "/"),
+            Err(Error::InvalidDefinitions(_))
+        ));
+    }
+}
@@ -0,0 +1,347 @@
+use crate::{
+    options::{
+        BasicConsumeOptions, BasicQosOptions, ExchangeBindOptions, ExchangeDeclareOptions,
+        QueueBindOptions, QueueDeclareOptions,
+    },
+    types::{FieldTable, ShortString, ShortUInt},
+    ExchangeKind,
+};
+use parking_lot::Mutex;
+use std::{collections::HashMap, fmt, sync::Arc};
+
+/// Keeps track of what a channel declared so that we can replay it after a reconnection
+#[derive(Clone)]
+pub(crate) struct TopologyRegistry {
+    enabled: bool,
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ExchangeRecord {
+    pub(crate) name: ShortString,
+    pub(crate) kind: ExchangeKind,
+    pub(crate) options: ExchangeDeclareOptions,
+    pub(crate) arguments: FieldTable,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct QueueRecord {
+    pub(crate) name: ShortString,
+    pub(crate) server_named: bool,
+    pub(crate) options: QueueDeclareOptions,
+    pub(crate) arguments: FieldTable,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct BindingRecord {
+    pub(crate) queue: ShortString,
+    pub(crate) exchange: ShortString,
+    pub(crate) routing_key: ShortString,
+    pub(crate) options: QueueBindOptions,
+    pub(crate) arguments: FieldTable,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ExchangeBindingRecord {
+    pub(crate) destination: ShortString,
+    pub(crate) source: ShortString,
+    pub(crate) routing_key: ShortString,
+    pub(crate) options: ExchangeBindOptions,
+    pub(crate) arguments: FieldTable,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ConsumerRecord {
+    pub(crate) consumer_tag: ShortString,
+    pub(crate) queue: ShortString,
+    pub(crate) options: BasicConsumeOptions,
+    pub(crate) arguments: FieldTable,
+}
+
+impl TopologyRegistry {
+    /// Nothing gets recorded unless the connection recovery or the topology recording is enabled
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            inner: Default::default(),
+        }
+    }
+
+    fn with_inner<F: FnOnce(&mut Inner)>(&self, f: F) {
+        if self.enabled {
+            f(&mut self.inner.lock())
+        }
+    }
+
+    pub(crate) fn register_exchange(&self, exchange: ExchangeRecord) {
+        if exchange.options.passive || exchange.name.as_str().is_empty() {
+            return;
+        }
+        self.with_inner(|inner| {
+            inner.exchanges.retain(|e| e.name != exchange.name);
+            inner.exchanges.push(exchange);
+        });
+    }
+
+    pub(crate) fn deregister_exchange(&self, exchange: &str) {
+        self.with_inner(|inner| {
+            inner.exchanges.retain(|e| e.name.as_str() != exchange);
+            inner.bindings.retain(|b| b.exchange.as_str() != exchange);
+            inner
+                .exchange_bindings
+                .retain(|b| b.source.as_str() != exchange && b.destination.as_str() != exchange);
+        });
+    }
+
+    pub(crate) fn register_queue(&self, queue: QueueRecord) {
+        if queue.options.passive {
+            return;
+        }
+        self.with_inner(|inner| {
+            inner.queues.retain(|q| q.name != queue.name);
+            inner.queues.push(queue);
+        });
+    }
+
+    pub(crate) fn deregister_queue(&self, queue: &str) {
+        self.with_inner(|inner| {
+            inner.queues.retain(|q| q.name.as_str() != queue);
+            inner.bindings.retain(|b| b.queue.as_str() != queue);
+            inner.consumers.retain(|c| c.queue.as_str() != queue);
+        });
+    }
+
+    pub(crate) fn register_binding(&self, binding: BindingRecord) {
+        self.with_inner(|inner| {
+            if !inner.bindings.contains(&binding) {
+                inner.bindings.push(binding);
+            }
+        });
+    }
+
+    pub(crate) fn deregister_binding(
+        &self,
+        queue: &str,
+        exchange: &str,
+        routing_key: &str,
+        arguments: &FieldTable,
+    ) {
+        self.with_inner(|inner| {
+            inner.bindings.retain(|b| {
+                b.queue.as_str() != queue
+                    || b.exchange.as_str() != exchange
+                    || b.routing_key.as_str() != routing_key
+                    || &b.arguments != arguments
+            })
+        });
+    }
+
+    pub(crate) fn register_exchange_binding(&self, binding: ExchangeBindingRecord) {
+        self.with_inner(|inner| {
+            if !inner.exchange_bindings.contains(&binding) {
+                inner.exchange_bindings.push(binding);
+            }
+        });
+    }
+
+    pub(crate) fn deregister_exchange_binding(
+        &self,
+        destination: &str,
+        source: &str,
+        routing_key: &str,
+        arguments: &FieldTable,
+    ) {
+        self.with_inner(|inner| {
+            inner.exchange_bindings.retain(|b| {
+                b.destination.as_str() != destination
+                    || b.source.as_str() != source
+                    || b.routing_key.as_str() != routing_key
+                    || &b.arguments != arguments
+            })
+        });
+    }
+
+    pub(crate) fn register_consumer(&self, consumer: ConsumerRecord) {
+        self.with_inner(|inner| {
+            inner
+                .consumers
+                .retain(|c| c.consumer_tag != consumer.consumer_tag);
+            inner.consumers.push(consumer);
+        });
+    }
+
+    pub(crate) fn deregister_consumer(&self, consumer_tag: &str) {
+        self.with_inner(|inner| {
+            inner
+                .consumers
+                .retain(|c| c.consumer_tag.as_str() != consumer_tag)
+        });
+    }
+
+    pub(crate) fn set_qos(&self, prefetch_count: ShortUInt, options: BasicQosOptions) {
+        self.with_inner(|inner| inner.qos = Some((prefetch_count, options)));
+    }
+
+    pub(crate) fn qos(&self) -> Option<(ShortUInt, BasicQosOptions)> {
+        self.inner.lock().qos
+    }
+
+    pub(crate) fn set_confirm(&self) {
+        self.with_inner(|inner| inner.confirm = true);
+    }
+
+    pub(crate) fn confirm(&self) -> bool {
+        self.inner.lock().confirm
+    }
+
+    pub(crate) fn exchanges(&self) -> Vec<ExchangeRecord> {
+        self.inner.lock().exchanges.clone()
+    }
+
+    pub(crate) fn queues(&self) -> Vec<QueueRecord> {
+        self.inner.lock().queues.clone()
+    }
+
+    pub(crate) fn bindings(&self) -> Vec<BindingRecord> {
+        self.inner.lock().bindings.clone()
+    }
+
+    pub(crate) fn exchange_bindings(&self) -> Vec<ExchangeBindingRecord> {
+        self.inner.lock().exchange_bindings.clone()
+    }
+
+    pub(crate) fn consumers(&self) -> Vec<ConsumerRecord> {
+        self.inner.lock().consumers.clone()
+    }
+
+    /// Server named queues get a new name when they're redeclared, follow it
+    pub(crate) fn rename_queues(&self, renames: &HashMap<ShortString, ShortString>) {
+        if renames.is_empty() {
+            return;
+        }
+        let mut inner = self.inner.lock();
+        let rename = |name: &mut ShortString| {
+            if let Some(new_name) = renames.get(name) {
+                *name = new_name.clone();
+            }
+        };
+        inner.queues.iter_mut().for_each(|q| rename(&mut q.name));
+        inner.bindings.iter_mut().for_each(|b| rename(&mut b.queue));
+        inner
+            .consumers
+            .iter_mut()
+            .for_each(|c| rename(&mut c.queue));
+    }
+}
+
+impl fmt::Debug for TopologyRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("TopologyRegistry");
+        debug.field("enabled", &self.enabled);
+        if let Some(inner) = self.inner.try_lock() {
+            debug
+                .field("exchanges", &inner.exchanges)
+                .field("queues", &inner.queues)
+                .field("bindings", &inner.bindings)
+                .field("exchange_bindings", &inner.exchange_bindings)
+                .field("consumers", &inner.consumers)
+                .field("qos", &inner.qos)
+                .field("confirm", &inner.confirm);
+        }
+        debug.finish()
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    exchanges: Vec<ExchangeRecord>,
+    queues: Vec<QueueRecord>,
+    bindings: Vec<BindingRecord>,
+    exchange_bindings: Vec<ExchangeBindingRecord>,
+    consumers: Vec<ConsumerRecord>,
+    qos: Option<(ShortUInt, BasicQosOptions)>,
+    confirm: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(name: &str, server_named: bool) -> QueueRecord {
+        QueueRecord {
+            name: name.into(),
+            server_named,
+            options: QueueDeclareOptions::default(),
+            arguments: FieldTable::default(),
+        }
+    }
+
+    fn binding(queue: &str, exchange: &str) -> BindingRecord {
+        BindingRecord {
+            queue: queue.into(),
+            exchange: exchange.into(),
+            routing_key: "key".into(),
+            options: QueueBindOptions::default(),
+            arguments: FieldTable::default(),
+        }
+    }
+
+    #[test]
+    fn disabled_registry_records_nothing() {
+        let registry = TopologyRegistry::new(false);
+        registry.register_queue(queue("q", false));
+        registry.set_confirm();
+        assert!(registry.queues().is_empty());
+        assert!(!registry.confirm());
+    }
+
+    #[test]
+    fn deleting_a_queue_forgets_its_bindings() {
+        let registry = TopologyRegistry::new(true);
+        registry.register_queue(queue("q1", false));
+        registry.register_queue(queue("q2", false));
+        registry.register_binding(binding("q1", "ex"));
+        registry.register_binding(binding("q1", "ex"));
+        registry.register_binding(binding("q2", "ex"));
+        assert_eq!(registry.bindings().len(), 2);
+        registry.deregister_queue("q1");
+        assert_eq!(registry.queues().len(), 1);
+        assert_eq!(registry.bindings(), vec![binding("q2", "ex")]);
+    }
+
+    #[test]
+    fn server_named_queues_follow_renames() {
+        let registry = TopologyRegistry::new(true);
+        registry.register_queue(queue("amq.gen-old", true));
+        registry.register_binding(binding("amq.gen-old", "ex"));
+        let mut renames = HashMap::default();
+        renames.insert("amq.gen-old".into(), "amq.gen-new".into());
+        registry.rename_queues(&renames);
+        assert_eq!(registry.queues()[0].name.as_str(), "amq.gen-new");
+        assert_eq!(registry.bindings(), vec![binding("amq.gen-new", "ex")]);
+    }
+
+    #[test]
+    fn deleting_an_exchange_forgets_its_exchange_bindings() {
+        let registry = TopologyRegistry::new(true);
+        let binding = |destination: &str, source: &str| ExchangeBindingRecord {
+            destination: destination.into(),
+            source: source.into(),
+            routing_key: "#".into(),
+            options: ExchangeBindOptions::default(),
+            arguments: FieldTable::default(),
+        };
+        registry.register_exchange_binding(binding("audit", "events"));
+        registry.register_exchange_binding(binding("audit", "events"));
+        registry.register_exchange_binding(binding("archive", "audit"));
+        registry.register_exchange_binding(binding("archive", "logs"));
+        assert_eq!(registry.exchange_bindings().len(), 3);
+        registry.deregister_exchange("audit");
+        assert_eq!(
+            registry.exchange_bindings(),
+            vec![binding("archive", "logs")]
+        );
+        registry.deregister_exchange_binding("archive", "logs", "#", &FieldTable::default());
+        assert!(registry.exchange_bindings().is_empty());
+    }
+}
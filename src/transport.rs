@@ -0,0 +1,146 @@
+use crate::Result;
+use socket2::{SockRef, Socket};
+use std::io::{self, Read, Write};
+
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(windows)]
+use std::os::windows::io::{AsRawSocket, RawSocket};
+
+/// A connected byte stream the AMQP protocol can run over.
+///
+/// The io loop never blocks on a transport: reads and writes must fail with
+/// `io::ErrorKind::WouldBlock` when they cannot make progress, and the [`Reactor`] gets
+/// notified of readiness through the OS socket returned by [`socket`].
+///
+/// This is implemented for [`TcpStream`] and `UnixStream`, which are what the default
+/// connectors use, and can be implemented to connect through wrapped streams using
+/// [`Connection::transport_connector`]. Whatever the wrapping, [`socket`] has to be a real OS
+/// socket: the reactor registers it to get readiness events, so in-memory pipes can't be used.
+///
+/// [`Reactor`]: ./reactor/trait.Reactor.html
+/// [`socket`]: #tymethod.socket
+/// [`TcpStream`]: ./struct.TcpStream.html
+/// [`Connection::transport_connector`]: ./struct.Connection.html#method.transport_connector
+pub trait Transport: Read + Write + Send {
+    /// The OS socket the reactor polls for readiness
+    fn socket(&self) -> TransportSocket;
+
+    /// A description of the peer we're connected to, for logging purposes
+    fn peer(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether a handshake (such as TLS) still has to be driven before exchanging data
+    fn is_handshaking(&self) -> bool {
+        false
+    }
+
+    /// Make progress on the handshake, called again on the next socket event while
+    /// `is_handshaking` stays true
+    fn handshake(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn socket(&self) -> TransportSocket {
+        (**self).socket()
+    }
+
+    fn peer(&self) -> Option<String> {
+        (**self).peer()
+    }
+
+    fn is_handshaking(&self) -> bool {
+        (**self).is_handshaking()
+    }
+
+    fn handshake(&mut self) -> Result<()> {
+        (**self).handshake()
+    }
+}
+
+#[cfg(unix)]
+impl Transport for std::os::unix::net::UnixStream {
+    fn socket(&self) -> TransportSocket {
+        TransportSocket::new(self)
+    }
+
+    fn peer(&self) -> Option<String> {
+        self.peer_addr()
+            .ok()
+            .and_then(|peer| peer.as_pathname().map(|path| path.display().to_string()))
+    }
+}
+
+#[cfg(unix)]
+type RawHandle = RawFd;
+#[cfg(windows)]
+type RawHandle = RawSocket;
+
+/// The OS socket of a [`Transport`], registered with the [`Reactor`] to get readiness events.
+///
+/// It doesn't own the socket, which stays open as long as the transport it comes from.
+///
+/// [`Transport`]: ./trait.Transport.html
+/// [`Reactor`]: ./reactor/trait.Reactor.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransportSocket(RawHandle);
+
+impl TransportSocket {
+    #[cfg(unix)]
+    pub fn new<S: AsRawFd>(socket: &S) -> Self {
+        Self(socket.as_raw_fd())
+    }
+
+    #[cfg(windows)]
+    pub fn new<S: AsRawSocket>(socket: &S) -> Self {
+        Self(socket.as_raw_socket())
+    }
+
+    /// Duplicate the socket, for reactors which can still be polling it after the transport
+    /// got dropped
+    ///
+    /// Once the transport is gone, the OS can give the same descriptor to another connection.
+    /// Registering a duplicate makes sure that the reactor only ever deregisters its own.
+    pub fn try_clone(&self) -> io::Result<OwnedTransportSocket> {
+        SockRef::from(self).try_clone().map(OwnedTransportSocket)
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for TransportSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+#[cfg(windows)]
+impl AsRawSocket for TransportSocket {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.0
+    }
+}
+
+/// A duplicate of a [`TransportSocket`], closed on drop
+///
+/// It is only meant to be polled for readiness, whatever the kind of the socket.
+///
+/// [`TransportSocket`]: ./struct.TransportSocket.html
+#[derive(Debug)]
+pub struct OwnedTransportSocket(Socket);
+
+#[cfg(unix)]
+impl AsRawFd for OwnedTransportSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+#[cfg(windows)]
+impl AsRawSocket for OwnedTransportSocket {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.0.as_raw_socket()
+    }
+}
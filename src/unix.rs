@@ -0,0 +1,89 @@
+use crate::{transport::Transport, uri::AMQPUri, Result};
+use std::path::{Path, PathBuf};
+
+/// The scheme of the URIs connecting through a Unix domain socket
+pub(crate) const SCHEME: &str = "amqp+unix://";
+
+/// Parse a `amqp+unix://[user:password@]/path/to/socket[?vhost=%2f&heartbeat=10...]` URI into
+/// the `AMQPUri`, whose host is `localhost`, and the socket path
+///
+/// The vhost, which defaults to `/`, goes in the query string as the path is already taken.
+pub(crate) fn parse(uri: &str) -> std::result::Result<(AMQPUri, PathBuf), String> {
+    if !cfg!(unix) {
+        return Err("Unix domain sockets aren't supported on this platform".into());
+    }
+    let rest = uri
+        .strip_prefix(SCHEME)
+        .ok_or_else(|| format!("Invalid Unix socket URI: '{}'", uri))?;
+    let (rest, query) = match rest.find('?') {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, ""),
+    };
+    let (userinfo, path) = match rest.find('/') {
+        Some(idx) => rest.split_at(idx),
+        None => return Err(format!("Missing socket path in URI: '{}'", uri)),
+    };
+    if path.len() < 2 {
+        return Err(format!("Missing socket path in URI: '{}'", uri));
+    }
+    // Let the regular parser handle the credentials, the query string and the percent-decoding
+    // by passing the socket path as the vhost
+    let mut parsed = format!("amqp://{}localhost{}{}", userinfo, path, query).parse::<AMQPUri>()?;
+    let path = PathBuf::from(format!("/{}", parsed.vhost));
+    parsed.vhost = match vhost_param(query) {
+        Some(vhost) => {
+            format!("amqp://localhost/{}", vhost)
+                .parse::<AMQPUri>()?
+                .vhost
+        }
+        None => "/".into(),
+    };
+    Ok((parsed, path))
+}
+
+#[cfg(unix)]
+pub(crate) fn connect(path: &Path) -> Result<Box<dyn Transport>> {
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    stream.set_nonblocking(true)?;
+    Ok(Box::new(stream))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn connect(_path: &Path) -> Result<Box<dyn Transport>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "Unix domain sockets aren't supported on this platform",
+    )
+    .into())
+}
+
+fn vhost_param(query: &str) -> Option<&str> {
+    query
+        .trim_start_matches('?')
+        .split('&')
+        .find_map(|param| param.strip_prefix("vhost="))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_unix_uri() {
+        let (uri, path) =
+            parse("amqp+unix://guest:secret@/run/rabbitmq/amqp%20sock?vhost=%2fprod&heartbeat=10")
+                .unwrap();
+        assert_eq!(path, Path::new("/run/rabbitmq/amqp sock"));
+        assert_eq!(uri.authority.host, "localhost");
+        assert_eq!(uri.authority.userinfo.username, "guest");
+        assert_eq!(uri.authority.userinfo.password, "secret");
+        assert_eq!(uri.vhost, "/prod");
+        assert_eq!(uri.query.heartbeat, Some(10));
+
+        let (uri, path) = parse("amqp+unix:///tmp/amqp.sock").unwrap();
+        assert_eq!(path, Path::new("/tmp/amqp.sock"));
+        assert_eq!(uri.vhost, "/");
+        assert!(parse("amqp+unix://").is_err());
+        assert!(parse("amqp+unix://guest@").is_err());
+    }
+}
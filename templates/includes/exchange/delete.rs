@@ -1 +0,0 @@
-/// Delete an exchange
@@ -0,0 +1,67 @@
+use lapin::{
+    options::*, types::FieldTable, BasicProperties, Connection, ConnectionProperties,
+    ConnectionState, IoLoopMode,
+};
+use lapin_mock::MockServer;
+
+#[test]
+fn io_loop_runs_on_the_executor() {
+    let server = MockServer::start().expect("mock server");
+    let threads = || {
+        std::fs::read_dir("/proc/self/task")
+            .map(|tasks| {
+                tasks
+                    .flatten()
+                    .filter_map(|task| std::fs::read_to_string(task.path().join("comm")).ok())
+                    .filter(|name| name.trim() == "lapin-io-loop")
+                    .count()
+            })
+            .unwrap_or(0)
+    };
+
+    let conn = async_global_executor::block_on(async {
+        let conn = Connection::connect(
+            &server.uri(),
+            ConnectionProperties::default().with_io_loop_mode(IoLoopMode::Async),
+        )
+        .await
+        .expect("connection");
+        let channel = conn.create_channel().await.expect("create_channel");
+        channel
+            .queue_declare(
+                "async-io-loop",
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .expect("queue_declare");
+        channel
+            .basic_publish(
+                "",
+                "async-io-loop",
+                BasicPublishOptions::default(),
+                b"Hello world!".to_vec(),
+                BasicProperties::default(),
+            )
+            .await
+            .expect("basic_publish");
+        let message = channel
+            .basic_get("async-io-loop", BasicGetOptions { no_ack: true })
+            .await
+            .expect("basic_get")
+            .expect("message");
+        assert_eq!(message.delivery.data, b"Hello world!");
+
+        // No dedicated thread got spawned for this connection
+        if cfg!(target_os = "linux") {
+            assert_eq!(threads(), 0);
+        }
+
+        conn.close(200, "OK").await.expect("close");
+        conn
+    });
+
+    assert_eq!(conn.status().state(), ConnectionState::Closed);
+    // Connection::run returns once the io loop task is done
+    conn.run().expect("io loop");
+}
@@ -0,0 +1,115 @@
+use lapin::{
+    blocking::Connection,
+    options::*,
+    publisher_confirm::Confirmation,
+    topology::{BindingDefinition, ExchangeDefinition, QueueDefinition, Topology},
+    types::FieldTable,
+    BasicProperties, ConnectionEvent, ConnectionProperties, ConnectionState, Endpoints,
+    ExchangeKind, RetryPolicy,
+};
+use lapin_mock::MockServer;
+
+#[test]
+fn blocking_client() {
+    let server = MockServer::start().expect("mock server");
+    let conn =
+        Connection::connect(&server.uri(), ConnectionProperties::default()).expect("connection");
+    let channel = conn.create_channel().expect("create_channel");
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .expect("confirm_select");
+    let queue = channel
+        .queue_declare(
+            "blocking",
+            QueueDeclareOptions::default(),
+            FieldTable::default(),
+        )
+        .expect("queue_declare");
+    assert_eq!(queue.name().as_str(), "blocking");
+
+    let mut consumer = channel
+        .basic_consume(
+            "blocking",
+            "blocking-consumer",
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .expect("basic_consume");
+    for _ in 0..2 {
+        let confirm = channel
+            .basic_publish(
+                "",
+                "blocking",
+                BasicPublishOptions::default(),
+                b"Hello world!".to_vec(),
+                BasicProperties::default(),
+            )
+            .expect("basic_publish")
+            .wait()
+            .expect("publisher confirm");
+        assert_eq!(confirm, Confirmation::Ack(None));
+    }
+
+    for _ in 0..2 {
+        let (channel, delivery) = consumer
+            .next()
+            .expect("consumer ended")
+            .expect("error in consumer");
+        assert_eq!(delivery.data, b"Hello world!");
+        channel
+            .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+            .expect("basic_ack");
+    }
+
+    channel
+        .basic_cancel(consumer.tag().as_str(), BasicCancelOptions::default())
+        .expect("basic_cancel");
+    // The iterator ends once the consumer got canceled
+    assert!(consumer.next().is_none());
+
+    conn.close(200, "OK").expect("close");
+    assert_eq!(conn.status().state(), ConnectionState::Closed);
+}
+
+#[test]
+fn blocking_topology_and_retries() {
+    let server = MockServer::start().expect("mock server");
+    let endpoints = server.uri().parse::<Endpoints>().expect("endpoints");
+    let conn = Connection::connect_endpoints(endpoints, ConnectionProperties::default())
+        .expect("connection");
+    let mut events = conn.events();
+    let channel = conn.create_channel().expect("create_channel");
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .expect("confirm_select");
+
+    let topology = Topology::default()
+        .with_exchange(ExchangeDefinition::new(
+            "blocking-events",
+            ExchangeKind::Topic,
+        ))
+        .with_queue(QueueDefinition::new("blocking-audit"))
+        .with_binding(BindingDefinition::queue(
+            "blocking-events",
+            "blocking-audit",
+            "#",
+        ));
+    channel.apply_topology(&topology).expect("apply_topology");
+    assert_eq!(conn.verify_topology(&topology), Ok(Vec::new()));
+
+    let confirm = channel
+        .basic_publish_with_retry(
+            "blocking-events",
+            "audit",
+            BasicPublishOptions::default(),
+            b"Hello world!".to_vec(),
+            BasicProperties::default(),
+            &RetryPolicy::default(),
+        )
+        .expect("basic_publish_with_retry");
+    assert_eq!(confirm, Confirmation::Ack(None));
+
+    channel.delete_topology(&topology).expect("delete_topology");
+    conn.close(200, "OK").expect("close");
+    assert!(matches!(events.next(), Some(ConnectionEvent::Closing)));
+}
@@ -0,0 +1,80 @@
+use futures_lite::future;
+use lapin::{options::ConfirmSelectOptions, ChannelPoolConfig, Connection, ConnectionProperties};
+use lapin_mock::MockServer;
+use std::time::Duration;
+
+#[test]
+fn channel_pool() {
+    let server = MockServer::start().expect("mock server");
+    let uri = format!("{}?channel_max=8", server.uri());
+
+    async_global_executor::block_on(async {
+        let conn = Connection::connect(&uri, ConnectionProperties::default())
+            .await
+            .expect("connection");
+
+        // Capped to the negotiated channel_max
+        let pool = conn.channel_pool(ChannelPoolConfig::default());
+        assert_eq!(pool.max_size(), 8);
+
+        let pool = conn.channel_pool(ChannelPoolConfig {
+            max_size: 2,
+            confirm: true,
+            prefetch_count: Some(10),
+        });
+        let first = pool.get().await.expect("lease");
+        assert!(first.status().confirm());
+        let id = first.id();
+        drop(first);
+        assert_eq!(pool.idle(), 1);
+
+        // Idle channels get reused
+        let first = pool.get().await.expect("lease");
+        assert_eq!(first.id(), id);
+        let second = pool.get().await.expect("lease");
+        assert_ne!(second.id(), id);
+
+        // Both are leased, wait for one to be given back
+        let third = future::or(async { Some(pool.get().await) }, async {
+            async_io::Timer::after(Duration::from_millis(100)).await;
+            None
+        })
+        .await;
+        assert!(third.is_none());
+        let (third, _) = future::zip(pool.get(), async move {
+            async_io::Timer::after(Duration::from_millis(50)).await;
+            drop(second);
+        })
+        .await;
+        let third = third.expect("lease");
+
+        // Closed channels are discarded instead of being given back
+        third.close(200, "OK").await.expect("close");
+        drop(third);
+        drop(first);
+        assert_eq!(pool.idle(), 1);
+        let channel = pool.get().await.expect("lease");
+        assert_eq!(channel.id(), id);
+        drop(channel);
+
+        // Channels switched to another mode by their lease aren't given back either
+        let pool = conn.channel_pool(ChannelPoolConfig {
+            max_size: 1,
+            ..ChannelPoolConfig::default()
+        });
+        let channel = pool.get().await.expect("lease");
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await
+            .expect("confirm_select");
+        let id = channel.id();
+        drop(channel);
+        assert_eq!(pool.idle(), 0);
+        let channel = pool.get().await.expect("lease");
+        assert_ne!(channel.id(), id);
+        assert!(!channel.status().confirm());
+        drop(channel);
+
+        conn.close(200, "OK").await.expect("close");
+    });
+}
@@ -0,0 +1,49 @@
+use futures_lite::{future, StreamExt};
+use lapin::{Connection, ConnectionEvent, ConnectionProperties};
+use lapin_mock::MockServer;
+use std::time::Duration;
+
+#[test]
+fn connections_only_see_their_own_events() {
+    let server = MockServer::start().expect("mock server");
+    let properties = ConnectionProperties::default();
+    let mut all_events = properties.events();
+
+    async_global_executor::block_on(async {
+        let first = Connection::connect(&server.uri(), properties.clone())
+            .await
+            .expect("connection");
+        let second = Connection::connect(&server.uri(), properties)
+            .await
+            .expect("connection");
+        let mut first_events = first.events();
+        let mut second_events = second.events();
+
+        first.close(200, "OK").await.expect("close");
+        assert!(matches!(
+            first_events.next().await,
+            Some(ConnectionEvent::Closing)
+        ));
+        let unexpected = future::or(async { second_events.next().await }, async {
+            async_io::Timer::after(Duration::from_millis(100)).await;
+            None
+        })
+        .await;
+        assert!(unexpected.is_none(), "unexpected event: {:?}", unexpected);
+
+        // The properties still see the events of both connections
+        assert!(matches!(
+            all_events.next().await,
+            Some(ConnectionEvent::Connected(_))
+        ));
+        assert!(matches!(
+            all_events.next().await,
+            Some(ConnectionEvent::Connected(_))
+        ));
+        assert!(matches!(
+            all_events.next().await,
+            Some(ConnectionEvent::Closing)
+        ));
+        second.close(200, "OK").await.expect("close");
+    });
+}
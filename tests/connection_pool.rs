@@ -0,0 +1,209 @@
+use lapin::{
+    ConnectionPool, ConnectionPoolConfig, ConnectionProperties, CredentialsProvider, Error, Result,
+    Secret,
+};
+use lapin_mock::MockServer;
+use parking_lot::Mutex;
+use std::{
+    io,
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Relay the connections to the mock server, so that we can cut them
+struct Relay {
+    address: SocketAddr,
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    disconnected: Arc<AtomicUsize>,
+}
+
+impl Relay {
+    /// Relay the first `max_clients` connections, refusing the next ones
+    fn start(server: SocketAddr, max_clients: usize) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let disconnected = Arc::new(AtomicUsize::new(0));
+        let accepted = clients.clone();
+        let gone = disconnected.clone();
+        thread::spawn(move || {
+            for client in listener.incoming().flatten().take(max_clients) {
+                let upstream = TcpStream::connect(server)?;
+                accepted.lock().push(client.try_clone()?);
+                let gone = gone.clone();
+                let (from, to) = (client.try_clone()?, upstream.try_clone()?);
+                thread::spawn(move || {
+                    forward(from, to);
+                    gone.fetch_add(1, Ordering::SeqCst);
+                });
+                thread::spawn(move || forward(upstream, client));
+            }
+            Ok::<(), io::Error>(())
+        });
+        Ok(Self {
+            address,
+            clients,
+            disconnected,
+        })
+    }
+
+    fn uri(&self) -> String {
+        format!("amqp://guest:guest@{}/%2f?heartbeat=1", self.address)
+    }
+
+    fn cut(&self, index: usize) {
+        let _ = self.clients.lock()[index].shutdown(Shutdown::Both);
+    }
+}
+
+fn forward(mut from: TcpStream, mut to: TcpStream) {
+    let _ = io::copy(&mut from, &mut to);
+    let _ = to.shutdown(Shutdown::Both);
+}
+
+#[test]
+fn connection_pool() {
+    let server = MockServer::start().expect("mock server");
+    let relay = Relay::start(server.address(), usize::MAX).expect("relay");
+
+    async_global_executor::block_on(async {
+        let pool = ConnectionPool::connect(
+            &relay.uri(),
+            ConnectionProperties::default(),
+            ConnectionPoolConfig {
+                size: 3,
+                retry_delay: Duration::from_millis(50),
+            },
+        )
+        .await
+        .expect("connection pool");
+        let metrics = pool.metrics();
+        assert_eq!(metrics.size, 3);
+        assert_eq!(metrics.connected, 3);
+
+        // Channels are spread across the connections, each numbering its own channels
+        let mut ids = Vec::new();
+        for _ in 0..6 {
+            ids.push(pool.create_channel().await.expect("create_channel").id());
+        }
+        ids.sort_unstable();
+        assert_eq!(ids, vec![1, 1, 1, 2, 2, 2]);
+        assert_eq!(pool.metrics().channels_created, 6);
+
+        // A failed connection gets replaced in the background, once its missing heartbeats
+        // give the cut away
+        relay.cut(0);
+        let started = Instant::now();
+        while pool.metrics().replaced == 0 {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "connection not replaced"
+            );
+            async_io::Timer::after(Duration::from_millis(20)).await;
+        }
+        let metrics = pool.metrics();
+        assert_eq!(metrics.connected, 3);
+        assert_eq!(metrics.replacing, 0);
+        assert_eq!(metrics.replaced, 1);
+
+        pool.close(200, "OK").await.expect("close");
+        assert_eq!(pool.metrics().connected, 0);
+        assert!(pool.create_channel().await.is_err());
+    });
+}
+
+#[test]
+fn opened_connections_are_closed_when_the_pool_fails() {
+    let server = MockServer::start().expect("mock server");
+    let relay = Relay::start(server.address(), 2).expect("relay");
+
+    async_global_executor::block_on(async {
+        let pool = ConnectionPool::connect(
+            &relay.uri(),
+            ConnectionProperties::default(),
+            ConnectionPoolConfig {
+                size: 3,
+                ..ConnectionPoolConfig::default()
+            },
+        )
+        .await;
+        assert!(pool.is_err());
+        let started = Instant::now();
+        while relay.disconnected.load(Ordering::SeqCst) < 2 {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "opened connections left behind"
+            );
+            async_io::Timer::after(Duration::from_millis(20)).await;
+        }
+    });
+}
+
+/// Hands out an already expired token, then fails to refresh it
+#[derive(Debug, Default)]
+struct FailingRefresh(Arc<AtomicUsize>);
+
+impl CredentialsProvider for FailingRefresh {
+    fn secret(&self) -> Result<Secret> {
+        if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+            Ok(Secret {
+                value: "token".into(),
+                expires_at: Some(Instant::now()),
+            })
+        } else {
+            Err(Error::IOError(Arc::new(io::Error::new(
+                io::ErrorKind::Other,
+                "token endpoint unavailable",
+            ))))
+        }
+    }
+
+    fn refresh_margin(&self) -> Duration {
+        Duration::default()
+    }
+}
+
+#[test]
+fn failed_credentials_refresh_keeps_the_connection() {
+    let server = MockServer::start().expect("mock server");
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    async_global_executor::block_on(async {
+        let pool = ConnectionPool::connect(
+            &server.uri(),
+            ConnectionProperties::default()
+                .with_credentials_provider(FailingRefresh(calls.clone())),
+            ConnectionPoolConfig {
+                size: 1,
+                retry_delay: Duration::from_millis(50),
+            },
+        )
+        .await
+        .expect("connection pool");
+        let connection = pool.connection().expect("connection");
+
+        let started = Instant::now();
+        while calls.load(Ordering::SeqCst) < 2 {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "secret not refreshed"
+            );
+            async_io::Timer::after(Duration::from_millis(20)).await;
+        }
+        // Give the pool some time to wrongly replace it
+        async_io::Timer::after(Duration::from_millis(200)).await;
+        let metrics = pool.metrics();
+        assert_eq!(metrics.connected, 1);
+        assert_eq!(metrics.replaced, 0);
+        assert!(Arc::ptr_eq(
+            &connection,
+            &pool.connection().expect("connection")
+        ));
+        pool.close(200, "OK").await.expect("close");
+    });
+}
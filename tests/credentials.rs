@@ -0,0 +1,56 @@
+use lapin::{
+    protocol::{connection, AMQPClass},
+    Connection, ConnectionProperties, CredentialsProvider, Result, Secret,
+};
+use lapin_mock::{Script, ScriptedServer};
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+/// A new token each time we ask, already expired
+#[derive(Debug, Default)]
+struct RotatingToken(AtomicUsize);
+
+impl CredentialsProvider for RotatingToken {
+    fn secret(&self) -> Result<Secret> {
+        Ok(Secret {
+            value: format!("token-{}", self.0.fetch_add(1, Ordering::SeqCst) + 1),
+            expires_at: Some(Instant::now()),
+        })
+    }
+
+    fn refresh_margin(&self) -> Duration {
+        Duration::default()
+    }
+}
+
+#[test]
+fn expiring_secret_is_sent_again() {
+    let script = Script::new()
+        .handshake(8192, 0)
+        .expect_method(0, "connection.update-secret", |method| {
+            matches!(
+                method,
+                AMQPClass::Connection(connection::AMQPMethod::UpdateSecret(update))
+                    if update.new_secret.as_str() == "token-2"
+            )
+        })
+        .send_method(
+            0,
+            AMQPClass::Connection(connection::AMQPMethod::UpdateSecretOk(
+                connection::UpdateSecretOk {},
+            )),
+        );
+    let server = ScriptedServer::start(script).expect("scripted server");
+
+    let _conn = async_global_executor::block_on(async {
+        Connection::connect(
+            &server.uri(),
+            ConnectionProperties::default().with_credentials_provider(RotatingToken::default()),
+        )
+        .await
+        .expect("connection")
+    });
+    server.finish().expect("script failed");
+}
@@ -0,0 +1,46 @@
+use futures_lite::StreamExt;
+use lapin::{Connection, ConnectionEvent, ConnectionProperties, ConnectionState, Error};
+use lapin_mock::{Script, ScriptedServer};
+use std::time::{Duration, Instant};
+
+#[test]
+fn silent_server_closes_connection() {
+    // Negotiate a one second heartbeat, then never send anything
+    let server = ScriptedServer::start(Script::new().handshake(8192, 1)).expect("scripted server");
+    let (sender, receiver) = flume::bounded(1);
+    let properties = ConnectionProperties::default();
+    let mut events = properties.events();
+
+    let conn = async_global_executor::block_on(async {
+        let conn = Connection::connect(&server.uri(), properties)
+            .await
+            .expect("connection");
+        conn.on_error(move |err| {
+            let _ = sender.try_send(err);
+        });
+        conn
+    });
+
+    let started = Instant::now();
+    let error = receiver
+        .recv_timeout(Duration::from_secs(10))
+        .expect("missing heartbeat not detected");
+    assert_eq!(error, Error::MissingHeartbeat);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(conn.status().state(), ConnectionState::Error);
+    async_global_executor::block_on(async {
+        assert!(matches!(
+            events.next().await,
+            Some(ConnectionEvent::Connected(_))
+        ));
+        assert!(matches!(
+            events.next().await,
+            Some(ConnectionEvent::HeartbeatMissed)
+        ));
+    });
+    // We kept on sending our own heartbeats until we gave up and closed the socket
+    let frames = server.finish().expect("script failed");
+    assert!(frames
+        .iter()
+        .any(|frame| matches!(frame, lapin_mock::AMQPFrame::Heartbeat(0))));
+}
@@ -0,0 +1,78 @@
+use lapin::{
+    message::DeliveryResult, options::*, publisher_confirm::Confirmation, types::FieldTable,
+    BasicProperties, Connection, ConnectionProperties,
+};
+use lapin_mock::MockServer;
+use std::time::Duration;
+
+#[test]
+fn publish_and_consume() {
+    let server = MockServer::start().expect("mock server");
+    let (sender, receiver) = flume::unbounded();
+
+    let _conn = async_global_executor::block_on(async {
+        let conn = Connection::connect(&server.uri(), ConnectionProperties::default())
+            .await
+            .expect("connection error");
+        let channel_a = conn.create_channel().await.expect("create_channel");
+        let channel_b = conn.create_channel().await.expect("create_channel");
+        channel_a
+            .confirm_select(ConfirmSelectOptions::default())
+            .await
+            .expect("confirm_select");
+        channel_a
+            .queue_declare(
+                "hello-async",
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .expect("queue_declare");
+
+        let consumer = channel_b
+            .basic_consume(
+                "hello-async",
+                "my_consumer",
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .expect("basic_consume");
+        consumer.set_delegate(move |delivery: DeliveryResult| {
+            let sender = sender.clone();
+            async move {
+                if let Some((channel, delivery)) = delivery.expect("delivery") {
+                    channel
+                        .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+                        .await
+                        .expect("basic_ack");
+                    let _ = sender.send(delivery.data);
+                }
+            }
+        });
+
+        for _ in 1..=2 {
+            let confirm = channel_a
+                .basic_publish(
+                    "",
+                    "hello-async",
+                    BasicPublishOptions::default(),
+                    b"Hello world!".to_vec(),
+                    BasicProperties::default(),
+                )
+                .await
+                .expect("basic_publish")
+                .await
+                .expect("publisher-confirms");
+            assert_eq!(confirm, Confirmation::Ack(None));
+        }
+        conn
+    });
+
+    for _ in 1..=2 {
+        let data = receiver
+            .recv_timeout(Duration::from_secs(5))
+            .expect("missing delivery");
+        assert_eq!(data, b"Hello world!");
+    }
+}
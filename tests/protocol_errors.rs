@@ -0,0 +1,246 @@
+use lapin::{
+    options::*,
+    protocol::{
+        basic, channel, connection, AMQPClass, AMQPErrorKind, AMQPHardError, BasicProperties,
+    },
+    types::FieldTable,
+    Channel, ChannelState, Connection, ConnectionProperties, ConnectionState, Error,
+};
+use lapin_mock::{AMQPContentHeader, AMQPFrame, Script, ScriptedServer};
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+struct Outcome {
+    frames: Vec<AMQPFrame>,
+    errors: Vec<Error>,
+    connection: ConnectionState,
+    channel: ChannelState,
+}
+
+/// The handshake and the opening of channel 1
+fn opened() -> Script {
+    Script::new().handshake(8192, 0).open_channel(1)
+}
+
+/// Connect to a server playing `script`, run `client` on channel 1 and collect what happened
+fn play<F, Fut>(script: Script, client: F) -> Outcome
+where
+    F: FnOnce(Channel) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let server = ScriptedServer::start(script).expect("scripted server");
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let reported = errors.clone();
+    let (conn, channel) = async_global_executor::block_on(async {
+        let conn = Connection::connect(&server.uri(), ConnectionProperties::default())
+            .await
+            .expect("connection");
+        conn.on_error(move |err| reported.lock().unwrap().push(err));
+        let channel = conn.create_channel().await.expect("create_channel");
+        client(channel.clone()).await;
+        (conn, channel)
+    });
+    // Don't block the executor while the server waits for the client to go silent
+    let frames = server.finish().expect("script failed");
+    let errors = errors.lock().unwrap().clone();
+    Outcome {
+        frames,
+        errors,
+        connection: conn.status().state(),
+        channel: channel.status().state(),
+    }
+}
+
+fn hard_error(outcome: &Outcome) -> Option<AMQPHardError> {
+    match outcome.errors.as_slice() {
+        [Error::ProtocolError(error)] => match error.kind() {
+            AMQPErrorKind::Hard(kind) => Some(kind.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn deliver(delivery_tag: u64) -> AMQPClass {
+    AMQPClass::Basic(basic::AMQPMethod::Deliver(basic::Deliver {
+        consumer_tag: "consumer".into(),
+        delivery_tag,
+        redelivered: false,
+        exchange: "".into(),
+        routing_key: "queue".into(),
+    }))
+}
+
+fn header(body_size: u64) -> AMQPFrame {
+    AMQPFrame::Header(
+        1,
+        60,
+        Box::new(AMQPContentHeader {
+            class_id: 60,
+            weight: 0,
+            body_size,
+            properties: BasicProperties::default(),
+        }),
+    )
+}
+
+async fn consume(channel: Channel) {
+    channel
+        .basic_consume(
+            "queue",
+            "consumer",
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .expect("basic_consume");
+}
+
+fn consuming() -> Script {
+    opened()
+        .expect_method(1, "basic.consume", |method| {
+            matches!(method, AMQPClass::Basic(basic::AMQPMethod::Consume(_)))
+        })
+        .send_method(
+            1,
+            AMQPClass::Basic(basic::AMQPMethod::ConsumeOk(basic::ConsumeOk {
+                consumer_tag: "consumer".into(),
+            })),
+        )
+}
+
+#[test]
+fn body_frame_without_header() {
+    let outcome = play(
+        opened().send(AMQPFrame::Body(1, b"unexpected".to_vec())),
+        |_| async {},
+    );
+    assert_eq!(hard_error(&outcome), Some(AMQPHardError::UNEXPECTEDFRAME));
+    assert_eq!(outcome.connection, ConnectionState::Error);
+    assert_eq!(outcome.channel, ChannelState::Error);
+}
+
+#[test]
+fn header_frame_without_method() {
+    let outcome = play(opened().send(header(3)), |_| async {});
+    assert_eq!(hard_error(&outcome), Some(AMQPHardError::UNEXPECTEDFRAME));
+    assert_eq!(outcome.connection, ConnectionState::Error);
+}
+
+#[test]
+fn truncated_body() {
+    let outcome = play(
+        consuming()
+            .send_method(1, deliver(1))
+            .send(header(10))
+            .send(AMQPFrame::Body(1, b"abc".to_vec()))
+            // The next body overflows what's left of the truncated one
+            .send(AMQPFrame::Body(1, b"0123456789".to_vec())),
+        consume,
+    );
+    assert_eq!(hard_error(&outcome), Some(AMQPHardError::UNEXPECTEDFRAME));
+    assert_eq!(outcome.connection, ConnectionState::Error);
+    assert_eq!(outcome.channel, ChannelState::Error);
+}
+
+#[test]
+fn method_on_unknown_channel() {
+    let outcome = play(
+        opened().send_method(
+            7,
+            AMQPClass::Channel(channel::AMQPMethod::FlowOk(channel::FlowOk {
+                active: true,
+            })),
+        ),
+        |_| async {},
+    );
+    assert_eq!(outcome.errors, vec![Error::InvalidChannel(7)]);
+    assert_eq!(outcome.connection, ConnectionState::Error);
+}
+
+#[test]
+fn connection_method_on_channel() {
+    let outcome = play(
+        opened().send_method(
+            1,
+            AMQPClass::Connection(connection::AMQPMethod::OpenOk(connection::OpenOk::default())),
+        ),
+        |_| async {},
+    );
+    assert_eq!(hard_error(&outcome), Some(AMQPHardError::COMMANDINVALID));
+    assert_eq!(outcome.connection, ConnectionState::Error);
+}
+
+#[test]
+fn channel_closed_by_server() {
+    let outcome = play(
+        opened()
+            .send_method(
+                1,
+                AMQPClass::Channel(channel::AMQPMethod::Close(channel::Close {
+                    reply_code: 406,
+                    reply_text: "PRECONDITION_FAILED".into(),
+                    class_id: 50,
+                    method_id: 10,
+                })),
+            )
+            .expect_method(1, "channel.close-ok", |method| {
+                matches!(method, AMQPClass::Channel(channel::AMQPMethod::CloseOk(_)))
+            }),
+        |_| async {},
+    );
+    assert!(outcome.errors.is_empty());
+    assert_eq!(outcome.connection, ConnectionState::Connected);
+    assert_eq!(outcome.channel, ChannelState::Closed);
+}
+
+#[test]
+fn connection_closed_mid_publish() {
+    let outcome = play(
+        opened()
+            .expect_method(1, "basic.publish", |method| {
+                matches!(method, AMQPClass::Basic(basic::AMQPMethod::Publish(_)))
+            })
+            .send_method(
+                0,
+                AMQPClass::Connection(connection::AMQPMethod::Close(connection::Close {
+                    reply_code: 320,
+                    reply_text: "CONNECTION_FORCED".into(),
+                    class_id: 0,
+                    method_id: 0,
+                })),
+            ),
+        |channel| async move {
+            channel
+                .basic_publish(
+                    "",
+                    "queue",
+                    BasicPublishOptions::default(),
+                    b"payload".to_vec(),
+                    BasicProperties::default(),
+                )
+                .await
+                .expect("basic_publish");
+        },
+    );
+    assert_eq!(hard_error(&outcome), Some(AMQPHardError::CONNECTIONFORCED));
+    assert_eq!(outcome.connection, ConnectionState::Error);
+    assert_eq!(outcome.channel, ChannelState::Error);
+    // The content frames went out right after their method
+    let publish = outcome
+        .frames
+        .iter()
+        .position(|frame| {
+            matches!(
+                frame,
+                AMQPFrame::Method(1, AMQPClass::Basic(basic::AMQPMethod::Publish(_)))
+            )
+        })
+        .expect("publish frame");
+    assert!(matches!(
+        &outcome.frames[publish + 1..],
+        [AMQPFrame::Header(1, 60, _), AMQPFrame::Body(1, body), ..] if body == b"payload"
+    ));
+}
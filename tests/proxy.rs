@@ -0,0 +1,89 @@
+use lapin::{
+    options::*, types::FieldTable, BasicProperties, Connection, ConnectionProperties, Proxy,
+};
+use lapin_mock::MockServer;
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    thread,
+};
+
+/// A SOCKS5 proxy without authentication accepting a single connection to an IPv4 address,
+/// returning the address the client asked for
+fn socks5_proxy() -> (u16, thread::JoinHandle<SocketAddr>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = thread::spawn(move || {
+        let (mut client, _) = listener.accept().unwrap();
+        let mut greeting = [0; 3];
+        client.read_exact(&mut greeting).unwrap();
+        assert_eq!(greeting, [5, 1, 0]);
+        client.write_all(&[5, 0]).unwrap();
+        let mut request = [0; 10];
+        client.read_exact(&mut request).unwrap();
+        assert_eq!(request[..4], [5, 1, 0, 1]);
+        let ip = Ipv4Addr::new(request[4], request[5], request[6], request[7]);
+        let target = SocketAddr::new(ip.into(), u16::from_be_bytes([request[8], request[9]]));
+        let server = TcpStream::connect(target).unwrap();
+        client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+        relay(client, server);
+        target
+    });
+    (port, handle)
+}
+
+fn relay(client: TcpStream, server: TcpStream) {
+    let (mut client_reader, mut server_writer) =
+        (client.try_clone().unwrap(), server.try_clone().unwrap());
+    let upstream = thread::spawn(move || {
+        let _ = io::copy(&mut client_reader, &mut server_writer);
+        let _ = server_writer.shutdown(Shutdown::Write);
+    });
+    let (mut server_reader, mut client_writer) = (server, client);
+    let _ = io::copy(&mut server_reader, &mut client_writer);
+    let _ = client_writer.shutdown(Shutdown::Write);
+    upstream.join().unwrap();
+}
+
+#[test]
+fn connect_through_socks5_proxy() {
+    let server = MockServer::start().expect("mock server");
+    let (proxy_port, proxy) = socks5_proxy();
+
+    async_global_executor::block_on(async {
+        let conn = Connection::connect(
+            &server.uri(),
+            ConnectionProperties::default().with_proxy(Proxy::socks5("127.0.0.1", proxy_port)),
+        )
+        .await
+        .expect("connection");
+        let channel = conn.create_channel().await.expect("create_channel");
+        channel
+            .queue_declare(
+                "proxied",
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .expect("queue_declare");
+        channel
+            .basic_publish(
+                "",
+                "proxied",
+                BasicPublishOptions::default(),
+                b"Hello world!".to_vec(),
+                BasicProperties::default(),
+            )
+            .await
+            .expect("basic_publish");
+        let message = channel
+            .basic_get("proxied", BasicGetOptions { no_ack: true })
+            .await
+            .expect("basic_get")
+            .expect("message");
+        assert_eq!(message.delivery.data, b"Hello world!");
+        conn.close(200, "OK").await.expect("close");
+    });
+
+    assert_eq!(proxy.join().unwrap(), server.address());
+}
@@ -0,0 +1,96 @@
+use futures_lite::StreamExt;
+use lapin::{
+    options::*,
+    protocol::{basic, confirm, AMQPClass},
+    publisher_confirm::Confirmation,
+    BasicProperties, Connection, ConnectionProperties, Publisher,
+};
+use lapin_mock::{AMQPFrame, Script, ScriptedServer};
+use std::time::Duration;
+
+/// The method, header and body frames of a message published on channel 1
+fn expect_publish(script: Script, payload: &'static [u8]) -> Script {
+    script
+        .expect_method(1, "basic.publish", |method| {
+            matches!(method, AMQPClass::Basic(basic::AMQPMethod::Publish(_)))
+        })
+        .expect("content header", |frame| {
+            matches!(frame, AMQPFrame::Header(1, ..))
+        })
+        .expect(
+            "content body",
+            move |frame| matches!(frame, AMQPFrame::Body(1, body) if body == payload),
+        )
+}
+
+#[test]
+fn window_bounds_unconfirmed_messages() {
+    let script = Script::new()
+        .handshake(8192, 0)
+        .open_channel(1)
+        .expect_method(1, "confirm.select", |method| {
+            matches!(method, AMQPClass::Confirm(confirm::AMQPMethod::Select(_)))
+        })
+        .send_method(
+            1,
+            AMQPClass::Confirm(confirm::AMQPMethod::SelectOk(confirm::SelectOk {})),
+        );
+    let script = expect_publish(expect_publish(script, b"1"), b"2")
+        // The window is full, the third message must wait for a confirmation
+        .expect_silence(Duration::from_millis(300))
+        .send_method(
+            1,
+            AMQPClass::Basic(basic::AMQPMethod::Nack(basic::Nack {
+                delivery_tag: 1,
+                multiple: false,
+                requeue: false,
+            })),
+        );
+    let script = expect_publish(script, b"3").send_method(
+        1,
+        AMQPClass::Basic(basic::AMQPMethod::Ack(basic::Ack {
+            delivery_tag: 3,
+            multiple: true,
+        })),
+    );
+    let server = ScriptedServer::start(script).expect("scripted server");
+
+    let (_conn, confirmations) = async_global_executor::block_on(async {
+        let conn = Connection::connect(&server.uri(), ConnectionProperties::default())
+            .await
+            .expect("connection");
+        let channel = conn.create_channel().await.expect("create_channel");
+        let publisher = Publisher::new(channel, 2).await.expect("publisher");
+        let confirmations = publisher.confirmations();
+        for token in 1..=3u8 {
+            publisher
+                .publish(
+                    token,
+                    "",
+                    "queue",
+                    BasicPublishOptions::default(),
+                    token.to_string().into_bytes(),
+                    BasicProperties::default(),
+                )
+                .await
+                .expect("publish");
+            assert!(publisher.in_flight() <= 2);
+        }
+        drop(publisher);
+        (conn, confirmations.collect::<Vec<_>>().await)
+    });
+    server.finish().expect("script failed");
+
+    let confirmations = confirmations
+        .into_iter()
+        .map(|(token, confirmation)| (token, confirmation.expect("confirmation")))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        confirmations,
+        vec![
+            (1, Confirmation::Nack(None)),
+            (2, Confirmation::Ack(None)),
+            (3, Confirmation::Ack(None)),
+        ]
+    );
+}
@@ -1 +0,0 @@
-../examples/publisher_confirms.rs
\ No newline at end of file
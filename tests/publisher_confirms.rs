@@ -0,0 +1,101 @@
+use lapin::{
+    message::{BasicReturnMessage, Delivery},
+    options::*,
+    protocol::{AMQPErrorKind, AMQPSoftError},
+    types::FieldTable,
+    BasicProperties, Connection, ConnectionProperties,
+};
+use lapin_mock::MockServer;
+
+#[test]
+fn publisher_confirms() {
+    let server = MockServer::start().expect("mock server");
+
+    async_global_executor::block_on(async {
+        let conn = Connection::connect(&server.uri(), ConnectionProperties::default())
+            .await
+            .expect("connection error");
+        let channel = conn.create_channel().await.expect("create_channel");
+        channel
+            .queue_declare(
+                "hello",
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .expect("queue_declare");
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await
+            .expect("confirm_select");
+
+        let payload = b"Hello world!";
+        let confirm = channel
+            .basic_publish(
+                "",
+                "hello",
+                BasicPublishOptions::default(),
+                payload.to_vec(),
+                BasicProperties::default(),
+            )
+            .await
+            .expect("basic_publish")
+            .await
+            .expect("publisher-confirms");
+        assert!(confirm.is_ack());
+        assert_eq!(confirm.take_message(), None);
+
+        for _ in 1..=2 {
+            channel
+                .basic_publish(
+                    "",
+                    "hello",
+                    BasicPublishOptions::default(),
+                    payload.to_vec(),
+                    BasicProperties::default(),
+                )
+                .await
+                .expect("basic_publish");
+        }
+        let returned = channel
+            .wait_for_confirms()
+            .await
+            .expect("wait for confirms");
+        assert!(returned.is_empty());
+
+        let confirm = channel
+            .basic_publish(
+                "",
+                "unroutable-routing-key-for-tests",
+                BasicPublishOptions {
+                    mandatory: true,
+                    ..BasicPublishOptions::default()
+                },
+                payload.to_vec(),
+                BasicProperties::default().with_priority(42),
+            )
+            .await
+            .expect("basic_publish")
+            .await
+            .expect("publisher-confirms");
+        assert!(confirm.is_ack());
+        let message = confirm.take_message().unwrap();
+        assert_eq!(
+            message,
+            BasicReturnMessage {
+                delivery: Delivery {
+                    delivery_tag: 0,
+                    exchange: "".into(),
+                    routing_key: "unroutable-routing-key-for-tests".into(),
+                    redelivered: false,
+                    properties: BasicProperties::default().with_priority(42),
+                    data: payload.to_vec(),
+                },
+                reply_code: 312,
+                reply_text: "NO_ROUTE".into(),
+            }
+        );
+        let error = message.error().unwrap();
+        assert_eq!(error.kind(), &AMQPErrorKind::Soft(AMQPSoftError::NOROUTE));
+    })
+}
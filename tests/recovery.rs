@@ -0,0 +1,165 @@
+use futures_lite::StreamExt;
+use lapin::{
+    options::*, types::FieldTable, BasicProperties, Connection, ConnectionEvent,
+    ConnectionProperties, ExchangeKind, IoLoopMode, RecoveryConfig,
+};
+use lapin_mock::MockServer;
+use parking_lot::Mutex;
+use std::{
+    io,
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+/// Forwards every connection it accepts to `target`, until they get cut
+struct Relay {
+    port: u16,
+    connections: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl Relay {
+    fn start(target: SocketAddr) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connections = Arc::new(Mutex::new(Vec::new()));
+        let relayed = connections.clone();
+        thread::spawn(move || {
+            for client in listener.incoming() {
+                let client = client.unwrap();
+                let server = TcpStream::connect(target).unwrap();
+                relayed.lock().push(client.try_clone().unwrap());
+                forward(client.try_clone().unwrap(), server.try_clone().unwrap());
+                forward(server, client);
+            }
+        });
+        Self { port, connections }
+    }
+
+    fn uri(&self) -> String {
+        format!("amqp://127.0.0.1:{}/%2f", self.port)
+    }
+
+    /// Drop the connections as a network failure would
+    fn cut(&self) {
+        for connection in self.connections.lock().drain(..) {
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+fn forward(mut from: TcpStream, mut to: TcpStream) {
+    thread::spawn(move || {
+        let _ = io::copy(&mut from, &mut to);
+        let _ = to.shutdown(Shutdown::Both);
+    });
+}
+
+fn recover_topology(mode: IoLoopMode) {
+    let server = MockServer::start().expect("mock server");
+    let relay = Relay::start(server.address());
+
+    async_global_executor::block_on(async {
+        let conn = Connection::connect(
+            &relay.uri(),
+            ConnectionProperties::default()
+                .with_io_loop_mode(mode)
+                .with_connection_recovery(RecoveryConfig {
+                    initial_delay: Duration::from_millis(50),
+                    max_delay: Duration::from_millis(200),
+                    max_attempts: Some(10),
+                }),
+        )
+        .await
+        .expect("connection");
+        let mut events = conn.events();
+        let channel = conn.create_channel().await.expect("create_channel");
+        channel
+            .exchange_declare(
+                "recovery",
+                ExchangeKind::Direct,
+                ExchangeDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .expect("exchange_declare");
+        // Exclusive queues and consumers die with the connection, they must be declared again
+        channel
+            .queue_declare(
+                "recovered",
+                QueueDeclareOptions {
+                    exclusive: true,
+                    ..QueueDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .expect("queue_declare");
+        channel
+            .queue_bind(
+                "recovered",
+                "recovery",
+                "key",
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .expect("queue_bind");
+        let mut consumer = channel
+            .basic_consume(
+                "recovered",
+                "consumer",
+                BasicConsumeOptions {
+                    no_ack: true,
+                    ..BasicConsumeOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .expect("basic_consume");
+
+        relay.cut();
+        loop {
+            match events.next().await.expect("connection events") {
+                ConnectionEvent::Recovered => break,
+                event => assert!(
+                    matches!(
+                        event,
+                        ConnectionEvent::Reconnecting | ConnectionEvent::Connected(_)
+                    ),
+                    "unexpected event: {:?}",
+                    event
+                ),
+            }
+        }
+
+        channel
+            .basic_publish(
+                "recovery",
+                "key",
+                BasicPublishOptions::default(),
+                b"still there".to_vec(),
+                BasicProperties::default(),
+            )
+            .await
+            .expect("basic_publish");
+        let (_, delivery) = consumer
+            .next()
+            .await
+            .expect("consumer ended")
+            .expect("delivery");
+        assert_eq!(delivery.data, b"still there");
+        conn.close(200, "OK").await.expect("close");
+    });
+}
+
+#[test]
+fn topology_is_recovered_after_losing_the_socket() {
+    recover_topology(IoLoopMode::Thread);
+}
+
+#[test]
+fn topology_is_recovered_by_the_async_io_loop() {
+    recover_topology(IoLoopMode::Async);
+}
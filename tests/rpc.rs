@@ -0,0 +1,64 @@
+use lapin::{
+    options::*, types::FieldTable, BasicProperties, Connection, ConnectionProperties, RpcClient,
+    RpcServer,
+};
+use lapin_mock::MockServer;
+
+fn round_trip(no_ack: bool) {
+    let server = MockServer::start().expect("mock server");
+
+    async_global_executor::block_on(async {
+        let conn = Connection::connect(&server.uri(), ConnectionProperties::default())
+            .await
+            .expect("connection");
+        let channel = conn.create_channel().await.expect("create_channel");
+        channel
+            .queue_declare("rpc", QueueDeclareOptions::default(), FieldTable::default())
+            .await
+            .expect("queue_declare");
+        let rpc_server = RpcServer::new(
+            channel,
+            "rpc",
+            BasicConsumeOptions {
+                no_ack,
+                ..BasicConsumeOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .expect("rpc server");
+        let serving = async_global_executor::spawn(rpc_server.serve(|request| async move {
+            let reply = request.data.iter().rev().cloned().collect::<Vec<_>>();
+            (reply, BasicProperties::default())
+        }));
+
+        let client = RpcClient::new(conn.create_channel().await.expect("create_channel"))
+            .await
+            .expect("rpc client");
+        for request in &["ping", "pong"] {
+            let reply = client
+                .call(
+                    "",
+                    "rpc",
+                    request.as_bytes().to_vec(),
+                    BasicProperties::default(),
+                )
+                .await
+                .expect("call");
+            let expected = request.bytes().rev().collect::<Vec<_>>();
+            assert_eq!(reply.data, expected);
+        }
+        // The server would have stopped on an error, such as an ack of an unknown delivery
+        assert!(serving.cancel().await.is_none());
+    });
+}
+
+#[test]
+fn rpc_round_trip() {
+    round_trip(false);
+}
+
+#[test]
+fn rpc_round_trip_without_acks() {
+    round_trip(true);
+}
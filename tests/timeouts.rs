@@ -0,0 +1,54 @@
+use lapin::{uri::AMQPUri, Connection, ConnectionProperties, Error};
+use lapin_mock::{AMQPFrame, Script, ScriptedServer};
+use std::{
+    io, thread,
+    time::{Duration, Instant},
+};
+
+/// A server accepting the connection but never answering the protocol header
+fn silent_server() -> ScriptedServer {
+    ScriptedServer::start(Script::new().expect("protocol header", |frame| {
+        matches!(frame, AMQPFrame::ProtocolHeader(_))
+    }))
+    .expect("scripted server")
+}
+
+#[test]
+fn handshake_timeout() {
+    let server = silent_server();
+    let started = Instant::now();
+    let result = async_global_executor::block_on(Connection::connect(
+        &server.uri(),
+        ConnectionProperties::default().with_handshake_timeout(Duration::from_millis(200)),
+    ));
+    assert_eq!(result.err(), Some(Error::Timeout));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    // The io loop went away along with its socket
+    server.finish().expect("script failed");
+}
+
+#[test]
+fn connection_timeout_from_uri() {
+    let server = silent_server();
+    let uri = format!("{}?connection_timeout=200", server.uri());
+    let result =
+        async_global_executor::block_on(Connection::connect(&uri, ConnectionProperties::default()));
+    assert_eq!(result.err(), Some(Error::Timeout));
+    server.finish().expect("script failed");
+}
+
+#[test]
+fn connection_timeout() {
+    let started = Instant::now();
+    let result = async_global_executor::block_on(Connection::transport_connector(
+        AMQPUri::default(),
+        Box::new(|_| {
+            // A blackholed broker
+            thread::sleep(Duration::from_secs(5));
+            Err(io::Error::from(io::ErrorKind::ConnectionRefused).into())
+        }),
+        ConnectionProperties::default().with_connection_timeout(Duration::from_millis(200)),
+    ));
+    assert_eq!(result.err(), Some(Error::Timeout));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
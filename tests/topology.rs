@@ -0,0 +1,52 @@
+use lapin::{
+    topology::{BindingDefinition, ExchangeDefinition, QueueDefinition, Topology, TopologyDrift},
+    types::{AMQPValue, FieldTable},
+    Connection, ConnectionProperties, ExchangeKind,
+};
+use lapin_mock::MockServer;
+
+fn with_ttl(ttl: u32) -> FieldTable {
+    let mut arguments = FieldTable::default();
+    arguments.insert("x-message-ttl".into(), AMQPValue::LongUInt(ttl));
+    arguments
+}
+
+#[test]
+fn verify_topology_reports_drifts() {
+    let server = MockServer::start().expect("mock server");
+
+    async_global_executor::block_on(async {
+        let conn = Connection::connect(&server.uri(), ConnectionProperties::default())
+            .await
+            .expect("connection");
+        let channel = conn.create_channel().await.expect("create_channel");
+        let deployed = Topology::default()
+            .with_exchange(ExchangeDefinition::new("events", ExchangeKind::Topic).durable())
+            .with_queue(QueueDefinition::new("audit").durable())
+            .with_queue(QueueDefinition::new("cache").with_arguments(with_ttl(1000)))
+            .with_binding(BindingDefinition::queue("events", "audit", "#"));
+        channel
+            .apply_topology(&deployed)
+            .await
+            .expect("apply_topology");
+        assert_eq!(conn.verify_topology(&deployed).await, Ok(Vec::new()));
+
+        let expected = Topology::default()
+            .with_exchange(ExchangeDefinition::new("events", ExchangeKind::Topic))
+            .with_queue(QueueDefinition::new("audit").durable())
+            .with_queue(QueueDefinition::new("cache").with_arguments(with_ttl(5000)))
+            .with_queue(QueueDefinition::new("missing"));
+        let drifts = conn
+            .verify_topology(&expected)
+            .await
+            .expect("verify_topology");
+        assert!(matches!(
+            drifts.as_slice(),
+            [
+                TopologyDrift::InequivalentExchange { name: exchange, .. },
+                TopologyDrift::InequivalentQueue { name: queue, .. },
+                TopologyDrift::MissingQueue(missing),
+            ] if exchange == "events" && queue == "cache" && missing == "missing"
+        ));
+    });
+}
@@ -0,0 +1,95 @@
+#![cfg(unix)]
+
+use lapin::{
+    options::*, types::FieldTable, uri::AMQPUri, BasicProperties, Connection, ConnectionProperties,
+    Transport, TransportSocket,
+};
+use lapin_mock::MockServer;
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+/// One end of an in-memory pipe, counting the bytes it writes
+struct Pipe {
+    stream: UnixStream,
+    written: Arc<AtomicUsize>,
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.stream.write(buf)?;
+        self.written.fetch_add(written, Ordering::SeqCst);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for Pipe {
+    fn socket(&self) -> TransportSocket {
+        TransportSocket::new(&self.stream)
+    }
+}
+
+#[test]
+fn connect_over_custom_transport() {
+    let server = Arc::new(MockServer::start().expect("mock server"));
+    let written = Arc::new(AtomicUsize::new(0));
+    let counter = written.clone();
+
+    async_global_executor::block_on(async {
+        let conn = Connection::transport_connector(
+            AMQPUri::default(),
+            Box::new(move |_| {
+                let (client, peer) = UnixStream::pair()?;
+                let server = server.clone();
+                let writer = peer.try_clone()?;
+                thread::spawn(move || server.serve(peer, writer));
+                Ok(Box::new(Pipe {
+                    stream: client,
+                    written: counter.clone(),
+                }))
+            }),
+            ConnectionProperties::default(),
+        )
+        .await
+        .expect("connection");
+        let channel = conn.create_channel().await.expect("create_channel");
+        let queue = channel
+            .queue_declare("", QueueDeclareOptions::default(), FieldTable::default())
+            .await
+            .expect("queue_declare");
+        channel
+            .basic_publish(
+                "",
+                queue.name().as_str(),
+                BasicPublishOptions::default(),
+                b"through the pipe".to_vec(),
+                BasicProperties::default(),
+            )
+            .await
+            .expect("basic_publish");
+        let message = channel
+            .basic_get(queue.name().as_str(), BasicGetOptions { no_ack: true })
+            .await
+            .expect("basic_get")
+            .expect("message");
+        assert_eq!(message.delivery.data, b"through the pipe");
+    });
+
+    assert!(written.load(Ordering::SeqCst) > 0);
+}
@@ -0,0 +1,45 @@
+#![cfg(unix)]
+
+use lapin::{options::*, types::FieldTable, Connection, ConnectionProperties};
+use lapin_mock::MockServer;
+use std::{os::unix::net::UnixListener, sync::Arc, thread};
+
+#[test]
+fn connect_through_unix_socket() {
+    let dir = std::env::temp_dir().join(format!("lapin-unix-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("socket dir");
+    let path = dir.join("amqp.sock");
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).expect("unix listener");
+    let server = Arc::new(MockServer::start().expect("mock server"));
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let server = server.clone();
+            thread::spawn(move || {
+                let writer = stream.try_clone()?;
+                server.serve(stream, writer)
+            });
+        }
+    });
+
+    async_global_executor::block_on(async {
+        let uri = format!("amqp+unix://guest:guest@{}?heartbeat=0", path.display());
+        let conn = Connection::connect(&uri, ConnectionProperties::default())
+            .await
+            .expect("connection");
+        assert_eq!(conn.status().host(), "localhost");
+        assert_eq!(conn.status().vhost(), "/");
+        let channel = conn.create_channel().await.expect("create_channel");
+        channel
+            .queue_declare(
+                "unix-socket",
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .expect("queue_declare");
+        conn.close(200, "OK").await.expect("close");
+    });
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -2,23 +2,45 @@ use lapin::{executor::Executor, ConnectionProperties};
 use std::{future::Future, pin::Pin, sync::Arc};
 use tokio::runtime::Runtime;
 
+#[cfg(unix)]
+mod reactor;
+
+#[cfg(unix)]
+pub use reactor::TokioReactorBuilder;
+
 pub trait LapinTokioExt {
     fn with_tokio(self, rt: Arc<Runtime>) -> Self
     where
         Self: Sized,
     {
-        self.with_tokio_executor(rt)
+        #[cfg(unix)]
+        let this = self.with_tokio_reactor(rt.clone());
+        #[cfg(not(unix))]
+        let this = self;
+        this.with_tokio_executor(rt)
     }
 
     fn with_tokio_executor(self, rt: Arc<Runtime>) -> Self
     where
         Self: Sized;
+
+    /// Wait for socket events and heartbeats with tokio's io and time drivers instead of the
+    /// default async-io based reactor
+    #[cfg(unix)]
+    fn with_tokio_reactor(self, rt: Arc<Runtime>) -> Self
+    where
+        Self: Sized;
 }
 
 impl LapinTokioExt for ConnectionProperties {
     fn with_tokio_executor(self, rt: Arc<Runtime>) -> Self {
         self.with_executor(TokioExecutor(rt))
     }
+
+    #[cfg(unix)]
+    fn with_tokio_reactor(self, rt: Arc<Runtime>) -> Self {
+        self.with_reactor(TokioReactorBuilder::new(rt))
+    }
 }
 
 #[derive(Debug)]
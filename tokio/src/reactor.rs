@@ -0,0 +1,281 @@
+use flume::{Receiver, Sender};
+use futures_lite::future;
+use lapin::{
+    executor::Executor,
+    heartbeat::Heartbeat,
+    reactor::{Reactor, ReactorBuilder, ReactorHandle, Slot},
+    socket_state::{SocketEvent, SocketStateHandle},
+    OwnedTransportSocket, Result, TransportSocket,
+};
+use parking_lot::Mutex;
+use std::{collections::HashMap, fmt, future::Future, pin::Pin, sync::Arc, time::Duration};
+use tokio::{io::unix::AsyncFd, runtime::Runtime};
+
+/// Reactor backed by tokio's `AsyncFd` for socket readiness and `tokio::time` for heartbeats.
+/// Everything runs on the given runtime, whatever the executor of the connection is.
+#[derive(Clone)]
+pub struct TokioReactorBuilder {
+    rt: Arc<Runtime>,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl TokioReactorBuilder {
+    pub fn new(rt: Arc<Runtime>) -> Self {
+        Self {
+            rt,
+            inner: Arc::new(Mutex::new(Default::default())),
+        }
+    }
+}
+
+impl fmt::Debug for TokioReactorBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokioReactorBuilder").finish()
+    }
+}
+
+#[derive(Debug)]
+struct TokioReactor(TokioReactorHandle);
+
+#[derive(Clone)]
+struct TokioReactorHandle {
+    heartbeat: Heartbeat,
+    rt: Arc<Runtime>,
+    inner: Arc<Mutex<Inner>>,
+}
+
+/// The sockets registered by all the reactors of a builder
+#[derive(Default)]
+struct Inner {
+    slot: Slot,
+    slots: HashMap<usize, Registration>,
+}
+
+struct Registration {
+    socket: Arc<AsyncFd<OwnedTransportSocket>>,
+    socket_state: SocketStateHandle,
+    unregistered: (Sender<()>, Receiver<()>),
+}
+
+impl fmt::Debug for TokioReactorHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokioReactorHandle").finish()
+    }
+}
+
+impl Inner {
+    fn register(
+        &mut self,
+        socket: Arc<AsyncFd<OwnedTransportSocket>>,
+        socket_state: SocketStateHandle,
+    ) -> Result<usize> {
+        let slot = self.slot;
+        self.slot += 1;
+        self.slots.insert(
+            slot,
+            Registration {
+                socket,
+                socket_state,
+                unregistered: flume::bounded(0),
+            },
+        );
+        Ok(slot)
+    }
+}
+
+impl ReactorBuilder for TokioReactorBuilder {
+    fn build(&self, heartbeat: Heartbeat, _executor: Arc<dyn Executor>) -> Box<dyn Reactor + Send> {
+        Box::new(TokioReactor(TokioReactorHandle {
+            heartbeat,
+            rt: self.rt.clone(),
+            inner: self.inner.clone(),
+        }))
+    }
+}
+
+impl Reactor for TokioReactor {
+    fn register(
+        &mut self,
+        socket: TransportSocket,
+        socket_state: SocketStateHandle,
+    ) -> Result<usize> {
+        let socket = {
+            // AsyncFd registers with the io driver of the current runtime
+            let _enter = self.0.rt.enter();
+            Arc::new(AsyncFd::new(socket.try_clone()?)?)
+        };
+        let slot = self.0.inner.lock().register(socket, socket_state)?;
+        self.0.poll_read(slot);
+        self.0.poll_write(slot);
+        Ok(slot)
+    }
+
+    fn handle(&self) -> Box<dyn ReactorHandle + Send> {
+        Box::new(self.0.clone())
+    }
+}
+
+impl ReactorHandle for TokioReactorHandle {
+    fn start_heartbeat(&self) {
+        self.rt.spawn(heartbeat(self.heartbeat.clone()));
+    }
+
+    fn poll_read(&self, slot: usize) {
+        if let Some(registration) = self.inner.lock().slots.get(&slot) {
+            self.rt.spawn(poll_read(
+                registration.socket.clone(),
+                registration.socket_state.clone(),
+                registration.unregistered.1.clone(),
+            ));
+        }
+    }
+
+    fn poll_write(&self, slot: usize) {
+        if let Some(registration) = self.inner.lock().slots.get(&slot) {
+            self.rt.spawn(poll_write(
+                registration.socket.clone(),
+                registration.socket_state.clone(),
+                registration.unregistered.1.clone(),
+            ));
+        }
+    }
+
+    fn unregister(&self, slot: usize) {
+        // Dropping the sender wakes the polling tasks up, releasing the socket
+        self.inner.lock().slots.remove(&slot);
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        // tokio's timers need to be polled from within the runtime
+        let sleep = self.rt.spawn(tokio::time::sleep(duration));
+        Box::pin(async move {
+            let _ = sleep.await;
+        })
+    }
+}
+
+async fn heartbeat(heartbeat: Heartbeat) {
+    while let Some(timeout) = heartbeat.poll_timeout() {
+        tokio::time::sleep(timeout).await;
+    }
+}
+
+// We only poll once lapin hit WouldBlock, clear the readiness tokio may have cached before that
+// so that the next poll waits for a new event
+
+async fn poll_read(
+    socket: Arc<AsyncFd<OwnedTransportSocket>>,
+    socket_state: SocketStateHandle,
+    unregistered: Receiver<()>,
+) {
+    let readable = async {
+        socket.readable().await.unwrap().clear_ready();
+        true
+    };
+    if future::or(readable, unregistered_event(unregistered)).await {
+        socket_state.send(SocketEvent::Readable);
+    }
+}
+
+async fn poll_write(
+    socket: Arc<AsyncFd<OwnedTransportSocket>>,
+    socket_state: SocketStateHandle,
+    unregistered: Receiver<()>,
+) {
+    let writable = async {
+        socket.writable().await.unwrap().clear_ready();
+        true
+    };
+    if future::or(writable, unregistered_event(unregistered)).await {
+        socket_state.send(SocketEvent::Writable);
+    }
+}
+
+async fn unregistered_event(unregistered: Receiver<()>) -> bool {
+    let _ = unregistered.recv_async().await;
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LapinTokioExt;
+    use futures_lite::StreamExt;
+    use lapin::{Connection, ConnectionEvent, ConnectionProperties, RecoveryConfig};
+    use lapin_mock::MockServer;
+    use std::{
+        io,
+        net::{Shutdown, TcpListener, TcpStream},
+        thread,
+        time::Instant,
+    };
+
+    /// Forward the connections to the mock server, returning a way to cut them
+    fn relay(server: std::net::SocketAddr) -> (String, Arc<Mutex<Vec<TcpStream>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("amqp://{}/%2f", listener.local_addr().unwrap());
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let accepted = clients.clone();
+        thread::spawn(move || {
+            for client in listener.incoming().flatten() {
+                let upstream = TcpStream::connect(server).unwrap();
+                accepted.lock().push(client.try_clone().unwrap());
+                forward(client.try_clone().unwrap(), upstream.try_clone().unwrap());
+                forward(upstream, client);
+            }
+        });
+        (uri, clients)
+    }
+
+    fn forward(mut from: TcpStream, mut to: TcpStream) {
+        thread::spawn(move || {
+            let _ = io::copy(&mut from, &mut to);
+            let _ = to.shutdown(Shutdown::Both);
+        });
+    }
+
+    #[test]
+    fn recovery_unregisters_the_lost_socket() {
+        let server = MockServer::start().expect("mock server");
+        let (uri, clients) = relay(server.address());
+        let rt = Arc::new(Runtime::new().expect("runtime"));
+        let reactor = TokioReactorBuilder::new(rt.clone());
+        let slots = reactor.inner.clone();
+
+        rt.clone().block_on(async move {
+            let conn = Connection::connect(
+                &uri,
+                ConnectionProperties::default()
+                    .with_tokio_executor(rt)
+                    .with_reactor(reactor)
+                    .with_connection_recovery(RecoveryConfig {
+                        initial_delay: Duration::from_millis(50),
+                        max_delay: Duration::from_millis(200),
+                        max_attempts: Some(10),
+                    }),
+            )
+            .await
+            .expect("connection");
+            let mut events = conn.events();
+            assert_eq!(slots.lock().slots.len(), 1);
+
+            for _ in 0..3 {
+                for client in clients.lock().drain(..) {
+                    let _ = client.shutdown(Shutdown::Both);
+                }
+                while !matches!(events.next().await, Some(ConnectionEvent::Recovered)) {}
+                assert_eq!(slots.lock().slots.len(), 1);
+            }
+
+            conn.close(200, "OK").await.expect("close");
+            let started = Instant::now();
+            while !slots.lock().slots.is_empty() {
+                assert!(
+                    started.elapsed() < Duration::from_secs(5),
+                    "socket left registered"
+                );
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+    }
+}
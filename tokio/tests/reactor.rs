@@ -0,0 +1,53 @@
+#![cfg(unix)]
+
+use lapin::{
+    options::*, types::FieldTable, BasicProperties, Connection, ConnectionProperties, IoLoopMode,
+};
+use lapin_mock::MockServer;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use tokio_amqp::*;
+
+#[test]
+fn tokio_reactor() {
+    let server = MockServer::start().expect("mock server");
+    let rt = Arc::new(Runtime::new().expect("runtime"));
+
+    rt.block_on(async {
+        // Everything, io loop included, runs on the tokio runtime
+        let conn = Connection::connect(
+            &server.uri(),
+            ConnectionProperties::default()
+                .with_tokio(rt.clone())
+                .with_io_loop_mode(IoLoopMode::Async),
+        )
+        .await
+        .expect("connection");
+        let channel = conn.create_channel().await.expect("create_channel");
+        channel
+            .queue_declare(
+                "tokio-reactor",
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .expect("queue_declare");
+        channel
+            .basic_publish(
+                "",
+                "tokio-reactor",
+                BasicPublishOptions::default(),
+                b"Hello world!".to_vec(),
+                BasicProperties::default(),
+            )
+            .await
+            .expect("basic_publish");
+        let message = channel
+            .basic_get("tokio-reactor", BasicGetOptions { no_ack: true })
+            .await
+            .expect("basic_get")
+            .expect("message");
+        assert_eq!(message.delivery.data, b"Hello world!");
+        conn.close(200, "OK").await.expect("close");
+    });
+}